@@ -0,0 +1,208 @@
+// Command line interface for running the pipeline over local files, outside of the langserver.
+//
+extern crate algorithm;
+extern crate algorithmia;
+extern crate serde_json;
+
+use algorithmia::algo::{AlgoInput, AlgoOutput, EntryPoint};
+use std::error::Error as StdError;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, Write};
+use std::path::*;
+use std::process;
+use std::sync::Arc;
+
+const USAGE: &'static str = "usage:
+    videotransform transform --input <file> --output <file> --algorithm <uri> [options]
+    videotransform extract --input <file> --output <file> --algorithm <uri> [options]
+    videotransform overlay --input <file> --output <file> --algorithm <uri> [--overlay <json>] [options]
+    videotransform audio_transform --input <file> --output <file> --algorithm <uri> --advanced-input <json> [--audio-window <seconds>]
+    videotransform audio_extract --input <file> --output <file> --algorithm <uri> --advanced-input <json> [--audio-window <seconds>]
+    videotransform probe <file>
+    videotransform split <file> <frames directory> [--fps <fps>] [--image-compression <0-100>]
+
+options:
+    --advanced-input <json>      advanced input template, see the README for keywords
+    --fps <fps>                  frame sampling rate, defaults to the input video's fps
+    --image-compression <0-100>  use jpeg frames with this compression
+    --video-compression <0-100>  output video compression, scaled onto the codec's crf range
+    --output-encoding <json>     codec & encoder settings, eg: {\"codec\": \"h265\", \"crf\": 24}
+    --audio <mode|json>          keep, drop, or a json object like {\"mode\": \"replace\", \"uri\": \"dub.m4a\"}
+    --overlay <json>             where boxes & labels are in the results, eg: {\"boxes\": \"/objects\", \"label\": \"/class\"}
+    --events <json>              turn extract results into time segments, eg: [{\"label\": \"nudity\", \"field\": \"/nude\", \"above\": 0.8}]
+    --output-format <name|json>  extract output as json, jsonl, csv, vtt or srt, eg: {\"format\": \"vtt\", \"field\": \"/caption\"}
+    --sampling <strategy|json>   uniform or scene, or a json object like {\"strategy\": \"scene\", \"threshold\": 0.4}
+    --dedup <mode|json>          only process one of each group of identical frames, exact, perceptual or {\"mode\": \"perceptual\", \"tolerance\": 6}
+    --cache <uri>                keep results in this directory & reuse them across jobs, eg: file:///var/cache/videotransform
+    --max-requests-per-second <n>  start at most this many algorithm calls a second
+    --start-time <seconds>       only process the video from here
+    --end-time <seconds>         only process the video up to here
+    --segments <json>            only process these parts, eg: [{\"start\": 10, \"end\": 20}]
+    --chunk-duration <seconds>   process the video in chunks of this length
+    --audio-window <seconds>     length of the audio windows in the audio modes, defaults to 10
+
+local paths are read & written directly, data:// and other uris go through the data api.";
+
+const BAR_WIDTH: usize = 40;
+
+//every --flag in the usage, as the request field name it maps onto.
+const FLAGS: &'static [&'static str] = &["input", "output", "algorithm", "advanced_input", "fps", "image_compression", "video_compression",
+    "output_encoding", "audio", "overlay", "events", "output_format", "sampling", "dedup", "cache", "max_requests_per_second",
+    "start_time", "end_time", "segments", "chunk_duration", "audio_window"];
+
+struct Arguments {
+    positional: Vec<String>,
+    flags: Map<String, Value>,
+}
+
+fn main() {
+    let mut args: VecDeque<String> = env::args().skip(1).collect();
+    let command = match args.pop_front() {
+        Some(command) => command,
+        None => usage_error("missing command")
+    };
+    let arguments = match parse_arguments(args) {
+        Ok(arguments) => arguments,
+        Err(message) => usage_error(&message)
+    };
+    let result = match command.as_str() {
+        "transform" | "extract" | "overlay" | "audio_transform" | "audio_extract" => run(&command, arguments),
+        "probe" => probe(arguments),
+        "split" => split(arguments),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => usage_error(&format!("unknown command '{}'", other))
+    };
+    if let Err(err) = result {
+        let _ = writeln!(io::stderr(), "\nerror: {}", error_cause_chain(&*err));
+        process::exit(1);
+    }
+}
+
+fn run(mode: &str, arguments: Arguments) -> Result<(), Box<StdError>> {
+    let mut flags = arguments.flags;
+    for required in &["input_file", "output_file", "algorithm"] {
+        if !flags.contains_key(*required) {
+            usage_error(&format!("--{} is required", required.replace("_file", "").replace("_", "-")));
+        }
+    }
+    for field in &["input_file", "output_file"] {
+        let uri = flags.get(*field).and_then(|value| value.as_str()).map(to_uri).unwrap();
+        flags.insert(field.to_string(), Value::String(uri));
+    }
+    //a replacement soundtrack can be a local path too.
+    if let Some(&mut Value::Object(ref mut audio)) = flags.get_mut("audio") {
+        let uri = audio.get("uri").and_then(|value| value.as_str()).map(to_uri);
+        if let Some(uri) = uri {
+            audio.insert("uri".to_string(), Value::String(uri));
+        }
+    }
+    flags.insert("mode".to_string(), Value::String(mode.to_string()));
+
+    let progress: Arc<algorithm::Progress> = Arc::new(draw_progress);
+    let algo = algorithm::Algo::default().with_progress(progress);
+    let output = algo.apply(AlgoInput::Json(Cow::Owned(Value::Object(flags))))?;
+    let _ = writeln!(io::stderr(), "");
+    match output {
+        AlgoOutput::Json(json) => println!("{}", json["output_file"].as_str().unwrap_or("")),
+        AlgoOutput::Text(text) => println!("{}", text),
+        AlgoOutput::Binary(_) => {}
+    }
+    Ok(())
+}
+
+fn probe(arguments: Arguments) -> Result<(), Box<StdError>> {
+    let video = arguments.positional.get(0).cloned().unwrap_or_else(|| usage_error("probe needs a video file"));
+    let info = algorithm::tools::probe(Path::new(&video))?;
+    println!("{}", serde_json::to_string_pretty(&info)?);
+    Ok(())
+}
+
+fn split(arguments: Arguments) -> Result<(), Box<StdError>> {
+    if arguments.positional.len() < 2 {
+        usage_error("split needs a video file and a frames directory");
+    }
+    let fps = arguments.flags.get("fps").and_then(|value| value.as_f64());
+    let image_compression = arguments.flags.get("image_compression").and_then(|value| value.as_u64());
+    let frames = algorithm::tools::split(Path::new(&arguments.positional[0]), &absolute(&arguments.positional[1]), fps, image_compression)?;
+    println!("{} frames written to {}", frames, arguments.positional[1]);
+    Ok(())
+}
+
+//flags map onto the request's fields, --advanced-input is parsed as json & numeric flags as numbers.
+fn parse_arguments(mut args: VecDeque<String>) -> Result<Arguments, String> {
+    let mut positional: Vec<String> = Vec::new();
+    let mut flags: Map<String, Value> = Map::new();
+    while let Some(arg) = args.pop_front() {
+        if arg.starts_with("--") {
+            let name = arg.trim_left_matches("--").replace("-", "_");
+            if !FLAGS.contains(&name.as_str()) {
+                return Err(format!("unknown option {}", arg))
+            }
+            let value = args.pop_front().ok_or(format!("{} needs a value", arg))?;
+            let (field, parsed) = match name.as_str() {
+                "input" | "output" => (format!("{}_file", name), Value::String(value)),
+                "advanced_input" | "segments" | "output_encoding" | "overlay" | "events" => {
+                    let json: Value = serde_json::from_str(&value).map_err(|err| format!("{} is not valid json: {}", arg, err))?;
+                    (name, json)
+                }
+                "sampling" | "dedup" | "audio" | "output_format" => {
+                    let json: Value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+                    (name, json)
+                }
+                "fps" | "image_compression" | "video_compression" | "start_time" | "end_time" | "chunk_duration" | "audio_window" | "max_requests_per_second" => {
+                    let number: Value = serde_json::from_str(&value).map_err(|_| format!("{} must be a number", arg))?;
+                    if !number.is_number() { return Err(format!("{} must be a number", arg)) }
+                    (name, number)
+                }
+                _ => (name, Value::String(value))
+            };
+            flags.insert(field, parsed);
+        } else {
+            positional.push(arg);
+        }
+    }
+    Ok(Arguments { positional: positional, flags: flags })
+}
+
+//local paths become file:// uris, anything that already has a scheme is passed through.
+fn to_uri(path: &str) -> String {
+    if path.contains("://") {
+        path.to_string()
+    } else {
+        format!("file://{}", absolute(path).display())
+    }
+}
+
+fn absolute(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    if path.is_absolute() { path } else { env::current_dir().unwrap().join(path) }
+}
+
+fn draw_progress(finished: usize, total: usize) {
+    let filled = if total == 0 { BAR_WIDTH } else { finished * BAR_WIDTH / total };
+    let bar: String = (0..BAR_WIDTH).map(|i| if i < filled { '#' } else { '-' }).collect();
+    let mut stderr = io::stderr();
+    let _ = write!(stderr, "\r[{}] {}/{} batches", bar, finished, total);
+    let _ = stderr.flush();
+}
+
+fn usage_error(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn error_cause_chain(err: &StdError) -> String {
+    let mut causes = vec![err.to_string()];
+    let mut e = err;
+    while let Some(cause) = e.cause() {
+        causes.push(cause.to_string());
+        e = cause;
+    }
+    causes.join("\ncaused by: ")
+}
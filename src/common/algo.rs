@@ -5,43 +5,50 @@ use common::video_error::VideoError;
 use std::thread;
 use common::file_mgmt::{from_regex, create_directory};
 use common::threading::*;
-use algorithmia::data::{FileData, HasDataPath};
-use std_semaphore::Semaphore;
-use std::sync::Arc;
+use common::storage::{Storage, Storages};
+use common::concurrency::{ConcurrencyController, is_throttled, throttle_backoff, milliseconds};
 use std::time::Duration;
-use std::io::copy;
 use std::path::*;
-use std::fs::File;
 use serde_json::Value;
+use uuid::Uuid;
 
 static SMART_VIDEO_DOWNLOADER: &'static str = "algo://media/SmartVideoDownloader/0.2.0";
 static MAX_ATTEMPTS_DATA: usize = 4;
 static MAX_ATTEMPTS_ALGO: usize = 4;
+static MAX_THROTTLED_ATTEMPTS: usize = 20;
 
 
-//fail fast if the exception contains '429'
+//throttled calls lower the concurrency limit & are retried after a jittered backoff once there's room under it, they don't count as failed attempts.
 pub fn try_algorithm(client: &Algorithmia, algorithm: &str, input: &Value,
-                     error_poll: Terminator, semaphore: Arc<Semaphore>) -> Result<AlgoResponse, VideoError> {
+                     error_poll: Terminator, controller: &ConcurrencyController) -> Result<AlgoResponse, VideoError> {
     let mut attempts = 0;
+    let mut throttled = 0;
     let final_result;
 
     loop {
         if error_poll.check_signal().is_some() { return Err(format!("already received an error.").into()) }
-        semaphore.acquire();
+        let permit = controller.acquire();
         match client.algo(algorithm).pipe(input.clone()) {
             Ok(result) => {
-                semaphore.release();
+                permit.succeeded();
                 final_result = result;
                 break;
             },
-            Err(ref err) if attempts < MAX_ATTEMPTS_ALGO && !err.to_string().contains("algorithm hit max number of active calls per session") => {
-                semaphore.release();
+            Err(ref err) if throttled < MAX_THROTTLED_ATTEMPTS && is_throttled(&err.to_string()) => {
+                permit.throttled();
+                let backoff = throttle_backoff(throttled, Uuid::new_v4().as_bytes()[0] as f64 / 255f64);
+                println!("throttled, concurrency limit is now {}, retrying in {}ms.", controller.limit(), milliseconds(backoff));
+                thread::sleep(backoff);
+                throttled += 1;
+            },
+            Err(ref err) if attempts < MAX_ATTEMPTS_ALGO && !is_throttled(&err.to_string()) => {
+                permit.failed();
                 println!("failed.");
                 thread::sleep(Duration::from_millis((1000 * attempts) as u64));
                 attempts += 1;
             },
             Err(ref err) => {
-                semaphore.release();
+                permit.failed();
                 println!("failed hard.");
                 return Err(format!("algorithm {} failed: \n{}", &algorithm, err).into())
             }
@@ -52,14 +59,14 @@ pub fn try_algorithm(client: &Algorithmia, algorithm: &str, input: &Value,
 
 
 
-//gets any remote file, http/https or data connector
-pub fn get_file_parallel(url: &str, local_path: &Path, client: &Algorithmia,
+//gets any remote file from storage, retrying a few times before failing.
+pub fn get_file_parallel(url: &str, local_path: &Path, storage: &Storage,
                          error_poll: Terminator) -> Result<PathBuf, VideoError> {
     let mut attempts = 0;
     let output;
     loop {
         if error_poll.check_signal().is_some() { return Err(format!("already receieved an error.").into()) }
-        let result = get_file_from_algorithmia(url, local_path, client);
+        let result = storage.get(url, local_path);
         if result.is_ok() {
             output = result.unwrap();
             break;
@@ -76,14 +83,13 @@ pub fn get_file_parallel(url: &str, local_path: &Path, client: &Algorithmia,
 }
 
 
-pub fn upload_file_parallel(url_dir: &str, local_file: &Path, client: &Algorithmia,
+pub fn upload_file_parallel(url_dir: &str, local_file: &Path, storage: &Storage,
                             error_poll: Terminator) -> Result<String, VideoError> {
     if local_file.exists() {
         let mut attempts = 0;
         loop {
             if error_poll.check_signal().is_some() { return Err(format!("already receieved an error.").into()) }
-            let file: File = File::open(local_file).map_err(|err| { format!("failed to open file: {}\n{}", local_file.display(), err) })?;
-            let response: Result<(), VideoError> = client.file(url_dir).put(file).map_err(|err| { format!("upload failure for:{}\n{}", url_dir, err.description()).into() });
+            let response: Result<(), VideoError> = storage.put(url_dir, local_file);
             if response.is_ok() {
                 let _ = response.unwrap();
                 break;
@@ -101,7 +107,8 @@ pub fn upload_file_parallel(url_dir: &str, local_file: &Path, client: &Algorithm
     }
 }
 
-pub fn get_file(url: &str, local_path: &Path, remote_scratch: &str, client: &Algorithmia) -> Result<PathBuf, VideoError> {
+//gets any remote file, http/https urls go through the smart video downloader, everything else is fetched from the storage its scheme points to.
+pub fn get_file(url: &str, local_path: &Path, remote_scratch: &str, storages: &Storages, client: &Algorithmia) -> Result<PathBuf, VideoError> {
     let tmp_url = url.clone();
     let remote_file = format!("{}/temp.mp4", remote_scratch);
     let prefix: &str = tmp_url.split("://").next().unwrap().clone();
@@ -109,9 +116,9 @@ pub fn get_file(url: &str, local_path: &Path, remote_scratch: &str, client: &Alg
     let output;
     loop {
         let result = if prefix == "http" || prefix == "https" {
-            get_file_from_html(url, local_path, &remote_file, client)
+            get_file_from_html(url, local_path, &remote_file, storages, client)
         } else {
-            get_file_from_algorithmia(url, local_path, client)
+            storages.resolve(url).and_then(|storage| storage.get(url, local_path))
         };
         if result.is_ok() {
             output = result.unwrap();
@@ -128,12 +135,11 @@ pub fn get_file(url: &str, local_path: &Path, remote_scratch: &str, client: &Alg
     Ok(output)
 }
 
-pub fn upload_file(url_dir: &str, local_file: &Path, client: &Algorithmia) -> Result<String, VideoError> {
+pub fn upload_file(url_dir: &str, local_file: &Path, storage: &Storage) -> Result<String, VideoError> {
     if local_file.exists() {
         let mut attempts = 0;
         loop {
-            let file: File = File::open(local_file).map_err(|err| { format!("failed to open file: {}\n{}", local_file.display(), err) })?;
-            let response: Result<(), VideoError> = client.file(url_dir).put(file).map_err(|err| { format!("upload failure for:{}\n{}", url_dir, err.description()).into() });
+            let response: Result<(), VideoError> = storage.put(url_dir, local_file);
             if response.is_ok() {
                 let _ = response.unwrap();
                 break;
@@ -151,7 +157,7 @@ pub fn upload_file(url_dir: &str, local_file: &Path, client: &Algorithmia) -> Re
     }
 }
 
-fn get_file_from_html(url: &str, local_path: &Path, remote_file: &str, client: &Algorithmia) -> Result<PathBuf, VideoError> {
+fn get_file_from_html(url: &str, local_path: &Path, remote_file: &str, storages: &Storages, client: &Algorithmia) -> Result<PathBuf, VideoError> {
     let local_dir = local_path.parent().unwrap();
     create_directory(local_dir);
     let input = json!({
@@ -159,34 +165,15 @@ fn get_file_from_html(url: &str, local_path: &Path, remote_file: &str, client: &
     "output": remote_file
     });
     let _response = client.algo(SMART_VIDEO_DOWNLOADER).pipe(input).map_err(|err| format!("smart video downloader failed: {}\n{}", url, err))?;
-    get_file_from_algorithmia(remote_file, local_path, client)
+    storages.resolve(remote_file)?.get(remote_file, local_path)
 
 }
 
-pub fn get_file_from_algorithmia(url: &str, local_path: &Path, client: &Algorithmia) -> Result<PathBuf, VideoError> {
-    let local_dir = local_path.parent().unwrap();
-    create_directory(local_dir);
-    let file = client.file(&url);
-    match file.exists() {
-        Ok(true) => {
-            let mut remote_file: FileData = file.get().map_err(|err| format!("couldn't download file from url: {} \n{}", &url, err))?;
-            let mut local_file = File::create(local_path).map_err(|err| format!("couldn't create local file: {} \n{}", local_path.to_str().unwrap(), err))?;
-            thread::sleep(Duration::from_secs(2));
-            copy(&mut remote_file, &mut local_file).map_err(|err| format!("couldn't copy remote file to local: {} \n{}", local_path.to_str().unwrap(), err))?;
-            Ok(PathBuf::from(local_path))
-        }
-        Ok(false) => {Err(format!("file not ready").into())}
-        Err(error) => {
-            Err(format!("recieved an error trying to download {}\n{}", url, error).into())
-        }
-    }
-}
-
 //exits early if the or if the output path is invalid.
-pub fn early_exit(client: &Algorithmia, output_path: &str) -> Result<(), VideoError> {
+pub fn early_exit(storage: &Storage, output_path: &str) -> Result<(), VideoError> {
     //try to upload a 0 size file to the output path, then delete it. if both succeed then the path is valid.
-    let r: Result<_, VideoError> = client.file(output_path).put("").map_err(|err| format!("early exit: \n output path {} invalid, or invalid permissions, unable to upload.\n{}", output_path, err).into());
-    let j: Result<_, VideoError> = client.file(output_path).delete().map_err(|err| format!("early exit: \n output path {} invalid, or invalid permissions, unable to delete.\n{}", output_path, err).into());
+    let r: Result<_, VideoError> = storage.put_bytes(output_path, &[]).map_err(|err| format!("early exit: \n output path {} invalid, or invalid permissions, unable to upload.\n{}", output_path, err).into());
+    let j: Result<_, VideoError> = storage.delete(output_path).map_err(|err| format!("early exit: \n output path {} invalid, or invalid permissions, unable to delete.\n{}", output_path, err).into());
     r?;j?;
     Ok(())
 }
@@ -202,26 +189,38 @@ pub fn batch_file_path(batch: &Vec<usize>, regex: &str, directory: &str) -> Resu
     }).collect::<Vec<String>>())
 }
 
+//the frame before each one in batch, None for the first frame.
+pub fn previous_file_path(batch: &Vec<usize>, regex: &str, directory: &str) -> Result<Vec<Option<PathBuf>>, VideoError>
+{
+    batch.iter().map(|iter| {
+        if *iter > 1 {
+            from_regex(regex, iter - 1).map(|filename| Some(PathBuf::from(format!("{}/{}", directory, filename))))
+        } else {
+            Ok(None)
+        }
+    }).collect()
+}
+
 //retry 3 times, if it fails 3 times we exit hard.
 pub fn batch_upload_file(local_files: &Vec<PathBuf>, remote_files: &Vec<String>,
-                         client: &Algorithmia,
+                         storage: &Storage,
                          error_poll: Terminator) -> Result<(), VideoError>
 {
     if error_poll.check_signal().is_some() { return Err(format!("already receieved an error.").into()) }
     for (local_file, remote_file) in local_files.iter().zip(remote_files.iter()) {
-        upload_file_parallel(&remote_file, &local_file, client, error_poll.clone())?;
+        upload_file_parallel(&remote_file, &local_file, storage, error_poll.clone())?;
     }
     Ok(())
 }
 
 pub fn batch_get_file(local_file_save_locations: &Vec<PathBuf>, remote_file_get_locations: &Vec<String>,
-                      client: &Algorithmia, error_poll: Terminator) -> Result<Vec<PathBuf>, VideoError>
+                      storage: &Storage, error_poll: Terminator) -> Result<Vec<PathBuf>, VideoError>
 {
     if error_poll.check_signal().is_some() { return Err(format!("already receieved an error.").into()) }
         else {
             let mut output: Vec<PathBuf> = Vec::new();
             for (local_file, remote_file) in local_file_save_locations.iter().zip(remote_file_get_locations.iter()) {
-                output.push(get_file_parallel(&remote_file, &local_file, client, error_poll.clone())?);
+                output.push(get_file_parallel(&remote_file, &local_file, storage, error_poll.clone())?);
             }
             Ok(output)
         }
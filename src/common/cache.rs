@@ -0,0 +1,189 @@
+use common::storage::{self, Storage};
+use common::file_mgmt::{from_regex, file_hash, create_directory};
+use common::structs::advanced_input::AdvancedInput;
+use common::structs::alter::Alter;
+use common::structs::extract::Extract;
+use rayon::prelude::*;
+use serde_json::{self, Value};
+use sha1::Sha1;
+use std::collections::BTreeMap;
+use std::fs::{remove_file, File};
+use std::path::*;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//results kept across jobs, keyed by a frame's content, the algorithm & its input, so re-running an algorithm on overlapping footage skips the frames it has already seen.
+pub struct ResultCache {
+    storage: Arc<Storage>,
+    directory: String,
+    //where cached results are downloaded to be read, its own directory so they never end up among the frames.
+    local_directory: PathBuf,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+//the part of the cache one algorithm & input can use.
+pub struct CacheScope {
+    cache: Arc<ResultCache>,
+    namespace: String,
+    //content hash of each input frame, they're hashed when looked up & reused when stored.
+    hashes: Mutex<BTreeMap<usize, String>>,
+}
+
+impl ResultCache {
+    pub fn new(storage: Arc<Storage>, directory: &str, local_directory: &Path) -> ResultCache {
+        ResultCache {
+            storage: storage,
+            directory: directory.trim_right_matches('/').to_string(),
+            local_directory: PathBuf::from(local_directory),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn hits(&self) -> usize {self.hits.load(Ordering::Relaxed)}
+    pub fn misses(&self) -> usize {self.misses.load(Ordering::Relaxed)}
+}
+
+impl CacheScope {
+    //None when the algorithm's results can't be reused, because it isn't pinned to a version or its input refers to more than the frame itself.
+    pub fn new(cache: Arc<ResultCache>, algorithm: &str, input: &AdvancedInput, kind: &str) -> Option<CacheScope> {
+        if !is_pinned(algorithm) {
+            println!("not caching results, {} isn't pinned to a version.", algorithm);
+            return None
+        }
+        if input.uses_frame_context() {
+            println!("not caching results, advanced_input uses frame context variables.");
+            return None
+        }
+        let mut hasher = Sha1::new();
+        //serde_json keeps object keys sorted, so the same input always serializes the same way.
+        hasher.update(json!({"kind": kind, "algorithm": storage::strip_scheme(algorithm), "input": input.source().clone()}).to_string().as_bytes());
+        Some(CacheScope { cache: cache, namespace: hasher.digest().to_string(), hashes: Mutex::new(BTreeMap::new()) })
+    }
+
+    //copies cached outputs into place, returns the frames that were found.
+    pub fn restore_frames(&self, frames: &Vec<usize>, data: &Alter) -> Vec<usize> {
+        let extension = extension(data.output_regex());
+        let mut found: Vec<Option<usize>> = Vec::new();
+        frames.par_iter().map(|frame| {
+            let output = match from_regex(data.output_regex(), *frame) {
+                Ok(name) => data.local_output().join(name),
+                Err(_) => return None
+            };
+            match self.entry(*frame, data.local_input(), data.input_regex(), extension) {
+                Some(entry) if self.cache.storage.get(&entry, &output).is_ok() => Some(*frame),
+                _ => None
+            }
+        }).weight_max().collect_into(&mut found);
+        let found: Vec<usize> = found.into_iter().filter_map(|frame| frame).collect();
+        self.count(frames.len(), found.len());
+        found
+    }
+
+    //keeps the outputs of processed frames, failing to is logged but doesn't fail the job.
+    pub fn store_frames(&self, frames: &Vec<usize>, data: &Alter) -> () {
+        let extension = extension(data.output_regex());
+        for frame in frames {
+            let output = match from_regex(data.output_regex(), *frame) {
+                Ok(name) => data.local_output().join(name),
+                Err(_) => continue
+            };
+            if let Some(entry) = self.entry(*frame, data.local_input(), data.input_regex(), extension) {
+                if let Err(err) = self.cache.storage.put(&entry, &output) {
+                    println!("failed to cache frame {}: {}", frame, err);
+                }
+            }
+        }
+    }
+
+    //the cached results of frames, for the frames that were found.
+    pub fn restore_results(&self, frames: &Vec<usize>, data: &Extract) -> Vec<(usize, Value)> {
+        create_directory(&self.cache.local_directory);
+        let mut found: Vec<Option<(usize, Value)>> = Vec::new();
+        frames.par_iter().map(|frame| {
+            let entry = match self.entry(*frame, data.local_input(), data.input_regex(), "json") {
+                Some(entry) => entry,
+                None => return None
+            };
+            let local = self.cache.local_directory.join(format!("{}-{}.json", self.namespace, frame));
+            let result = self.cache.storage.get(&entry, &local).ok()
+                .and_then(|local| File::open(&local).ok())
+                .and_then(|file| serde_json::from_reader(file).ok());
+            let _ = remove_file(&local);
+            result.map(|value| (*frame, value))
+        }).weight_max().collect_into(&mut found);
+        let found: Vec<(usize, Value)> = found.into_iter().filter_map(|result| result).collect();
+        self.count(frames.len(), found.len());
+        found
+    }
+
+    pub fn store_results(&self, frames: &Vec<usize>, results: &Vec<Value>, data: &Extract) -> () {
+        for (frame, result) in frames.iter().zip(results.iter()) {
+            if let Some(entry) = self.entry(*frame, data.local_input(), data.input_regex(), "json") {
+                if let Err(err) = self.cache.storage.put_bytes(&entry, result.to_string().as_bytes()) {
+                    println!("failed to cache frame {}: {}", frame, err);
+                }
+            }
+        }
+    }
+
+    fn count(&self, looked_up: usize, found: usize) -> () {
+        self.cache.hits.fetch_add(found, Ordering::Relaxed);
+        self.cache.misses.fetch_add(looked_up - found, Ordering::Relaxed);
+        if found > 0 {
+            println!("found {} of {} frames in the result cache.", found, looked_up);
+        }
+    }
+
+    //where a frame's result is kept, None if the frame can't be read.
+    fn entry(&self, frame: usize, directory: &Path, regex: &str, extension: &str) -> Option<String> {
+        let known = self.hashes.lock().unwrap().get(&frame).cloned();
+        let hash = match known {
+            Some(hash) => hash,
+            None => {
+                let hash = match from_regex(regex, frame).and_then(|name| file_hash(&directory.join(name))) {
+                    Ok(hash) => hash,
+                    Err(_) => return None
+                };
+                self.hashes.lock().unwrap().insert(frame, hash.clone());
+                hash
+            }
+        };
+        Some(format!("{}/{}/{}.{}", self.cache.directory, self.namespace, hash, extension))
+    }
+}
+
+fn extension(regex: &str) -> &str {
+    regex.rsplit('.').next().unwrap_or("png")
+}
+
+//algo://owner/name/1.2.3 or a commit hash, results of unpinned or local algorithms can change between runs.
+fn is_pinned(algorithm: &str) -> bool {
+    if storage::scheme(algorithm).map(|scheme| scheme == "exec").unwrap_or(false) {
+        return false
+    }
+    let segments: Vec<&str> = storage::strip_scheme(algorithm).split('/').collect();
+    match segments.get(2) {
+        Some(version) if segments.len() == 3 => {
+            let parts: Vec<&str> = version.split('.').collect();
+            (parts.len() == 3 && parts.iter().all(|part| !part.is_empty() && part.chars().all(|c| c.is_digit(10))))
+                || (version.len() >= 7 && version.chars().all(|c| c.is_digit(16)))
+        }
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_pinned_algorithms_are_cached() {
+        assert!(is_pinned("algo://deeplearning/SalNet/0.2.0"));
+        assert!(is_pinned("deeplearning/SalNet/3fa4b2c"));
+        assert!(!is_pinned("algo://deeplearning/SalNet"));
+        assert!(!is_pinned("algo://deeplearning/SalNet/0.2.x"));
+        assert!(!is_pinned("exec://convert"));
+    }
+}
@@ -0,0 +1,290 @@
+use common::video_error::VideoError;
+use common::storage::Storage;
+use common::file_mgmt::{create_directory, from_regex};
+use common::threading::Lockstep;
+use serde_json::{self, Value};
+use sha1::Sha1;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::*;
+use std::sync::{Arc, Mutex};
+
+static MANIFEST: &'static str = "manifest.jsonl";
+static UPLOAD_EVERY: usize = 20;
+static KEY_LENGTH: usize = 16;
+
+struct Ledger {
+    completed: BTreeMap<usize, Value>,
+    unsynced_batches: usize,
+}
+
+//records which frames of a job have been processed so a retry of the same job only processes the rest.
+//the manifest is an append only json lines file kept next to the processed frames, when the frames are processed remotely both are mirrored
+//to the job's own directory next to the output, so a job can resume in a new session after the session's working directory is gone.
+pub struct Checkpoint {
+    job_id: String,
+    local_directory: PathBuf,
+    manifest_file: PathBuf,
+    remote: Option<(Arc<Storage>, String)>,
+    ledger: Lockstep<Ledger>,
+}
+
+impl Checkpoint {
+    //a job is identified by the content of its input video & the request fields that change the frames or how they're processed.
+    pub fn job_id(input_file: &Path, settings: &Value) -> Result<String, VideoError> {
+        let mut hasher = Sha1::new();
+        let mut file = File::open(input_file).map_err(|err| format!("failed to open {} to hash it\n{}", input_file.display(), err))?;
+        let mut buffer = [0u8; 65536];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 { break }
+            hasher.update(&buffer[..read]);
+        }
+        hasher.update(serde_json::to_string(settings)?.as_bytes());
+        let digest = hasher.digest().to_string();
+        Ok(digest[..KEY_LENGTH].to_string())
+    }
+
+    //loads the manifest for this job from the local root or the job's directory under the remote checkpoint directory, other jobs' local checkpoints are removed to keep disk use bounded.
+    pub fn create(job_id: &str, local_root: &Path, remote: Option<(Arc<Storage>, &str)>) -> Result<Checkpoint, VideoError> {
+        let local_directory = local_root.join(job_id);
+        remove_other_jobs(local_root, job_id);
+        create_directory(&local_directory);
+        let manifest_file = local_directory.join(MANIFEST);
+        let remote = remote.map(|(storage, directory)| (storage, format!("{}/{}", directory, job_id)));
+        if !manifest_file.exists() {
+            if let Some((ref storage, ref remote_directory)) = remote {
+                let remote_manifest = format!("{}/{}", remote_directory, MANIFEST);
+                if storage.exists(&remote_manifest).unwrap_or(false) {
+                    println!("found a remote checkpoint for job {}", job_id);
+                    storage.get(&remote_manifest, &manifest_file)?;
+                }
+            }
+        }
+        let completed = read_manifest(&manifest_file)?;
+        if !completed.is_empty() {
+            println!("resuming job {}, {} frames already processed.", job_id, completed.len());
+        }
+        Ok(Checkpoint {
+            job_id: job_id.to_string(),
+            local_directory: local_directory,
+            manifest_file: manifest_file,
+            remote: remote,
+            ledger: Arc::new(Mutex::new(Ledger { completed: completed, unsynced_batches: 0 })),
+        })
+    }
+
+    pub fn id(&self) -> &str {&self.job_id}
+    pub fn local_directory(&self) -> &Path {&self.local_directory}
+    //the job's remote directory, checkpoints for parts of the job are kept inside it so they're removed with it.
+    pub fn remote(&self) -> Option<(Arc<Storage>, &str)> {
+        self.remote.as_ref().map(|&(ref storage, ref directory)| (storage.clone(), directory.as_str()))
+    }
+
+    //processed frame names are tied to the job so outputs from an earlier attempt can be found again.
+    pub fn output_regex(&self, extension: &str) -> String {
+        format!("{}-%07d.{}", self.job_id, extension)
+    }
+
+    //the frames that still need processing, completed frames whose output file can't be found locally or restored from the job's remote directory are processed again.
+    pub fn pending_frames(&self, number_of_frames: usize, output_regex: Option<&str>) -> Result<Vec<usize>, VideoError> {
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(regex) = output_regex {
+            let completed: Vec<usize> = ledger.completed.keys().cloned().collect();
+            for frame in completed {
+                let name = from_regex(regex, frame)?;
+                let local = self.local_directory.join(&name);
+                if !local.exists() && !self.restore(&name, &local) {
+                    ledger.completed.remove(&frame);
+                }
+            }
+        }
+        Ok((1..number_of_frames + 1).filter(|frame| !ledger.completed.contains_key(frame)).collect())
+    }
+
+    fn restore(&self, name: &str, local: &Path) -> bool {
+        match self.remote {
+            Some((ref storage, ref remote_directory)) => storage.get(&format!("{}/{}", remote_directory, name), local).is_ok(),
+            None => false
+        }
+    }
+
+    //marks a batch of processed frames, copying their output files to the job's remote directory first so they outlive the session.
+    //a frame that fails to upload isn't recorded, it's processed again if the job is retried.
+    pub fn record_outputs(&self, frames: &Vec<usize>, output_regex: &str) -> () {
+        let mut kept: Vec<usize> = Vec::new();
+        for frame in frames {
+            let uploaded = match (self.remote.as_ref(), from_regex(output_regex, *frame)) {
+                (Some(&(ref storage, ref remote_directory)), Ok(name)) => {
+                    storage.put(&format!("{}/{}", remote_directory, name), &self.local_directory.join(&name))
+                        .map_err(|err| println!("failed to upload frame {} of job {} to its checkpoint: {}", frame, self.job_id, err)).is_ok()
+                }
+                (None, _) => true,
+                (_, Err(_)) => false
+            };
+            if uploaded {
+                kept.push(*frame);
+            }
+        }
+        self.record(&kept, None);
+    }
+
+    //marks a batch as processed, results are only kept for extract jobs. failing to persist is logged but doesn't fail the job.
+    pub fn record(&self, frames: &Vec<usize>, results: Option<&Vec<Value>>) -> () {
+        let mut ledger = self.ledger.lock().unwrap();
+        let values: Vec<Value> = match results {
+            Some(results) => results.clone(),
+            None => frames.iter().map(|_| Value::Null).collect()
+        };
+        for (frame, value) in frames.iter().zip(values.iter()) {
+            ledger.completed.insert(*frame, value.clone());
+        }
+        let line = json!({"frames": frames, "results": values});
+        let appended = OpenOptions::new().create(true).append(true).open(&self.manifest_file)
+            .and_then(|mut file| file.write_all(format!("{}\n", line).as_bytes()));
+        if let Err(err) = appended {
+            println!("failed to update checkpoint for job {}: {}", self.job_id, err);
+        }
+        ledger.unsynced_batches += 1;
+        if ledger.unsynced_batches >= UPLOAD_EVERY {
+            ledger.unsynced_batches = 0;
+            self.upload();
+        }
+    }
+
+    //duplicate frames take the recorded result of the frame they duplicate, they're never recorded themselves.
+    pub fn share_results(&self, duplicates: &BTreeMap<usize, usize>) -> () {
+        let mut ledger = self.ledger.lock().unwrap();
+        for (frame, representative) in duplicates {
+            let result = ledger.completed.get(representative).cloned();
+            if let Some(result) = result {
+                ledger.completed.insert(*frame, result);
+            }
+        }
+    }
+
+    //every recorded result in frame order, used to rebuild extract output from earlier attempts.
+    pub fn results(&self) -> Vec<Value> {
+        self.ledger.lock().unwrap().completed.values().cloned().collect()
+    }
+
+    //mirrors the manifest remotely, called periodically and whenever the processing stage ends.
+    pub fn flush(&self) -> () {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.unsynced_batches = 0;
+        self.upload();
+    }
+
+    fn upload(&self) -> () {
+        if let Some((ref storage, ref remote_directory)) = self.remote {
+            if self.manifest_file.exists() {
+                if let Err(err) = storage.put(&format!("{}/{}", remote_directory, MANIFEST), &self.manifest_file) {
+                    println!("failed to upload checkpoint for job {}: {}", self.job_id, err);
+                }
+            }
+        }
+    }
+
+    //the job succeeded, so nothing needs to be resumed. the directory holding the job's remote directory goes too once nothing else is left in it.
+    pub fn finish(&self) -> () {
+        let _ = fs::remove_dir_all(&self.local_directory);
+        if let Some((ref storage, ref remote_directory)) = self.remote {
+            let _ = storage.delete_directory(remote_directory, true);
+            if let Some(parent) = remote_directory.rsplitn(2, '/').nth(1) {
+                let _ = storage.delete_directory(parent, false);
+            }
+        }
+    }
+}
+
+//a truncated last line means we were killed mid write, everything before it is still valid.
+fn read_manifest(manifest_file: &Path) -> Result<BTreeMap<usize, Value>, VideoError> {
+    let mut completed = BTreeMap::new();
+    if !manifest_file.exists() {
+        return Ok(completed)
+    }
+    let file = File::open(manifest_file)?;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let entry: Value = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(_) => break
+        };
+        let frames = entry["frames"].as_array().cloned().unwrap_or(Vec::new());
+        let results = entry["results"].as_array().cloned().unwrap_or(Vec::new());
+        for (frame, result) in frames.iter().zip(results.into_iter()) {
+            if let Some(frame) = frame.as_u64() {
+                completed.insert(frame as usize, result);
+            }
+        }
+    }
+    Ok(completed)
+}
+
+fn remove_other_jobs(local_root: &Path, job_id: &str) -> () {
+    if let Ok(entries) = fs::read_dir(local_root) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if entry.file_name().to_str() != Some(job_id) {
+                let path = entry.path();
+                let _ = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::storage::MemoryStorage;
+    use std::env;
+    use uuid::Uuid;
+
+    //a root of its own for every test run, so tests running in parallel or again don't see each other's checkpoints.
+    fn scratch_directory(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("{}-{}", name, Uuid::new_v4()))
+    }
+
+    #[test]
+    fn resumes_recorded_frames() {
+        let root = scratch_directory("checkpoint_test");
+        {
+            let checkpoint = Checkpoint::create("job", &root, None).unwrap();
+            checkpoint.record(&vec![1, 2], Some(&vec![json!({"tag": 1}), json!({"tag": 2})]));
+            checkpoint.record(&vec![4], Some(&vec![json!({"tag": 4})]));
+        }
+        let resumed = Checkpoint::create("job", &root, None).unwrap();
+        assert_eq!(resumed.pending_frames(5, None).unwrap(), vec![3, 5]);
+        assert_eq!(resumed.results(), vec![json!({"tag": 1}), json!({"tag": 2}), json!({"tag": 4})]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn missing_outputs_are_reprocessed() {
+        let root = scratch_directory("checkpoint_output_test");
+        let checkpoint = Checkpoint::create("job", &root, None).unwrap();
+        checkpoint.record(&vec![1, 2], None);
+        File::create(checkpoint.local_directory().join("job-0000001.png")).unwrap();
+        assert_eq!(checkpoint.pending_frames(3, Some("job-%07d.png")).unwrap(), vec![2, 3]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn frames_are_restored_from_the_remote_checkpoint() {
+        let root = scratch_directory("checkpoint_remote_test");
+        let memory = MemoryStorage::new();
+        let remote: Arc<Storage> = Arc::new(memory.clone());
+        {
+            let checkpoint = Checkpoint::create("job", &root.join("first"), Some((remote.clone(), "memory://output/.checkpoints"))).unwrap();
+            File::create(checkpoint.local_directory().join("job-0000001.png")).unwrap();
+            checkpoint.record_outputs(&vec![1], "job-%07d.png");
+            checkpoint.flush();
+        }
+        let resumed = Checkpoint::create("job", &root.join("second"), Some((remote.clone(), "memory://output/.checkpoints"))).unwrap();
+        assert_eq!(resumed.pending_frames(2, Some("job-%07d.png")).unwrap(), vec![2]);
+        assert!(resumed.local_directory().join("job-0000001.png").exists());
+        resumed.finish();
+        assert_eq!(memory.files("memory://output"), Vec::<String>::new());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
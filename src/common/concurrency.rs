@@ -0,0 +1,319 @@
+use std::sync::{Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+static THROTTLE_MESSAGE: &'static str = "algorithm hit max number of active calls per session";
+//a call is slow when it takes this many times longer than the running average.
+static LATENCY_TOLERANCE: f64 = 2.0;
+//how much each call moves the running average.
+static LATENCY_SMOOTHING: f64 = 0.1;
+//the limit is multiplied by these, throttles mean we're definitely over so they cut deeper than slow calls.
+static THROTTLE_BACKOFF: f64 = 0.5;
+static LATENCY_BACKOFF: f64 = 0.9;
+static MIN_LIMIT: usize = 1;
+//a throttled call waits this long before it's retried, doubled for every throttle in a row up to the cap.
+static THROTTLE_DELAY_MS: u64 = 250;
+static MAX_THROTTLE_DELAY_MS: u64 = 30000;
+
+//how a call that held a permit went, decides whether the limit grows or shrinks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    //finished, after this long.
+    Success(Duration),
+    //the algorithm had too many calls running already.
+    Throttled,
+    //failed for any other reason, it doesn't say anything about load so the limit stays where it is.
+    Failed,
+}
+
+//a snapshot of how the limit has moved over a job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub limit: usize,
+    pub lowest_limit: usize,
+    pub highest_limit: usize,
+    pub in_flight: usize,
+    pub calls: usize,
+    pub throttled: usize,
+    pub slow: usize,
+    pub decreases: usize,
+}
+
+//additive-increase/multiplicative-decrease of the number of calls allowed at once.
+//every fast success adds 1/limit, so the limit grows by one per limit's worth of calls. throttles & slow calls multiply it down.
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    limit: f64,
+    max: usize,
+    //running average of successful call latencies, in seconds.
+    average: Option<f64>,
+    //calls left before the limit can be lowered again, it's only lowered once per limit's worth of calls,
+    //so a burst of throttles from calls that were all started at the old limit counts once.
+    cooldown: usize,
+    metrics: Metrics,
+}
+
+//refills at rate tokens a second, holding up to a second's worth. every call takes one.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+//decides when an algorithm call may start, every call to an algorithm or local process holds a Permit while it runs.
+pub struct ConcurrencyController {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+struct State {
+    aimd: Aimd,
+    bucket: Option<TokenBucket>,
+    in_flight: usize,
+}
+
+//held for the length of one call, the slot is given back exactly once when it's dropped.
+pub struct Permit<'a> {
+    controller: &'a ConcurrencyController,
+    started: Instant,
+    outcome: Outcome,
+}
+
+impl Aimd {
+    pub fn new(starting: usize, max: usize) -> Aimd {
+        let max = max.max(MIN_LIMIT);
+        let starting = starting.max(MIN_LIMIT).min(max);
+        Aimd {
+            limit: starting as f64,
+            max: max,
+            average: None,
+            cooldown: 0,
+            metrics: Metrics { limit: starting, lowest_limit: starting, highest_limit: starting, in_flight: 0,
+                calls: 0, throttled: 0, slow: 0, decreases: 0 },
+        }
+    }
+
+    pub fn limit(&self) -> usize {(self.limit.floor() as usize).max(MIN_LIMIT)}
+    pub fn max(&self) -> usize {self.max}
+    pub fn metrics(&self) -> &Metrics {&self.metrics}
+
+    pub fn update(&mut self, outcome: Outcome) -> () {
+        self.metrics.calls += 1;
+        self.cooldown = self.cooldown.saturating_sub(1);
+        match outcome {
+            Outcome::Throttled => {
+                self.metrics.throttled += 1;
+                self.decrease(THROTTLE_BACKOFF);
+            }
+            Outcome::Success(latency) => {
+                let latency = seconds(latency);
+                let slow = match self.average {
+                    Some(average) => latency > average * LATENCY_TOLERANCE,
+                    None => false
+                };
+                self.average = Some(match self.average {
+                    Some(average) => average + (latency - average) * LATENCY_SMOOTHING,
+                    None => latency
+                });
+                if slow {
+                    self.metrics.slow += 1;
+                    self.decrease(LATENCY_BACKOFF);
+                } else {
+                    self.limit = (self.limit + 1f64 / self.limit).min(self.max as f64);
+                }
+            }
+            Outcome::Failed => {}
+        }
+        let limit = self.limit();
+        self.metrics.limit = limit;
+        self.metrics.lowest_limit = self.metrics.lowest_limit.min(limit);
+        self.metrics.highest_limit = self.metrics.highest_limit.max(limit);
+    }
+
+    fn decrease(&mut self, factor: f64) -> () {
+        if self.cooldown == 0 {
+            self.cooldown = self.limit();
+            self.limit = (self.limit * factor).max(MIN_LIMIT as f64);
+            self.metrics.decreases += 1;
+        }
+    }
+}
+
+impl TokenBucket {
+    //starts full, so the first second's worth of calls go straight out.
+    pub fn new(rate: f64, now: Instant) -> TokenBucket {
+        let capacity = rate.max(1f64);
+        TokenBucket { rate: rate, capacity: capacity, tokens: capacity, updated: now }
+    }
+
+    //takes a token if there is one, otherwise returns how long until there is.
+    pub fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        if now > self.updated {
+            self.tokens = (self.tokens + seconds(now.duration_since(self.updated)) * self.rate).min(self.capacity);
+            self.updated = now;
+        }
+        if self.tokens >= 1f64 {
+            self.tokens -= 1f64;
+            Ok(())
+        } else {
+            Err(Duration::from_millis(((1f64 - self.tokens) / self.rate * 1000f64).ceil() as u64))
+        }
+    }
+}
+
+impl ConcurrencyController {
+    //requests_per_second caps how often calls start, on top of how many run at once.
+    pub fn new(starting: usize, max: usize, requests_per_second: Option<f64>) -> ConcurrencyController {
+        let state = State {
+            aimd: Aimd::new(starting, max),
+            bucket: requests_per_second.map(|rate| TokenBucket::new(rate, Instant::now())),
+            in_flight: 0,
+        };
+        ConcurrencyController { state: Mutex::new(state), released: Condvar::new() }
+    }
+
+    //blocks until there's both a free slot under the limit & a token, if there's a rate cap.
+    pub fn acquire<'a>(&'a self) -> Permit<'a> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.in_flight >= state.aimd.limit() {
+                state = self.released.wait(state).unwrap();
+                continue
+            }
+            let wait: Option<Duration> = match state.bucket {
+                Some(ref mut bucket) => bucket.take(Instant::now()).err(),
+                None => None
+            };
+            match wait {
+                Some(wait) => state = self.released.wait_timeout(state, wait).unwrap().0,
+                None => break
+            }
+        }
+        state.in_flight += 1;
+        Permit { controller: self, started: Instant::now(), outcome: Outcome::Failed }
+    }
+
+    fn release(&self, outcome: Outcome) -> () {
+        let mut state = self.state.lock().unwrap();
+        let before = state.aimd.limit();
+        state.in_flight -= 1;
+        state.aimd.update(outcome);
+        let after = state.aimd.limit();
+        if after < before {
+            println!("lowering the concurrency limit from {} to {}.", before, after);
+        }
+        self.released.notify_all();
+    }
+
+    pub fn limit(&self) -> usize {self.state.lock().unwrap().aimd.limit()}
+    pub fn max(&self) -> usize {self.state.lock().unwrap().aimd.max()}
+    pub fn metrics(&self) -> Metrics {
+        let state = self.state.lock().unwrap();
+        let mut metrics = state.aimd.metrics().clone();
+        metrics.in_flight = state.in_flight;
+        metrics
+    }
+}
+
+impl<'a> Permit<'a> {
+    pub fn succeeded(mut self) -> () {
+        self.outcome = Outcome::Success(self.started.elapsed());
+    }
+    pub fn throttled(mut self) -> () {
+        self.outcome = Outcome::Throttled;
+    }
+    pub fn failed(mut self) -> () {
+        self.outcome = Outcome::Failed;
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.controller.release(self.outcome);
+    }
+}
+
+//true if the error means the algorithm is already running as many calls as it will take.
+pub fn is_throttled(message: &str) -> bool {
+    message.contains(THROTTLE_MESSAGE)
+}
+
+//how long to wait before retrying after the nth throttle in a row, jitter between 0 & 1 spreads it over the upper half of the delay
+//so calls that were throttled together don't all come back at once.
+pub fn throttle_backoff(throttled: usize, jitter: f64) -> Duration {
+    let delay = THROTTLE_DELAY_MS.saturating_mul(1u64 << throttled.min(16)).min(MAX_THROTTLE_DELAY_MS) as f64;
+    Duration::from_millis((delay * (0.5 + 0.5 * jitter.max(0f64).min(1f64))).round() as u64)
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+pub fn milliseconds(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000000
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_grows_additively_and_shrinks_multiplicatively() {
+        let mut aimd = Aimd::new(4, 6);
+        for _ in 0..4 {
+            aimd.update(Outcome::Success(Duration::from_millis(100)));
+        }
+        assert_eq!(aimd.limit(), 4);
+        aimd.update(Outcome::Success(Duration::from_millis(100)));
+        assert_eq!(aimd.limit(), 5);
+        for _ in 0..100 {
+            aimd.update(Outcome::Success(Duration::from_millis(100)));
+        }
+        assert_eq!(aimd.limit(), 6);
+        //every call that was running at the old limit comes back throttled, that's one decrease.
+        for _ in 0..6 {
+            aimd.update(Outcome::Throttled);
+        }
+        assert_eq!(aimd.limit(), 3);
+        aimd.update(Outcome::Failed);
+        assert_eq!(aimd.limit(), 3);
+        aimd.update(Outcome::Success(Duration::from_millis(1000)));
+        assert_eq!(aimd.limit(), 2);
+        assert_eq!(aimd.metrics(), &Metrics { limit: 2, lowest_limit: 2, highest_limit: 6, in_flight: 0,
+            calls: 113, throttled: 6, slow: 1, decreases: 2 });
+    }
+
+    #[test]
+    fn token_bucket_caps_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2f64, start);
+        assert_eq!(bucket.take(start), Ok(()));
+        assert_eq!(bucket.take(start), Ok(()));
+        assert_eq!(bucket.take(start), Err(Duration::from_millis(500)));
+        assert_eq!(bucket.take(start + Duration::from_millis(250)), Err(Duration::from_millis(250)));
+        assert_eq!(bucket.take(start + Duration::from_millis(500)), Ok(()));
+    }
+
+    #[test]
+    fn throttles_back_off_exponentially() {
+        assert_eq!(throttle_backoff(0, 1f64), Duration::from_millis(250));
+        assert_eq!(throttle_backoff(0, 0f64), Duration::from_millis(125));
+        assert_eq!(throttle_backoff(3, 1f64), Duration::from_millis(2000));
+        assert_eq!(throttle_backoff(19, 0.5), Duration::from_millis(22500));
+    }
+
+    #[test]
+    fn permits_are_released_once() {
+        let controller = ConcurrencyController::new(2, 4, None);
+        {
+            let first = controller.acquire();
+            let _second = controller.acquire();
+            assert_eq!(controller.metrics().in_flight, 2);
+            first.throttled();
+            assert_eq!(controller.metrics().in_flight, 1);
+        }
+        let metrics = controller.metrics();
+        assert_eq!((metrics.in_flight, metrics.calls, metrics.throttled, metrics.limit), (0, 2, 1, 1));
+    }
+}
@@ -0,0 +1,179 @@
+use algorithmia::Algorithmia;
+use common::video_error::VideoError;
+use common::preprocess::ExecutionStyle;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::*;
+use toml;
+
+static PROD_API: &'static str = "https://api.algorithmia.com";
+static TEST_API: &'static str = "https://api.test.algorithmia.com";
+static SESSION_DIRECTORY: &'static str = "data://.session";
+static LOCAL_DATA_DIRECTORY: &'static str = "data://.my/ProcessVideo";
+static LOCAL_WORK_DIRECTORY: &'static str = "/tmp";
+
+static CONFIG_FILE_VAR: &'static str = "VIDEOTRANSFORM_CONFIG";
+static STYLE_VAR: &'static str = "VIDEOTRANSFORM_EXECUTION_STYLE";
+static API_URL_VAR: &'static str = "ALGORITHMIA_API";
+static API_KEY_VAR: &'static str = "ALGORITHMIA_API_KEY";
+static DATA_DIRECTORY_VAR: &'static str = "VIDEOTRANSFORM_DATA_DIRECTORY";
+static WORK_DIRECTORY_VAR: &'static str = "VIDEOTRANSFORM_WORK_DIRECTORY";
+
+//the optional toml config file, every field can be overridden by its environment variable.
+#[derive(Debug, Default, Deserialize)]
+struct FileConfig {
+    execution_style: Option<String>,
+    api_url: Option<String>,
+    api_key: Option<String>,
+    data_work_directory: Option<String>,
+    local_work_directory: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    style: ExecutionStyle,
+    api_url: Option<String>,
+    api_key: Option<String>,
+    data_work_directory: String,
+    local_work_directory: PathBuf,
+}
+
+impl Config {
+    //fills in the defaults for the execution style, then checks that everything it needs is there.
+    pub fn new(style: ExecutionStyle,
+               api_url: Option<String>,
+               api_key: Option<String>,
+               data_work_directory: Option<String>,
+               local_work_directory: Option<PathBuf>) -> Result<Config, VideoError> {
+        Config::validate(Vec::new(), style, api_url, api_key, data_work_directory, local_work_directory)
+    }
+
+    //problems are the ones found before these settings were, they're all reported together.
+    fn validate(mut problems: Vec<String>,
+                style: ExecutionStyle,
+                api_url: Option<String>,
+                api_key: Option<String>,
+                data_work_directory: Option<String>,
+                local_work_directory: Option<PathBuf>) -> Result<Config, VideoError> {
+        let api_url = match (&style, api_url) {
+            (_, Some(url)) => Some(url),
+            (&ExecutionStyle::Algo, None) => None,
+            (&ExecutionStyle::ProdLocal, None) => Some(PROD_API.to_string()),
+            (&ExecutionStyle::TestLocal, None) => Some(TEST_API.to_string()),
+        };
+        match (&style, &api_key) {
+            (&ExecutionStyle::Algo, _) | (_, &Some(_)) => {}
+            (_, &None) => problems.push(format!("missing api key, set {} or api_key in the config file.", API_KEY_VAR)),
+        }
+        let data_work_directory = data_work_directory.unwrap_or(match style {
+            ExecutionStyle::Algo => SESSION_DIRECTORY.to_string(),
+            _ => LOCAL_DATA_DIRECTORY.to_string()
+        });
+        if !data_work_directory.contains("://") {
+            problems.push(format!("data work directory '{}' must be a uri like data://.my/ProcessVideo, set {} or data_work_directory in the config file.",
+                                  data_work_directory, DATA_DIRECTORY_VAR));
+        }
+        let local_work_directory = local_work_directory.unwrap_or(PathBuf::from(LOCAL_WORK_DIRECTORY));
+        if !local_work_directory.is_absolute() {
+            problems.push(format!("local work directory '{}' must be an absolute path, set {} or local_work_directory in the config file.",
+                                  local_work_directory.display(), WORK_DIRECTORY_VAR));
+        }
+        if problems.is_empty() {
+            Ok(Config { style: style, api_url: api_url, api_key: api_key,
+                data_work_directory: data_work_directory, local_work_directory: local_work_directory })
+        } else {
+            Err(format!("invalid configuration:\n - {}", problems.join("\n - ")).into())
+        }
+    }
+
+    //reads the config file pointed to by VIDEOTRANSFORM_CONFIG if there is one, then the environment.
+    pub fn load() -> Result<Config, VideoError> {
+        let vars: HashMap<String, String> = env::vars().collect();
+        let file = match vars.get(CONFIG_FILE_VAR) {
+            Some(path) => read_file(Path::new(path))?,
+            None => FileConfig::default()
+        };
+        Config::from_sources(file, &vars)
+    }
+
+    fn from_sources(file: FileConfig, vars: &HashMap<String, String>) -> Result<Config, VideoError> {
+        let pick = |var: &str, from_file: Option<String>| -> Option<String> {
+            vars.get(var).cloned().filter(|value| !value.trim().is_empty()).or(from_file)
+        };
+        let mut problems: Vec<String> = Vec::new();
+        //an invalid style is reported with everything else, the rest is checked as if it were algo.
+        let style = match pick(STYLE_VAR, file.execution_style) {
+            Some(name) => ExecutionStyle::from_name(&name).unwrap_or_else(|err| {
+                problems.push(format!("{}, set {} or execution_style in the config file.", err, STYLE_VAR));
+                ExecutionStyle::Algo
+            }),
+            None => ExecutionStyle::Algo
+        };
+        Config::validate(problems,
+                         style,
+                         pick(API_URL_VAR, file.api_url),
+                         pick(API_KEY_VAR, file.api_key),
+                         pick(DATA_DIRECTORY_VAR, file.data_work_directory),
+                         pick(WORK_DIRECTORY_VAR, file.local_work_directory).map(PathBuf::from))
+    }
+
+    pub fn client(&self) -> Algorithmia {
+        match (&self.api_url, &self.api_key) {
+            (&Some(ref url), &Some(ref key)) => Algorithmia::client_with_url(url.as_str(), key.as_str()),
+            (&None, &Some(ref key)) => Algorithmia::client(key.as_str()),
+            _ => Algorithmia::default()
+        }
+    }
+
+    pub fn style(&self) -> &ExecutionStyle {&self.style}
+    //exec:// algorithms run on this machine, that's only allowed when it's our own machine rather than a hosted worker.
+    pub fn allows_local_processes(&self) -> bool {self.style != ExecutionStyle::Algo}
+    pub fn data_work_directory(&self) -> &str {&self.data_work_directory}
+    pub fn local_work_directory(&self) -> &Path {&self.local_work_directory}
+}
+
+fn read_file(path: &Path) -> Result<FileConfig, VideoError> {
+    let mut contents = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|err| format!("invalid configuration:\n - couldn't read config file {}\n{}", path.display(), err))?;
+    toml::from_str(&contents).map_err(|err| format!("invalid configuration:\n - couldn't parse config file {}\n{}", path.display(), err).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_styles_need_a_key() {
+        let vars: HashMap<String, String> = vec![(STYLE_VAR.to_string(), "prod_local".to_string()),
+                                                 (DATA_DIRECTORY_VAR.to_string(), "ProcessVideo".to_string())]
+            .into_iter().collect();
+        let err = Config::from_sources(FileConfig::default(), &vars).err().unwrap().to_string();
+        assert!(err.contains(API_KEY_VAR));
+        assert!(err.contains(DATA_DIRECTORY_VAR));
+    }
+
+    #[test]
+    fn invalid_style_is_reported_with_the_rest() {
+        let vars: HashMap<String, String> = vec![(STYLE_VAR.to_string(), "staging".to_string()),
+                                                 (DATA_DIRECTORY_VAR.to_string(), "ProcessVideo".to_string())]
+            .into_iter().collect();
+        let err = Config::from_sources(FileConfig::default(), &vars).err().unwrap().to_string();
+        assert!(err.contains("unknown execution style 'staging'"));
+        assert!(err.contains(DATA_DIRECTORY_VAR));
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let file = FileConfig { execution_style: Some("test_local".to_string()), api_key: Some("from_file".to_string()),
+            local_work_directory: Some("/scratch".to_string()), ..FileConfig::default() };
+        let vars: HashMap<String, String> = vec![(API_KEY_VAR.to_string(), "from_env".to_string())].into_iter().collect();
+        let config = Config::from_sources(file, &vars).unwrap();
+        assert_eq!(config.api_key, Some("from_env".to_string()));
+        assert_eq!(config.api_url, Some(TEST_API.to_string()));
+        assert_eq!(config.data_work_directory(), LOCAL_DATA_DIRECTORY);
+        assert_eq!(config.local_work_directory(), Path::new("/scratch"));
+    }
+}
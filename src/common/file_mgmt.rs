@@ -1,5 +1,7 @@
 use std::path::*;
-use std::fs::{ReadDir, read_dir, create_dir_all, remove_dir_all, metadata};
+use std::fs::{ReadDir, File, read_dir, create_dir_all, remove_dir_all, metadata};
+use std::io::Read;
+use sha1::Sha1;
 use regex::Regex;
 use common::video_error::VideoError;
 
@@ -38,6 +40,16 @@ pub fn from_regex(regex: &str, iter: usize) -> Result<String, VideoError> {
     Ok(regex.replace(&format!("%0{}d", num_digits), &format!("{:0width$}", iter, width = num_digits)))
 }
 
+//sha1 of a file's contents, frames with the same hash are the same frame.
+pub fn file_hash(file: &Path) -> Result<String, VideoError> {
+    let mut contents: Vec<u8> = Vec::new();
+    File::open(file).and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|err| format!("failed to read frame {} to hash it\n{}", file.display(), err))?;
+    let mut hasher = Sha1::new();
+    hasher.update(&contents);
+    Ok(hasher.digest().to_string())
+}
+
 
 
 
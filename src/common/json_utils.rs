@@ -1,121 +1,123 @@
-use std::collections::VecDeque;
 //use serde_json;
 use serde_json::Value;
 use common::video_error::VideoError;
 use either::{Either, Left, Right};
-use std::ops::{Index, IndexMut};
+use std::collections::BTreeMap;
 
 
-//depth first search of json blob, returns true if the keyword was found, false if it wasn't.
-pub fn search_json(json: &Value, path: &mut VecDeque<String>, keyword: &str) -> Result<(bool, Option<usize>), VideoError> {
-    if json.is_object() {
-        let tree = json.as_object().unwrap();
-        for (k, v) in tree {
-            path.push_back(k.to_string());
-            match v {
-                &Value::String(ref text) if text == keyword => {
-                    return Ok((true, None))
-                }
-                &Value::Object(_) => {
-                    let (found, iter) = search_json(&v, path, keyword)?;
-                    if found {
-                        return Ok((found, iter))
-                    }
-                }
-                &Value::Array(ref arr) => {
-                    for i in 0..arr.len() {
-                        let val = arr.index(i);
-                        match val {
-                            &Value::String(ref str) if str == keyword => {
-                                return Ok((true, Some(i)))
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {}
-            }
-            path.pop_back();
+//depth first search of json blob, returns the json pointer of every string that's exactly keyword, in document order. empty if there are none.
+pub fn search_json(json: &Value, keyword: &str) -> Result<Vec<String>, VideoError> {
+    match json {
+        &Value::Object(_) | &Value::Array(_) | &Value::String(_) => {
+            let mut paths: Vec<String> = Vec::new();
+            find_paths(json, &mut String::new(), keyword, &mut paths);
+            Ok(paths)
         }
-        Ok((false, None))
-    } else if json.is_array() {
-        let arr = json.as_array().unwrap();
-        for i in 0..arr.len() {
-            let mut val = arr.index(i);
-            match val {
-                &Value::String(ref str) if str == keyword => {
-                    return Ok((true, Some(i)))
-                }
-                _ => {}
+        _ => Err(format!("advanced input was neither a json object or an array.").into())
+    }
+}
+
+//path is the json pointer of json, every match is pushed onto paths.
+fn find_paths(json: &Value, path: &mut String, keyword: &str, paths: &mut Vec<String>) -> () {
+    match json {
+        &Value::String(ref text) if text == keyword => paths.push(path.clone()),
+        &Value::Object(ref tree) => {
+            for (key, value) in tree {
+                let length = path.len();
+                path.push('/');
+                path.push_str(&key.replace("~", "~0").replace("/", "~1"));
+                find_paths(value, path, keyword, paths);
+                path.truncate(length);
             }
         }
-        return Ok((false, None))
-    }
-                else if json.is_string() {
-                if json.as_str().unwrap() == keyword {
-                    return Ok((true, None))
-                }
-                else {
-                    return Ok((false, None))
-                }
+        &Value::Array(ref arr) => {
+            for (index, value) in arr.iter().enumerate() {
+                let length = path.len();
+                path.push_str(&format!("/{}", index));
+                find_paths(value, path, keyword, paths);
+                path.truncate(length);
             }
-        else {
-            Err(format!("advanced input was neither a json object or an array.").into())
         }
+        _ => {}
+    }
 }
-//gets the cursor of the json tree into the requested scope
-fn get_cursor<'a>(input: &'a mut Value, path_vec: &mut VecDeque<String>) -> Result< &'a mut Value, VideoError> {
-    if input.is_object() {
-        let path = path_vec.pop_front().ok_or(format!("did not exit properly, path contained a json object, not a final node of array or string."))?;
-        let b_tree_obj = input.as_object_mut().unwrap();
-        let json = b_tree_obj.get_mut(&path).ok_or(format!("path traversal invalid, path not found."))?;
-        Ok(get_cursor(json, path_vec)?)
-    } else {
-        Ok(input)
+
+//gets the cursor of the json tree at a json pointer, through any mix of objects & arrays.
+fn get_cursor<'a>(input: &'a mut Value, path: &str) -> Result<&'a mut Value, VideoError> {
+    let mut cursor = input;
+    for token in path.split('/').skip(1) {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        let next = cursor;
+        cursor = match next {
+            &mut Value::Object(ref mut tree) => tree.get_mut(&token),
+            &mut Value::Array(ref mut arr) => match token.parse::<usize>() {
+                Ok(index) => arr.get_mut(index),
+                Err(_) => None
+            },
+            _ => None
+        }.ok_or(format!("path traversal invalid, {} not found.", path))?;
     }
+    Ok(cursor)
 }
 
-//using the path variable, this traverses the mutable base Json tree to find the keyword containing key/value pair, once found it replaces the key/value pair with either an Array or String, depending on the data.
-pub fn replace_json(base: &mut Value, paths: &mut VecDeque<String>, array_iter: Option<usize>, data: Either<&Vec<String>, &str>) -> Result< (), VideoError> {
-    //since for our usecase we'll never see obj -> array -> obj -> result, we can safely exit when it finds the key points to an array.
-    let cursor: &mut Value = get_cursor(base, paths)?;
-    //we if check instead of pattern match because we don't want to borrow cursor, for the string branch, since we need to replace the json, not the string.
-    if cursor.is_array() {
-        let arr: &mut Vec<Value> = cursor.as_array_mut().unwrap();
-        let index = try!(array_iter.ok_or(format!("array iter must be passed if the final node is an array type.")));
-        match data {
-            Left(batch) => {
-                let mut formatted_array = Value::Array(batch.iter()
-                    .map(|d| { Value::String(d.to_string()) }).collect::<Vec<Value>>());
-                *arr.index_mut(index) = formatted_array;
-            }
-            Right(single) => {
-                let mut formatted_string = Value::String(single.to_string());
-                *arr.index_mut(index) =  formatted_string;
-            }
+//using the paths from search_json, this traverses the mutable base Json tree to every keyword & replaces it with either an Array or String, depending on the data.
+pub fn replace_json(base: &mut Value, paths: &Vec<String>, data: Either<&Vec<String>, &str>) -> Result<(), VideoError> {
+    let replacement = match data {
+        Left(batch) => Value::Array(batch.iter().map(|d| { Value::String(d.to_string()) }).collect::<Vec<Value>>()),
+        Right(single) => Value::String(single.to_string())
+    };
+    for path in paths {
+        let cursor: &mut Value = get_cursor(base, path)?;
+        if !cursor.is_string() {
+            return Err(format!("something went wrong, {} is not a keyword.", path).into())
         }
-        Ok(())
-    } else if cursor.is_string() {
-        match data {
-            Left(array) => {
-                *cursor = Value::Array(array.iter()
-                    .map(|d| { Value::String(d.to_string()) })
-                    .collect::<Vec<Value>>());
+        *cursor = replacement.clone();
+    }
+    Ok(())
+}
+
+//replaces every string in the json tree that's exactly one of the variables with its value.
+pub fn substitute(json: &mut Value, variables: &BTreeMap<&'static str, Value>) -> () {
+    let replacement = match json {
+        &mut Value::String(ref text) => variables.get(text.as_str()).cloned(),
+        &mut Value::Array(ref mut array) => {
+            for item in array.iter_mut() {
+                substitute(item, variables);
             }
-            Right(single) => {
-                *cursor = Value::String(single.to_string());
+            None
+        }
+        &mut Value::Object(ref mut object) => {
+            for (_, value) in object.iter_mut() {
+                substitute(value, variables);
             }
+            None
         }
-        Ok(())
-    } else { Err(format!("something went wrong, you should never get here.").into()) }
+        _ => None
+    };
+    if let Some(value) = replacement {
+        *json = value;
+    }
+}
+
+//true if any string in the json tree is exactly keyword.
+pub fn contains_keyword(json: &Value, keyword: &str) -> bool {
+    match json {
+        &Value::String(ref text) => text == keyword,
+        &Value::Array(ref array) => array.iter().any(|item| contains_keyword(item, keyword)),
+        &Value::Object(ref object) => object.values().any(|value| contains_keyword(value, keyword)),
+        _ => false
+    }
 }
 
-//takes an array of json blobs & a frame stamp, returns a json object with an array of json objects containing the frame's timestamp & data.
-pub fn combine_data_extract(data: &Vec<Value>, frame_stamp: f64) -> Result<Value, VideoError> {
+//takes an array of json blobs & each frame's timestamp, returns a json object with an array of json objects containing the frame's timestamp & data.
+pub fn combine_data_extract(data: &Vec<Value>, timestamps: &Vec<f64>) -> Result<Value, VideoError> {
+    if data.len() != timestamps.len() {
+        return Err(format!("got {} extracted results for {} frames.", data.len(), timestamps.len()).into())
+    }
     let mut combined: Vec<Value> = Vec::new();
     for iter in 0..data.len() {
         let ref value: Value = data[iter];
-        let time_s: f64 = iter as f64 * frame_stamp;
+        let time_s: f64 = timestamps[iter];
         let json = json!({
             "timestamp": time_s,
             "data": value.clone()
@@ -127,3 +129,17 @@ pub fn combine_data_extract(data: &Vec<Value>, frame_stamp: f64) -> Result<Value
     });
     Ok(finale)
 }
+
+//joins the frame_data of extract results that were produced chunk by chunk, in order.
+pub fn concat_data_extract(parts: Vec<Value>) -> Result<Value, VideoError> {
+    let mut combined: Vec<Value> = Vec::new();
+    for part in parts {
+        match part["frame_data"] {
+            Value::Array(ref frames) => combined.extend(frames.iter().cloned()),
+            _ => return Err(format!("chunk result did not contain any frame_data.").into())
+        }
+    }
+    Ok(json!({
+    "frame_data" : combined
+    }))
+}
@@ -5,20 +5,20 @@ use std::io::{BufWriter, Write};
 use std::fs::File;
 use serde_json::to_string;
 
-pub fn frame_batches_advanced(batch_size: usize, number_of_frames: usize, option: &str) -> Box<Vec<Vec<usize>>> {
+pub fn frame_batches_advanced(batch_size: usize, frames: &Vec<usize>, option: &str) -> Box<Vec<Vec<usize>>> {
     match option {
         "batch" => {
-            frame_batches_simple(batch_size, number_of_frames)
+            frame_batches_simple(batch_size, frames)
         }
         _ => {
-            frame_batches_simple(1, number_of_frames)
+            frame_batches_simple(1, frames)
         }
     }
 }
 
-pub fn frame_batches_simple(batch_size: usize, number_of_frames: usize) -> Box<Vec<Vec<usize>>> {
-    let array: Vec<usize> = (1..number_of_frames).collect::<Vec<usize>>();
-    Box::new(array.chunks(batch_size).map(|chunk| { chunk.iter().cloned().collect() }).collect::<Vec<Vec<usize>>>())
+//frames are the ffmpeg frame numbers still to process, which start at 1.
+pub fn frame_batches_simple(batch_size: usize, frames: &Vec<usize>) -> Box<Vec<Vec<usize>>> {
+    Box::new(frames.chunks(batch_size).map(|chunk| { chunk.iter().cloned().collect() }).collect::<Vec<Vec<usize>>>())
 }
 
 pub fn json_to_file(json: &Value, json_path: &Path) -> Result<PathBuf, VideoError> {
@@ -0,0 +1,261 @@
+//A stand-in for the Algorithmia API, serving the data API from a MemoryStorage and the algo pipe endpoint from scripted handlers.
+use common::storage::{MemoryStorage, Storage};
+use serde_json::{self, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+static LAST_MODIFIED: &'static str = "Thu, 01 Jan 2015 00:00:00 GMT";
+
+pub enum MockResponse {
+    Result(Value),
+    Error(u16, String),
+}
+
+//gets the request json, the number of earlier calls to the same algorithm & the data store.
+pub type Handler = Fn(&Value, usize, &MemoryStorage) -> MockResponse + Send + Sync;
+
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct MockServer {
+    url: String,
+    data: MemoryStorage,
+    algorithms: Arc<Mutex<HashMap<String, Arc<Handler>>>>,
+    calls: Arc<Mutex<HashMap<String, usize>>>,
+    read_only: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MockServer {
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = MockServer {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            data: MemoryStorage::new(),
+            algorithms: Arc::new(Mutex::new(HashMap::new())),
+            calls: Arc::new(Mutex::new(HashMap::new())),
+            read_only: Arc::new(Mutex::new(HashSet::new())),
+        };
+        let inner = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let connection = inner.clone();
+                    thread::spawn(move || { connection.handle(stream); });
+                }
+            }
+        });
+        server
+    }
+
+    pub fn url(&self) -> &str {&self.url}
+    pub fn data(&self) -> &MemoryStorage {&self.data}
+
+    //scripts the response for an algorithm, matched on its path without the version, eg: "test/Identity".
+    pub fn script<F>(&self, algorithm: &str, handler: F) where F: Fn(&Value, usize, &MemoryStorage) -> MockResponse + Send + Sync + 'static {
+        self.algorithms.lock().unwrap().insert(algorithm.to_string(), Arc::new(handler));
+    }
+
+    //puts & deletes under this uri prefix fail with a 403.
+    pub fn read_only(&self, prefix: &str) {
+        self.read_only.lock().unwrap().insert(prefix.to_string());
+    }
+
+    pub fn calls(&self, algorithm: &str) -> usize {
+        self.calls.lock().unwrap().get(algorithm).cloned().unwrap_or(0)
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let request = match read_request(&mut reader) {
+            Some(request) => request,
+            None => return
+        };
+        let (status, headers, body) = if request.path.starts_with("/v1/algo/") {
+            self.pipe(&request)
+        } else {
+            match data_uri(&request.path) {
+                Some(uri) => self.data_api(&request, &uri),
+                None => (404, Vec::new(), error_body("unknown endpoint"))
+            }
+        };
+        write_response(stream, status, headers, body, request.method == "HEAD");
+    }
+
+    fn data_api(&self, request: &Request, uri: &str) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        let file_headers = vec![("X-Data-Type".to_string(), "file".to_string()),
+                                ("Last-Modified".to_string(), LAST_MODIFIED.to_string())];
+        let read_only = self.read_only.lock().unwrap().iter().any(|prefix| uri.starts_with(prefix.as_str()));
+        match request.method.as_str() {
+            "HEAD" | "GET" => {
+                match self.data.contents(uri) {
+                    Some(bytes) => (200, file_headers, bytes),
+                    None => (404, Vec::new(), error_body(&format!("{} does not exist", uri)))
+                }
+            }
+            "PUT" if read_only => (403, Vec::new(), error_body(&format!("permission denied for {}", uri))),
+            "PUT" => {
+                self.data.put_bytes(uri, &request.body).unwrap();
+                (200, Vec::new(), json!({"result": uri}).to_string().into_bytes())
+            }
+            "DELETE" if read_only => (403, Vec::new(), error_body(&format!("permission denied for {}", uri))),
+            "DELETE" if self.data.exists(uri).unwrap_or(false) => {
+                self.data.delete(uri).unwrap();
+                (200, Vec::new(), json!({"result": {"deleted": 1}}).to_string().into_bytes())
+            }
+            //anything else is a directory, it has to be forced if there are files in it.
+            "DELETE" => {
+                let deleted = self.data.files(uri).len();
+                match self.data.delete_directory(uri, request.query.contains("force=true")) {
+                    Ok(()) => (200, Vec::new(), json!({"result": {"deleted": deleted}}).to_string().into_bytes()),
+                    Err(err) => (400, Vec::new(), error_body(&err.to_string()))
+                }
+            }
+            _ => (405, Vec::new(), error_body("method not allowed"))
+        }
+    }
+
+    fn pipe(&self, request: &Request) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        let parts: Vec<&str> = request.path.trim_left_matches("/v1/algo/").split('/').collect();
+        let algorithm = parts.iter().take(2).cloned().collect::<Vec<&str>>().join("/");
+        let handler = match self.algorithms.lock().unwrap().get(&algorithm) {
+            Some(handler) => handler.clone(),
+            None => return (404, Vec::new(), error_body(&format!("algorithm {} not found", algorithm)))
+        };
+        let input: Value = match serde_json::from_slice(&request.body) {
+            Ok(input) => input,
+            Err(err) => return (400, Vec::new(), error_body(&format!("invalid json: {}", err)))
+        };
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            let count = calls.entry(algorithm.clone()).or_insert(0);
+            *count += 1;
+            *count - 1
+        };
+        match handler(&input, call, &self.data) {
+            MockResponse::Result(result) => {
+                let body = json!({"result": result, "metadata": {"content_type": "json", "duration": 0.01}});
+                (200, Vec::new(), body.to_string().into_bytes())
+            }
+            MockResponse::Error(status, message) => (status, Vec::new(), error_body(&message))
+        }
+    }
+}
+
+//copies each input file to its output path, understands both single & batch inputs.
+pub fn identity(input_key: &'static str, output_key: &'static str) -> Box<Fn(&Value, usize, &MemoryStorage) -> MockResponse + Send + Sync> {
+    Box::new(move |input: &Value, _: usize, data: &MemoryStorage| {
+        let sources = strings(&input[input_key]);
+        let destinations = strings(&input[output_key]);
+        for (source, destination) in sources.iter().zip(destinations.iter()) {
+            match data.contents(source) {
+                Some(bytes) => { data.put_bytes(destination, &bytes).unwrap(); }
+                None => return MockResponse::Error(400, format!("{} does not exist", source))
+            }
+        }
+        MockResponse::Result(json!({"output": destinations}))
+    })
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    match value {
+        &Value::String(ref text) => vec![text.clone()],
+        &Value::Array(ref array) => array.iter().filter_map(|item| item.as_str().map(|text| text.to_string())).collect(),
+        _ => Vec::new()
+    }
+}
+
+//the data api lives at /v1/connector/<scheme>/<path>, older clients use /v1/data/<path> for data://.
+fn data_uri(path: &str) -> Option<String> {
+    if path.starts_with("/v1/connector/") {
+        let rest = path.trim_left_matches("/v1/connector/");
+        let mut split = rest.splitn(2, '/');
+        let scheme = split.next().unwrap_or("");
+        let remainder = split.next().unwrap_or("");
+        Some(format!("{}://{}", scheme, remainder))
+    } else if path.starts_with("/v1/data/") {
+        Some(format!("data://{}", path.trim_left_matches("/v1/data/")))
+    } else {
+        None
+    }
+}
+
+fn error_body(message: &str) -> Vec<u8> {
+    json!({"error": {"message": message}}).to_string().into_bytes()
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<Request> {
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).ok()? == 0 { return None }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+    let path = target.split('?').next().unwrap().to_string();
+    let query = target.splitn(2, '?').nth(1).unwrap_or("").to_string();
+
+    let mut content_length: usize = 0;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_right();
+        if line.is_empty() { break }
+        let mut header = line.splitn(2, ':');
+        let name = header.next().unwrap_or("").trim().to_lowercase();
+        let value = header.next().unwrap_or("").trim().to_string();
+        if name == "content-length" {
+            content_length = value.parse().unwrap_or(0);
+        } else if name == "transfer-encoding" && value.to_lowercase().contains("chunked") {
+            chunked = true;
+        }
+    }
+
+    let mut body: Vec<u8> = Vec::new();
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line).ok()?;
+            let size = usize::from_str_radix(size_line.trim().split(';').next().unwrap(), 16).ok()?;
+            let mut chunk = vec![0u8; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 { break }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if content_length > 0 {
+        body = vec![0u8; content_length];
+        reader.read_exact(&mut body).ok()?;
+    }
+    Some(Request { method: method, path: path, query: query, body: body })
+}
+
+fn write_response(mut stream: TcpStream, status: u16, headers: Vec<(String, String)>, body: Vec<u8>, head_only: bool) {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    };
+    let mut response = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, reason, body.len());
+    if !headers.iter().any(|&(ref name, _)| name == "X-Data-Type") {
+        response.push_str("Content-Type: application/json\r\n");
+    }
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    let _ = stream.write_all(response.as_bytes());
+    if !head_only {
+        let _ = stream.write_all(&body);
+    }
+    let _ = stream.flush();
+}
@@ -6,6 +6,13 @@ pub mod structs;
 pub mod misc;
 pub mod json_utils;
 pub mod threading;
+pub mod concurrency;
 pub mod watchdog;
 pub mod preprocess;
-pub mod algo;
\ No newline at end of file
+pub mod config;
+pub mod algo;
+pub mod storage;
+pub mod checkpoint;
+pub mod cache;
+#[cfg(test)]
+pub mod mock_server;
\ No newline at end of file
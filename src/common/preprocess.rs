@@ -4,32 +4,48 @@ use common::structs::ffmpeg::FFMpeg;
 use uuid::Uuid;
 use common::file_mgmt::clean_up;
 use common::video_error::VideoError;
+use common::storage::Storages;
+use common::config::Config;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExecutionStyle {
     Algo,
     ProdLocal,
     TestLocal
 }
 
+impl ExecutionStyle {
+    pub fn from_name(name: &str) -> Result<ExecutionStyle, VideoError> {
+        match name.trim().to_lowercase().replace("-", "_").as_str() {
+            "algo" => Ok(ExecutionStyle::Algo),
+            "prod_local" | "prodlocal" => Ok(ExecutionStyle::ProdLocal),
+            "test_local" | "testlocal" => Ok(ExecutionStyle::TestLocal),
+            _ => Err(format!("unknown execution style '{}', must be one of: algo, prod_local, test_local", name).into())
+        }
+    }
+}
+
 
 pub struct PreDefines{
     pub client: Algorithmia,
+    pub storages: Storages,
     pub scattered_working_directory: PathBuf,
     pub processed_working_directory: PathBuf,
     pub video_working_directory: PathBuf,
+    pub cache_working_directory: PathBuf,
     pub data_api_work_directory: String,
     pub local_input_file: PathBuf,
     pub local_output_file: PathBuf,
     pub ffmpeg: FFMpeg,
     pub scatter_regex: String,
-    pub process_regex: String,
+    pub audio_regex: String,
     pub batch_size: usize,
     pub starting_threads: isize,
     pub max_threads: isize
 }
 
 impl PreDefines {
-    pub fn create(format: ExecutionStyle,
+    pub fn create(config: &Config,
                   batch_size: usize,
                   starting_threads: usize,
                   max_threads: usize,
@@ -37,41 +53,38 @@ impl PreDefines {
                   input_file: &str,
                   has_image_compression: bool
     ) -> Result<PreDefines, VideoError> {
-        let prod_key = "simA8y8WJtWGW+4h1hB0sLKnvb11";
-        let test_key = "simA8y8WJtWGW+4h1hB0sLKnvb11";
-        let test_api = "https://api.test.algorithmia.com";
-        let session = String::from("data://.session");
-        let not_session = String::from("data://.my/ProcessVideo");
-
-        let (client, data_work_dir) = match format {
-            ExecutionStyle::Algo => { (Algorithmia::default(), session) }
-            ExecutionStyle::ProdLocal => { (Algorithmia::client(prod_key), not_session) }
-            ExecutionStyle::TestLocal => { (Algorithmia::client_with_url(test_api, test_key), not_session) }
-        };
+        let client = config.client();
+        let data_work_dir = config.data_work_directory().to_string();
+        let local_root = config.local_work_directory();
+        let storages = Storages::new(&client);
         let ffmpeg_remote_url = "data://media/bin/ffmpeg-static.tar.gz";
-        let ffmpeg_working_directory = PathBuf::from("/tmp/ffmpeg");
-        let scattered_working_directory = PathBuf::from("/tmp/scattered_frames");
-        let processed_working_directory = PathBuf::from("/tmp/processed_frames");
-        let video_working_directory = PathBuf::from("/tmp/video");
+        let ffmpeg_working_directory = local_root.join("ffmpeg");
+        let scattered_working_directory = local_root.join("scattered_frames");
+        let processed_working_directory = local_root.join("processed_frames");
+        let video_working_directory = local_root.join("video");
+        //cached results are downloaded here to be read, away from the frames.
+        let cache_working_directory = local_root.join("cached_results");
         let local_output_file: PathBuf = PathBuf::from(format!("{}/{}", video_working_directory.display(), output_file.split("/").last().unwrap().clone()));
         let local_input_file: PathBuf = PathBuf::from(format!("{}/{}", video_working_directory.display(), input_file.split("/").last().unwrap().clone()));
         let input_uuid = Uuid::new_v4();
-        let output_uuid = Uuid::new_v4();
         let scatter_regex = if has_image_compression { format!("{}-%07d.jpg", input_uuid) } else { format!("{}-%07d.png", input_uuid) };
-        let process_regex = if has_image_compression { format!("{}-%07d.jpg", output_uuid) } else { format!("{}-%07d.png", output_uuid) };
-        clean_up(Some(&scattered_working_directory), Some(&processed_working_directory), &video_working_directory);
-        let ffmpeg: FFMpeg = FFMpeg::create(ffmpeg_remote_url, &ffmpeg_working_directory, &client)?;
+        let audio_regex = format!("{}-%07d.wav", input_uuid);
+        //processed frames are kept between runs so a failed job can be resumed, the checkpoint cleans them up.
+        clean_up(Some(&scattered_working_directory), None, &video_working_directory);
+        let ffmpeg: FFMpeg = FFMpeg::create(ffmpeg_remote_url, &ffmpeg_working_directory, storages.resolve(ffmpeg_remote_url)?.as_ref())?;
         Ok(PreDefines {
             client: client,
+            storages: storages,
             scattered_working_directory: scattered_working_directory,
             processed_working_directory: processed_working_directory,
             data_api_work_directory: data_work_dir,
             video_working_directory: video_working_directory,
+            cache_working_directory: cache_working_directory,
             local_input_file: local_input_file,
             local_output_file: local_output_file,
             ffmpeg: ffmpeg,
             scatter_regex: scatter_regex,
-            process_regex: process_regex,
+            audio_regex: audio_regex,
             batch_size: batch_size,
             starting_threads: starting_threads as isize,
             max_threads: max_threads as isize
@@ -0,0 +1,308 @@
+use algorithmia::Algorithmia;
+use algorithmia::data::{FileData, HasDataPath};
+use common::video_error::VideoError;
+use common::file_mgmt::create_directory;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{copy, Read, Write};
+use std::path::*;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+static LOCAL_SCHEME: &'static str = "file";
+static MEMORY_SCHEME: &'static str = "memory";
+
+//anywhere files can be downloaded from and uploaded to, addressed by uri.
+pub trait Storage: Send + Sync {
+    fn get(&self, url: &str, local_path: &Path) -> Result<PathBuf, VideoError>;
+    fn put(&self, url: &str, local_file: &Path) -> Result<(), VideoError>;
+    fn put_bytes(&self, url: &str, bytes: &[u8]) -> Result<(), VideoError>;
+    fn exists(&self, url: &str) -> Result<bool, VideoError>;
+    fn delete(&self, url: &str) -> Result<(), VideoError>;
+    //removes a directory, only when it's empty unless recursive is set.
+    fn delete_directory(&self, url: &str, recursive: bool) -> Result<(), VideoError>;
+}
+
+//the Algorithmia data API, used for data:// and every other hosted data connector (s3://, dropbox://, etc).
+#[derive(Clone)]
+pub struct AlgorithmiaStorage {
+    client: Algorithmia
+}
+
+//the local filesystem, used for file:// uris.
+#[derive(Clone)]
+pub struct LocalStorage;
+
+//a shared in-memory store used for memory:// uris, mostly useful for tests.
+#[derive(Clone)]
+pub struct MemoryStorage {
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>
+}
+
+//picks the storage implementation for a uri based on its scheme.
+#[derive(Clone)]
+pub struct Storages {
+    algorithmia: Arc<Storage>,
+    local: Arc<Storage>,
+    memory: Option<Arc<Storage>>,
+}
+
+impl AlgorithmiaStorage {
+    pub fn new(client: Algorithmia) -> AlgorithmiaStorage {
+        AlgorithmiaStorage { client: client }
+    }
+}
+
+impl Storage for AlgorithmiaStorage {
+    fn get(&self, url: &str, local_path: &Path) -> Result<PathBuf, VideoError> {
+        let local_dir = local_path.parent().unwrap();
+        create_directory(local_dir);
+        let file = self.client.file(&url);
+        match file.exists() {
+            Ok(true) => {
+                let mut remote_file: FileData = file.get().map_err(|err| format!("couldn't download file from url: {} \n{}", &url, err))?;
+                let mut local_file = File::create(local_path).map_err(|err| format!("couldn't create local file: {} \n{}", local_path.to_str().unwrap(), err))?;
+                thread::sleep(Duration::from_secs(2));
+                copy(&mut remote_file, &mut local_file).map_err(|err| format!("couldn't copy remote file to local: {} \n{}", local_path.to_str().unwrap(), err))?;
+                Ok(PathBuf::from(local_path))
+            }
+            Ok(false) => { Err(format!("file not ready").into()) }
+            Err(error) => {
+                Err(format!("recieved an error trying to download {}\n{}", url, error).into())
+            }
+        }
+    }
+
+    fn put(&self, url: &str, local_file: &Path) -> Result<(), VideoError> {
+        let file: File = File::open(local_file).map_err(|err| { format!("failed to open file: {}\n{}", local_file.display(), err) })?;
+        self.client.file(url).put(file).map_err(|err| { format!("upload failure for:{}\n{}", url, err).into() })
+    }
+
+    fn put_bytes(&self, url: &str, bytes: &[u8]) -> Result<(), VideoError> {
+        self.client.file(url).put(bytes.to_vec()).map_err(|err| { format!("upload failure for:{}\n{}", url, err).into() })
+    }
+
+    fn exists(&self, url: &str) -> Result<bool, VideoError> {
+        self.client.file(url).exists().map_err(|err| { format!("couldn't check if {} exists\n{}", url, err).into() })
+    }
+
+    fn delete(&self, url: &str) -> Result<(), VideoError> {
+        self.client.file(url).delete().map(|_| ()).map_err(|err| { format!("couldn't delete {}\n{}", url, err).into() })
+    }
+
+    fn delete_directory(&self, url: &str, recursive: bool) -> Result<(), VideoError> {
+        self.client.dir(url).delete(recursive).map(|_| ()).map_err(|err| { format!("couldn't delete directory {}\n{}", url, err).into() })
+    }
+}
+
+impl LocalStorage {
+    //file:///tmp/video.mp4 -> /tmp/video.mp4
+    fn path(url: &str) -> PathBuf {
+        PathBuf::from(strip_scheme(url))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get(&self, url: &str, local_path: &Path) -> Result<PathBuf, VideoError> {
+        let source = LocalStorage::path(url);
+        if !source.exists() {
+            return Err(format!("file not ready").into())
+        }
+        create_directory(local_path.parent().unwrap());
+        fs::copy(&source, local_path).map_err(|err| format!("couldn't copy {} to {}\n{}", source.display(), local_path.display(), err))?;
+        Ok(PathBuf::from(local_path))
+    }
+
+    fn put(&self, url: &str, local_file: &Path) -> Result<(), VideoError> {
+        let destination = LocalStorage::path(url);
+        destination.parent().map(|dir| create_directory(dir));
+        fs::copy(local_file, &destination).map_err(|err| format!("upload failure for:{}\n{}", url, err))?;
+        Ok(())
+    }
+
+    fn put_bytes(&self, url: &str, bytes: &[u8]) -> Result<(), VideoError> {
+        let destination = LocalStorage::path(url);
+        destination.parent().map(|dir| create_directory(dir));
+        let mut file = File::create(&destination).map_err(|err| format!("upload failure for:{}\n{}", url, err))?;
+        file.write_all(bytes)?;
+        Ok(())
+    }
+
+    fn exists(&self, url: &str) -> Result<bool, VideoError> {
+        Ok(LocalStorage::path(url).exists())
+    }
+
+    fn delete(&self, url: &str) -> Result<(), VideoError> {
+        fs::remove_file(LocalStorage::path(url)).map_err(|err| format!("couldn't delete {}\n{}", url, err).into())
+    }
+
+    fn delete_directory(&self, url: &str, recursive: bool) -> Result<(), VideoError> {
+        let path = LocalStorage::path(url);
+        let removed = if recursive { fs::remove_dir_all(&path) } else { fs::remove_dir(&path) };
+        removed.map_err(|err| format!("couldn't delete directory {}\n{}", url, err).into())
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage { files: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn contents(&self, url: &str) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(url).cloned()
+    }
+
+    //every file under a directory uri, at any depth. there are no directories of their own, a directory exists while it has files.
+    pub fn files(&self, directory: &str) -> Vec<String> {
+        let prefix = format!("{}/", directory.trim_right_matches('/'));
+        let mut files: Vec<String> = self.files.lock().unwrap().keys().filter(|url| url.starts_with(&prefix)).cloned().collect();
+        files.sort();
+        files
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, url: &str, local_path: &Path) -> Result<PathBuf, VideoError> {
+        let bytes = self.contents(url).ok_or(format!("file not ready"))?;
+        create_directory(local_path.parent().unwrap());
+        let mut file = File::create(local_path).map_err(|err| format!("couldn't create local file: {} \n{}", local_path.display(), err))?;
+        file.write_all(&bytes)?;
+        Ok(PathBuf::from(local_path))
+    }
+
+    fn put(&self, url: &str, local_file: &Path) -> Result<(), VideoError> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut file = File::open(local_file).map_err(|err| { format!("failed to open file: {}\n{}", local_file.display(), err) })?;
+        file.read_to_end(&mut bytes)?;
+        self.put_bytes(url, &bytes)
+    }
+
+    fn put_bytes(&self, url: &str, bytes: &[u8]) -> Result<(), VideoError> {
+        self.files.lock().unwrap().insert(url.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, url: &str) -> Result<bool, VideoError> {
+        Ok(self.files.lock().unwrap().contains_key(url))
+    }
+
+    fn delete(&self, url: &str) -> Result<(), VideoError> {
+        match self.files.lock().unwrap().remove(url) {
+            Some(_) => Ok(()),
+            None => Err(format!("couldn't delete {}, it doesn't exist.", url).into())
+        }
+    }
+
+    fn delete_directory(&self, url: &str, recursive: bool) -> Result<(), VideoError> {
+        let files = self.files(url);
+        if !recursive && !files.is_empty() {
+            return Err(format!("couldn't delete directory {}, it isn't empty.", url).into())
+        }
+        let mut stored = self.files.lock().unwrap();
+        for file in files {
+            stored.remove(&file);
+        }
+        Ok(())
+    }
+}
+
+impl Storages {
+    pub fn new(client: &Algorithmia) -> Storages {
+        Storages {
+            algorithmia: Arc::new(AlgorithmiaStorage::new(client.clone())),
+            local: Arc::new(LocalStorage),
+            memory: None,
+        }
+    }
+
+    pub fn with_memory(self, memory: MemoryStorage) -> Storages {
+        Storages { memory: Some(Arc::new(memory)), ..self }
+    }
+
+    //file:// is the local filesystem, memory:// is the in-memory store if one was provided, everything else goes through the data API.
+    pub fn resolve(&self, url: &str) -> Result<Arc<Storage>, VideoError> {
+        match scheme(url) {
+            Some(ref scheme) if scheme == LOCAL_SCHEME => Ok(self.local.clone()),
+            Some(ref scheme) if scheme == MEMORY_SCHEME => {
+                self.memory.clone().ok_or(format!("no in-memory storage was configured for {}", url).into())
+            }
+            Some(ref scheme) if scheme == "http" || scheme == "https" => {
+                Err(format!("{} is a web url, it can't be used as storage.", url).into())
+            }
+            Some(_) => Ok(self.algorithmia.clone()),
+            None => Err(format!("{} is not a valid uri, it must start with a scheme like data:// or file://", url).into())
+        }
+    }
+}
+
+pub fn scheme(url: &str) -> Option<String> {
+    let trimmed = url.trim();
+    trimmed.find("://").map(|index| trimmed[..index].to_lowercase())
+}
+
+pub fn strip_scheme(url: &str) -> &str {
+    let trimmed = url.trim();
+    match trimmed.find("://") {
+        Some(index) => &trimmed[index + 3..],
+        None => trimmed
+    }
+}
+
+pub fn is_local(url: &str) -> bool {
+    scheme(url).map(|scheme| scheme == LOCAL_SCHEME).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use uuid::Uuid;
+
+    //a directory of its own for every test run, so tests running in parallel or again don't see each other's files.
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("{}-{}", name, Uuid::new_v4()));
+        create_directory(&directory);
+        directory
+    }
+
+    #[test]
+    fn memory_round_trip() {
+        let scratch = scratch_directory("storage_memory_test");
+        let memory = MemoryStorage::new();
+        let local = scratch.join("memory_in.txt");
+        File::create(&local).unwrap().write_all(b"frame").unwrap();
+        memory.put("memory://frames/1.png", &local).unwrap();
+        assert!(memory.exists("memory://frames/1.png").unwrap());
+        let out = memory.get("memory://frames/1.png", &scratch.join("memory_out.txt")).unwrap();
+        let mut contents = String::new();
+        File::open(out).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "frame");
+        memory.delete("memory://frames/1.png").unwrap();
+        assert!(!memory.exists("memory://frames/1.png").unwrap());
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[test]
+    fn local_round_trip() {
+        let scratch = scratch_directory("storage_local_test");
+        let local = scratch.join("local_in.txt");
+        File::create(&local).unwrap().write_all(b"frame").unwrap();
+        let remote = format!("file://{}", scratch.join("remote").join("local.txt").display());
+        LocalStorage.put(&remote, &local).unwrap();
+        assert!(LocalStorage.exists(&remote).unwrap());
+        LocalStorage.delete(&remote).unwrap();
+        assert!(!LocalStorage.exists(&remote).unwrap());
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[test]
+    fn scheme_parsing() {
+        assert_eq!(scheme(" data://.my/ProcessVideo"), Some("data".to_string()));
+        assert_eq!(scheme("FILE:///tmp/video.mp4"), Some("file".to_string()));
+        assert_eq!(scheme("/tmp/video.mp4"), None);
+        assert_eq!(strip_scheme("file:///tmp/video.mp4"), "/tmp/video.mp4");
+    }
+}
@@ -1,101 +1,161 @@
 
-use std::collections::VecDeque;
 use serde_json::Value;
 use either::Either;
 use common::video_error::VideoError;
-use common::json_utils::{replace_json, search_json};
+use common::json_utils::{replace_json, search_json, substitute, contains_keyword};
+use common::structs::frame_context::{Variables, FRAME_INDEX, TIMESTAMP, PREVIOUS_FRAME, BATCH_INDICES, BATCH_TIMESTAMPS, SINGLE_VARIABLES, BATCH_VARIABLES, VARIABLES};
 
 static BATCH_INPUT: &'static str = "$BATCH_INPUT";
 static SINGLE_INPUT: &'static str = "$SINGLE_INPUT";
 static BATCH_OUTPUT: &'static str = "$BATCH_OUTPUT";
 static SINGLE_OUTPUT: &'static str = "$SINGLE_OUTPUT";
+static AUDIO_INPUT: &'static str = "$AUDIO_INPUT";
+static AUDIO_OUTPUT: &'static str = "$AUDIO_OUTPUT";
 
 
 #[derive(Debug, Clone)]
 pub struct AdvancedInput {
     batch_single: String,
-    in_path: VecDeque<String>,
-    in_array_iter: Option<usize>,
-    out_path: VecDeque<String>,
-    out_array_iter: Option<usize>,
+    //json pointers of every input & output keyword, they're all replaced.
+    in_paths: Vec<String>,
+    out_paths: Vec<String>,
     source: Value,
 }
 
 impl AdvancedInput {
-    fn new (batch_single: String, in_path: VecDeque<String>, in_array_iter: Option<usize>,
-                out_path: VecDeque<String>, out_array_iter: Option<usize>, source: Value) -> AdvancedInput {
-        AdvancedInput {batch_single: batch_single, in_path: in_path,
-            in_array_iter: in_array_iter, out_array_iter: out_array_iter,
-            out_path: out_path, source: source}
+    fn new (batch_single: String, in_paths: Vec<String>, out_paths: Vec<String>, source: Value) -> AdvancedInput {
+        AdvancedInput {batch_single: batch_single, in_paths: in_paths,
+            out_paths: out_paths, source: source}
     }
     pub fn option(&self) -> &str {&self.batch_single}
-    pub fn in_path(&self) -> &VecDeque<String> {&self.in_path}
-    pub fn in_array_iter(&self) -> Option<usize> {self.in_array_iter}
-    pub fn out_path(&self) -> &VecDeque<String> {&self.out_path}
-    pub fn out_array_iter(&self) -> Option<usize> {self.out_array_iter}
+    pub fn in_paths(&self) -> &Vec<String> {&self.in_paths}
+    pub fn out_paths(&self) -> &Vec<String> {&self.out_paths}
     pub fn source(&self) -> &Value {&self.source}
 
 
-    pub fn replace_variables_extract(&self, input: Either<&Vec<String>, &str>) -> Result<Value, VideoError> {
+    //variables are the frame context keywords for this call, see FrameContext.
+    pub fn replace_variables_extract(&self, input: Either<&Vec<String>, &str>, variables: &Variables) -> Result<Value, VideoError> {
         let mut mutable: Value = self.source().clone();
-        let mut in_path = self.in_path().clone();
         //for input
-        replace_json(&mut mutable, &mut in_path, self.in_array_iter(), input)?;
+        replace_json(&mut mutable, self.in_paths(), input)?;
+        substitute(&mut mutable, variables);
         Ok(mutable)
     }
 
 
-    pub fn replace_variables_transform(&self, input: Either<&Vec<String>, &str>, output: Either<&Vec<String>, &str>) -> Result<Value, VideoError> {
+    pub fn replace_variables_transform(&self, input: Either<&Vec<String>, &str>, output: Either<&Vec<String>, &str>, variables: &Variables) -> Result<Value, VideoError> {
         let mut mutable: Value = self.source().clone();
-        let mut in_path = self.in_path().clone();
-        let mut out_path = self.out_path().clone();
         //for input
-        replace_json(&mut mutable, &mut in_path, self.in_array_iter(), input)?;
+        replace_json(&mut mutable, self.in_paths(), input)?;
         //for output
-        replace_json(&mut mutable, &mut out_path, self.out_array_iter(), output)?;
+        replace_json(&mut mutable, self.out_paths(), output)?;
+        substitute(&mut mutable, variables);
         Ok(mutable)
     }
 
 
+    //true if the previous frame has to be available to the algorithm as well.
+    pub fn uses_previous_frame(&self) -> bool {
+        contains_keyword(&self.source, PREVIOUS_FRAME)
+    }
+
+
+    //the first variable in source whose value differs from frame to frame, frames that look the same still get different inputs with it.
+    pub fn frame_variable(source: &Value) -> Option<&'static str> {
+        SINGLE_VARIABLES.iter().chain(BATCH_VARIABLES.iter()).find(|variable| contains_keyword(source, variable)).cloned()
+    }
+
+
+    //true if the algorithm gets anything about the frame besides its contents.
+    pub fn uses_frame_context(&self) -> bool {
+        VARIABLES.iter().any(|variable| contains_keyword(&self.source, variable))
+    }
+
+
+    //per frame variables only mean something when frames are sent one at a time, and the batch ones when they're sent together.
+    fn check_variables(self) -> Result<AdvancedInput, VideoError> {
+        let (wrong, right) = if self.batch_single == "batch" { (&SINGLE_VARIABLES[..], (BATCH_INDICES, BATCH_TIMESTAMPS)) } else { (&BATCH_VARIABLES[..], (FRAME_INDEX, TIMESTAMP)) };
+        match wrong.iter().find(|variable| contains_keyword(&self.source, variable)) {
+            Some(variable) => Err(format!("json parsing error:\n{} can't be used when frames are sent {}, use {} or {} instead.",
+                                          variable, if self.batch_single == "batch" { "as a batch" } else { "one at a time" }, right.0, right.1).into()),
+            None => Ok(self)
+        }
+    }
+
+
     //only difference between extract & alter format search, extract only cares about input keywords, it doesn't have output keywords.
     pub fn create_extract(json: &Value) -> Result<AdvancedInput, VideoError> {
-        let mut batch_in_path = VecDeque::new();
-        let mut single_in_path = VecDeque::new();
-        let (batch_in, batch_iter) = try!(search_json(json, &mut batch_in_path, BATCH_INPUT));
-        let (single_in, single_iter) = try!(search_json(json, &mut single_in_path, SINGLE_INPUT));
-        if batch_in {
+        let batch_in_paths = search_json(json, BATCH_INPUT)?;
+        let single_in_paths = search_json(json, SINGLE_INPUT)?;
+        if !batch_in_paths.is_empty() {
             println!("json parsed as batch input.");
-            Ok(AdvancedInput::new("batch".to_string(), batch_in_path.into(),
-                                  batch_iter, VecDeque::new(), None, json.clone()))
-        } else if single_in {
+            AdvancedInput::new("batch".to_string(), batch_in_paths, Vec::new(), json.clone()).check_variables()
+        } else if !single_in_paths.is_empty() {
             println!("json parsed as single input.");
-            Ok(AdvancedInput::new("single".to_string(), single_in_path.into(),
-                                  single_iter, VecDeque::new(), None, json.clone()))
+            AdvancedInput::new("single".to_string(), single_in_paths, Vec::new(), json.clone()).check_variables()
         } else {
             Err(String::from("json parsing error:\nadvanced_input did not contain any keywords!").into())
         }
     }
 
 
+    //audio windows are always sent one at a time, so the audio keywords behave like the single ones.
+    pub fn create_audio_extract(json: &Value) -> Result<AdvancedInput, VideoError> {
+        let in_paths = search_json(json, AUDIO_INPUT)?;
+        if !in_paths.is_empty() {
+            AdvancedInput::new("single".to_string(), in_paths, Vec::new(), json.clone()).check_variables()
+        } else {
+            Err(String::from("json parsing error:\nadvanced_input did not contain $AUDIO_INPUT!").into())
+        }
+    }
+
+
+    pub fn create_audio_transform(json: &Value) -> Result<AdvancedInput, VideoError> {
+        let in_paths = search_json(json, AUDIO_INPUT)?;
+        let out_paths = search_json(json, AUDIO_OUTPUT)?;
+        if !in_paths.is_empty() && !out_paths.is_empty() {
+            AdvancedInput::new("single".to_string(), in_paths, out_paths, json.clone()).check_variables()
+        } else {
+            Err(String::from("json parsing error:\nboth $AUDIO_INPUT and $AUDIO_OUTPUT must be defined.").into())
+        }
+    }
+
+
+    //true if the json contains $AUDIO_OUTPUT, used to pick between the audio objectives.
+    pub fn has_audio_output(json: &Value) -> Result<bool, VideoError> {
+        Ok(!search_json(json, AUDIO_OUTPUT)?.is_empty())
+    }
+
+
+    //true if the json contains $AUDIO_INPUT, requests with it process the audio track instead of frames.
+    pub fn has_audio_input(json: &Value) -> Result<bool, VideoError> {
+        Ok(!search_json(json, AUDIO_INPUT)?.is_empty())
+    }
+
+
+    //true if the json contains either of the output keywords, used to catch transform inputs being sent to extract.
+    pub fn has_output_keywords(json: &Value) -> Result<bool, VideoError> {
+        let batch_out = !search_json(json, BATCH_OUTPUT)?.is_empty();
+        let single_out = !search_json(json, SINGLE_OUTPUT)?.is_empty();
+        Ok(batch_out || single_out)
+    }
+
+
     pub fn create_transform(json: &Value) -> Result<AdvancedInput, VideoError> {
-        let mut batch_in_path = VecDeque::new();
-        let mut batch_out_path = VecDeque::new();
-        let mut single_in_path = VecDeque::new();
-        let mut single_out_path = VecDeque::new();
-        let (batch_in, batch_in_iter) = search_json(json, &mut batch_in_path, BATCH_INPUT)?;
-        let (batch_out, batch_out_iter) = search_json(json, &mut batch_out_path, BATCH_OUTPUT)?;
-        let (single_in, single_in_iter) = search_json(json, &mut single_in_path, SINGLE_INPUT)?;
-        let (single_out, single_out_iter) = search_json(json, &mut single_out_path, SINGLE_OUTPUT)?;
+        let batch_in_paths = search_json(json, BATCH_INPUT)?;
+        let batch_out_paths = search_json(json, BATCH_OUTPUT)?;
+        let single_in_paths = search_json(json, SINGLE_INPUT)?;
+        let single_out_paths = search_json(json, SINGLE_OUTPUT)?;
+        let (batch_in, batch_out) = (!batch_in_paths.is_empty(), !batch_out_paths.is_empty());
+        let (single_in, single_out) = (!single_in_paths.is_empty(), !single_out_paths.is_empty());
         if batch_in && batch_out {
             println!("json parsed as batch input.");
-            Ok(AdvancedInput::new("batch".to_string(), batch_in_path, batch_in_iter,
-                                  batch_out_path, batch_out_iter, json.clone()))
+            AdvancedInput::new("batch".to_string(), batch_in_paths, batch_out_paths, json.clone()).check_variables()
         } else if batch_in || batch_out {
             Err(String::from("json parsing error:\nif batch selected both $BATCH_INPUT and $BATCH_OUTPUT must be defined.").into())
         } else if single_in && single_out {
             println!("json parsed as single input.");
-            Ok(AdvancedInput::new("single".to_string(), single_in_path, single_in_iter,
-                                  single_out_path, single_out_iter, json.clone()))
+            AdvancedInput::new("single".to_string(), single_in_paths, single_out_paths, json.clone()).check_variables()
         } else if single_in || single_out {
             Err(String::from("json parsing error:\nif single selected both $SINGLE_INPUT and $SINGLE_OUTPUT must be defined.").into())
         } else {
@@ -104,4 +164,21 @@ impl AdvancedInput {
     }
 
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use either::Right;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn every_keyword_is_replaced() {
+        let template = AdvancedInput::create_transform(&json!({"inputs": [{"image": "$SINGLE_INPUT", "mask": "$SINGLE_INPUT"}],
+                                                               "save/to": ["$SINGLE_OUTPUT", {"copy": "$SINGLE_OUTPUT"}]})).unwrap();
+        assert_eq!(template.in_paths(), &vec!["/inputs/0/image".to_string(), "/inputs/0/mask".to_string()]);
+        assert_eq!(template.out_paths(), &vec!["/save~1to/0".to_string(), "/save~1to/1/copy".to_string()]);
+        let json = template.replace_variables_transform(Right("in.png"), Right("out.png"), &BTreeMap::new()).unwrap();
+        assert_eq!(json, json!({"inputs": [{"image": "in.png", "mask": "in.png"}], "save/to": ["out.png", {"copy": "out.png"}]}));
+    }
+}
@@ -0,0 +1,212 @@
+use common::video_error::VideoError;
+use common::structs::segments::TimeRange;
+use serde_json::Value;
+use std::path::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioSource {
+    //the original video's audio streams.
+    Original,
+    //no audio at all.
+    Dropped,
+    //the first audio stream of another file, by uri.
+    Replaced(String),
+}
+
+//which audio ends up in the output video & how it's encoded, the audio is always cut to the length of the output video,
+//& to the same spans of the original as the video when only parts of it were processed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioTrack {
+    source: AudioSource,
+    codec: Option<String>,
+    bitrate: Option<String>,
+    //where a replacement was downloaded to.
+    local_file: Option<PathBuf>,
+    //the spans of the source audio joined into the output, in order. None keeps all of it.
+    ranges: Option<Vec<TimeRange>>,
+}
+
+impl AudioTrack {
+    //accepts "keep", "drop" or an object like {"mode": "replace", "uri": "data://.my/dub.m4a", "codec": "aac", "bitrate": "192k"}, defaults to keep.
+    //"encode" keeps the original audio but re-encodes it, every mode but drop takes a codec & bitrate.
+    pub fn from_request(json: Option<&Value>, output_file: &str) -> Result<AudioTrack, VideoError> {
+        let track = match json {
+            None => AudioTrack::new(AudioSource::Original, None, None),
+            Some(&Value::String(ref mode)) => AudioTrack::from_parts(mode, None, None, None)?,
+            Some(&Value::Object(ref object)) => {
+                let mode = object.get("mode").and_then(|mode| mode.as_str())
+                    .ok_or(format!("early exit:\naudio must have a 'mode' of 'keep', 'drop', 'replace' or 'encode'."))?;
+                let field = |name: &str| -> Result<Option<String>, VideoError> {
+                    match object.get(name) {
+                        Some(&Value::String(ref value)) => Ok(Some(value.clone())),
+                        Some(&Value::Number(ref value)) if name == "bitrate" => Ok(Some(value.to_string())),
+                        Some(other) => Err(format!("early exit:\naudio {} must be a string, got {}.", name, other).into()),
+                        None => Ok(None)
+                    }
+                };
+                AudioTrack::from_parts(mode, field("uri")?, field("codec")?, field("bitrate")?)?
+            }
+            Some(_) => return Err(format!("early exit:\naudio must be a string or an object.").into())
+        };
+        let extension = output_file.rsplit('.').next().unwrap_or("").to_lowercase();
+        if let Some(ref codec) = track.codec {
+            if !containers(codec).contains(&extension.as_str()) {
+                return Err(format!("early exit:\n{} audio can't be stored in a '.{}' file, use one of: {}.", codec, extension, containers(codec).join(", ")).into())
+            }
+        }
+        //webm only holds opus & vorbis, which few sources are already in.
+        if track.codec.is_none() && extension == "webm" && track.source != AudioSource::Dropped {
+            return Ok(AudioTrack { codec: Some("opus".to_string()), ..track })
+        }
+        Ok(track)
+    }
+
+    fn from_parts(mode: &str, uri: Option<String>, codec: Option<String>, bitrate: Option<String>) -> Result<AudioTrack, VideoError> {
+        if let Some(ref codec) = codec {
+            if encoder(codec).is_none() {
+                return Err(format!("early exit:\nunknown audio codec '{}', must be one of: aac, mp3, opus, vorbis, flac, pcm.", codec).into())
+            }
+        }
+        if let Some(ref bitrate) = bitrate {
+            if codec.is_none() {
+                return Err(format!("early exit:\naudio bitrate needs a codec to re-encode with.").into())
+            }
+            let digits = bitrate.trim_right_matches(|c: char| c == 'k' || c == 'K');
+            if digits.is_empty() || digits.parse::<u64>().is_err() {
+                return Err(format!("early exit:\naudio bitrate must be bits per second or a string like '192k', got '{}'.", bitrate).into())
+            }
+        }
+        match (mode, uri) {
+            ("keep", None) => Ok(AudioTrack::new(AudioSource::Original, codec, bitrate)),
+            ("encode", None) if codec.is_some() => Ok(AudioTrack::new(AudioSource::Original, codec, bitrate)),
+            ("encode", None) => Err(format!("early exit:\naudio mode 'encode' needs a codec.").into()),
+            ("drop", None) if codec.is_none() => Ok(AudioTrack::new(AudioSource::Dropped, None, None)),
+            ("drop", None) => Err(format!("early exit:\ndropped audio can't have a codec.").into()),
+            ("replace", Some(uri)) => Ok(AudioTrack::new(AudioSource::Replaced(uri), codec, bitrate)),
+            ("replace", None) => Err(format!("early exit:\naudio mode 'replace' needs the 'uri' of the new audio.").into()),
+            ("keep", Some(_)) | ("encode", Some(_)) | ("drop", Some(_)) => Err(format!("early exit:\nonly audio mode 'replace' takes a uri.").into()),
+            (other, _) => Err(format!("early exit:\nunknown audio mode '{}', must be 'keep', 'drop', 'replace' or 'encode'.", other).into())
+        }
+    }
+
+    fn new(source: AudioSource, codec: Option<String>, bitrate: Option<String>) -> AudioTrack {
+        AudioTrack { source: source, codec: codec, bitrate: bitrate, local_file: None, ranges: None }
+    }
+
+    pub fn source(&self) -> &AudioSource {&self.source}
+    pub fn local_file(&self) -> Option<&Path> {self.local_file.as_ref().map(|file| file.as_path())}
+    pub fn codec(&self) -> Option<&str> {self.codec.as_ref().map(|codec| codec.as_str())}
+    pub fn ranges(&self) -> Option<&Vec<TimeRange>> {self.ranges.as_ref()}
+
+    //the same track, with its replacement audio downloaded to local_file.
+    pub fn with_local_file(self, local_file: PathBuf) -> AudioTrack {
+        AudioTrack { local_file: Some(local_file), ..self }
+    }
+
+    //the same track, cut to ranges of its source.
+    pub fn trimmed_to(self, ranges: Vec<TimeRange>) -> AudioTrack {
+        AudioTrack { ranges: Some(ranges), ..self }
+    }
+
+    //picks the audio streams, the original video is input 1 & a replacement input 2.
+    //trimmed audio is cut & joined with a filter, original_streams is how many audio streams the original has.
+    pub fn map_args(&self, original_streams: usize) -> Vec<String> {
+        if let Some(ref ranges) = self.ranges {
+            let inputs: Vec<String> = match self.source {
+                AudioSource::Original => (0..original_streams).map(|stream| format!("1:a:{}", stream)).collect(),
+                AudioSource::Replaced(_) => vec!["2:a:0".to_string()],
+                AudioSource::Dropped => Vec::new()
+            };
+            if inputs.is_empty() || ranges.is_empty() {
+                return Vec::new()
+            }
+            let mut graph: Vec<String> = Vec::new();
+            let mut args: Vec<String> = Vec::new();
+            for (stream, input) in inputs.iter().enumerate() {
+                let copies: String = (0..ranges.len()).map(|index| format!("[s{}_{}]", stream, index)).collect();
+                graph.push(format!("[{}]asplit={}{}", input, ranges.len(), copies));
+                let mut cuts = String::new();
+                for (index, range) in ranges.iter().enumerate() {
+                    graph.push(format!("[s{}_{}]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}_{}]", stream, index, range.start(), range.end(), stream, index));
+                    cuts.push_str(&format!("[a{}_{}]", stream, index));
+                }
+                graph.push(format!("{}concat=n={}:v=0:a=1[a{}]", cuts, ranges.len(), stream));
+                args.push("-map".to_string());
+                args.push(format!("[a{}]", stream));
+            }
+            let mut filter = vec!["-filter_complex".to_string(), graph.join(";")];
+            filter.extend(args);
+            return filter
+        }
+        match self.source {
+            AudioSource::Original => vec!["-map".to_string(), "1:a?".to_string()],
+            AudioSource::Replaced(_) => vec!["-map".to_string(), "2:a:0".to_string()],
+            AudioSource::Dropped => Vec::new()
+        }
+    }
+
+    //has to come after any -c that would otherwise copy the audio.
+    pub fn codec_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        if let Some(ref codec) = self.codec {
+            args.push("-c:a".to_string());
+            args.push(encoder(codec).unwrap().to_string());
+            if let Some(ref bitrate) = self.bitrate {
+                args.push("-b:a".to_string());
+                args.push(bitrate.clone());
+            }
+        }
+        args
+    }
+}
+
+fn encoder(codec: &str) -> Option<&'static str> {
+    match codec {
+        "aac" => Some("aac"),
+        "mp3" => Some("libmp3lame"),
+        "opus" => Some("libopus"),
+        "vorbis" => Some("libvorbis"),
+        "flac" => Some("flac"),
+        "pcm" => Some("pcm_s16le"),
+        _ => None
+    }
+}
+
+fn containers(codec: &str) -> &'static [&'static str] {
+    match codec {
+        "aac" => &["mp4", "m4v", "mkv", "mov", "avi"],
+        "mp3" => &["mp4", "m4v", "mkv", "mov", "avi"],
+        "opus" => &["webm", "mkv", "mp4"],
+        "vorbis" => &["webm", "mkv"],
+        "flac" => &["mkv", "mp4"],
+        "pcm" => &["mkv", "mov", "avi"],
+        _ => &[]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn audio_modes() {
+        let replaced = AudioTrack::from_request(Some(&json!({"mode": "replace", "uri": "data://.my/dub.m4a", "codec": "aac", "bitrate": "192k"})), "out.mp4").unwrap();
+        assert_eq!(replaced.map_args(0), vec!["-map", "2:a:0"]);
+        assert_eq!(replaced.codec_args(), vec!["-c:a", "aac", "-b:a", "192k"]);
+        assert!(AudioTrack::from_request(Some(&json!("drop")), "out.mp4").unwrap().map_args(0).is_empty());
+        assert_eq!(AudioTrack::from_request(None, "out.webm").unwrap().codec_args(), vec!["-c:a", "libopus"]);
+        assert!(AudioTrack::from_request(Some(&json!({"mode": "encode", "codec": "vorbis"})), "out.mp4").is_err());
+        assert!(AudioTrack::from_request(Some(&json!({"mode": "replace"})), "out.mp4").is_err());
+    }
+
+    #[test]
+    fn trimmed_audio_follows_the_ranges() {
+        let track = AudioTrack::from_request(None, "out.mp4").unwrap().trimmed_to(vec![TimeRange::new(0f64, 1.5), TimeRange::new(1.5, 2f64)]);
+        assert_eq!(track.map_args(1), vec!["-filter_complex",
+                                           "[1:a:0]asplit=2[s0_0][s0_1];[s0_0]atrim=start=0:end=1.5,asetpts=PTS-STARTPTS[a0_0];\
+                                            [s0_1]atrim=start=1.5:end=2,asetpts=PTS-STARTPTS[a0_1];[a0_0][a0_1]concat=n=2:v=0:a=1[a0]",
+                                           "-map", "[a0]"]);
+        assert_eq!(track.map_args(0), Vec::<String>::new());
+        assert!(AudioTrack::from_request(Some(&json!("drop")), "out.mp4").unwrap().trimmed_to(vec![TimeRange::new(0f64, 1f64)]).map_args(2).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use common::video_error::VideoError;
+use common::structs::ffmpeg::FFMpeg;
+use common::file_mgmt::{from_regex, file_hash};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::*;
+
+static DEFAULT_TOLERANCE: u32 = 4;
+
+//how identical frames are found, only one frame of each group is processed and its result is used for the rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dedup {
+    //frames whose files are byte for byte the same.
+    Exact,
+    //frames whose 64 bit average hashes differ in at most this many bits, so compression noise doesn't split a static scene up.
+    Perceptual(u32),
+}
+
+impl Dedup {
+    //accepts true, "exact", "perceptual" or an object like {"mode": "perceptual", "tolerance": 4}, None when it's missing or false.
+    pub fn from_json(json: Option<&Value>) -> Result<Option<Dedup>, VideoError> {
+        match json {
+            None | Some(&Value::Bool(false)) | Some(&Value::Null) => Ok(None),
+            Some(&Value::Bool(true)) => Ok(Some(Dedup::Exact)),
+            Some(&Value::String(ref mode)) => Dedup::from_parts(mode, None).map(Some),
+            Some(&Value::Object(ref object)) => {
+                let mode = object.get("mode").and_then(|mode| mode.as_str())
+                    .ok_or(format!("early exit:\ndedup must have a 'mode' of 'exact' or 'perceptual'."))?;
+                let tolerance = match object.get("tolerance") {
+                    Some(tolerance) => Some(tolerance.as_u64().ok_or(format!("early exit:\ndedup tolerance must be a whole number of bits."))?),
+                    None => None
+                };
+                Dedup::from_parts(mode, tolerance).map(Some)
+            }
+            Some(_) => Err(format!("early exit:\ndedup must be true, a string or an object.").into())
+        }
+    }
+
+    fn from_parts(mode: &str, tolerance: Option<u64>) -> Result<Dedup, VideoError> {
+        match mode {
+            "exact" if tolerance.is_none() => Ok(Dedup::Exact),
+            "exact" => Err(format!("early exit:\nexact dedup only groups identical frames, it doesn't take a tolerance.").into()),
+            "perceptual" => {
+                let tolerance = tolerance.unwrap_or(DEFAULT_TOLERANCE as u64);
+                if tolerance < 64 {
+                    Ok(Dedup::Perceptual(tolerance as u32))
+                } else {
+                    Err(format!("early exit:\ndedup tolerance must be less than 64 bits, got {}.", tolerance).into())
+                }
+            }
+            other => Err(format!("early exit:\nunknown dedup mode '{}', must be 'exact' or 'perceptual'.", other).into())
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            &Dedup::Exact => "exact".to_string(),
+            &Dedup::Perceptual(tolerance) => format!("perceptual({})", tolerance)
+        }
+    }
+
+    //maps every duplicate frame to the first frame it duplicates, frames in skip are left out of grouping entirely.
+    pub fn duplicates(&self, ffmpeg: &FFMpeg, frames_dir: &Path, regex: &str, number_of_frames: usize, skip: &Vec<usize>) -> Result<BTreeMap<usize, usize>, VideoError> {
+        let frames: Vec<usize> = (1..number_of_frames + 1).filter(|frame| !skip.contains(frame)).collect();
+        match self {
+            &Dedup::Exact => {
+                let mut hashes: Vec<(usize, String)> = Vec::new();
+                for frame in frames {
+                    hashes.push((frame, file_hash(&frames_dir.join(from_regex(regex, frame)?))?));
+                }
+                Ok(group_exact(&hashes))
+            }
+            &Dedup::Perceptual(tolerance) => {
+                let thumbnails = ffmpeg.thumbnails(frames_dir, regex, number_of_frames)?;
+                let hashes: Vec<(usize, u64)> = frames.into_iter().map(|frame| (frame, average_hash(&thumbnails[frame - 1]))).collect();
+                Ok(group_perceptual(&hashes, tolerance))
+            }
+        }
+    }
+}
+
+//a bit per pixel of the thumbnail, set when the pixel is brighter than the thumbnail's mean.
+fn average_hash(thumbnail: &Vec<u8>) -> u64 {
+    let mean = thumbnail.iter().map(|pixel| *pixel as u64).sum::<u64>() / thumbnail.len().max(1) as u64;
+    thumbnail.iter().enumerate().fold(0u64, |hash, (index, pixel)| if *pixel as u64 > mean { hash | (1u64 << index) } else { hash })
+}
+
+fn group_exact(hashes: &Vec<(usize, String)>) -> BTreeMap<usize, usize> {
+    let mut representatives: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut duplicates = BTreeMap::new();
+    for &(frame, ref hash) in hashes {
+        let existing = representatives.get(hash.as_str()).cloned();
+        match existing {
+            Some(representative) => { duplicates.insert(frame, representative); }
+            None => { representatives.insert(hash.as_str(), frame); }
+        }
+    }
+    duplicates
+}
+
+//each frame joins the run before it when it's within tolerance of the run's first frame, comparing against that representative keeps a run from drifting.
+//only consecutive frames are grouped, frames that merely look alike elsewhere in the video, like two dark scenes, start runs of their own.
+fn group_perceptual(hashes: &Vec<(usize, u64)>, tolerance: u32) -> BTreeMap<usize, usize> {
+    let mut run: Option<(usize, u64)> = None;
+    let mut previous_frame: Option<usize> = None;
+    let mut duplicates = BTreeMap::new();
+    for &(frame, hash) in hashes {
+        let follows = previous_frame.map(|previous| previous + 1 == frame).unwrap_or(false);
+        match run {
+            Some((representative, representative_hash)) if follows && (hash ^ representative_hash).count_ones() <= tolerance => {
+                duplicates.insert(frame, representative);
+            }
+            _ => run = Some((frame, hash))
+        }
+        previous_frame = Some(frame);
+    }
+    duplicates
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_group_under_their_first_occurrence() {
+        let exact = vec![(1, "a".to_string()), (2, "a".to_string()), (3, "b".to_string()), (5, "a".to_string())];
+        assert_eq!(group_exact(&exact).into_iter().collect::<Vec<(usize, usize)>>(), vec![(2, 1), (5, 1)]);
+        //5 looks like 1 but isn't next to it, & 8 follows 5 in the list but frames were skipped in between.
+        let perceptual = vec![(1, 0b0000u64), (2, 0b0011), (3, 0b1111), (4, 0b1110), (5, 0b0001), (8, 0b0001)];
+        assert_eq!(group_perceptual(&perceptual, 2).into_iter().collect::<Vec<(usize, usize)>>(), vec![(2, 1), (4, 3)]);
+        assert_eq!(average_hash(&vec![0, 255, 0, 255]), 0b1010);
+        assert!(Dedup::from_json(Some(&json!({"mode": "exact", "tolerance": 2}))).is_err());
+        assert_eq!(Dedup::from_json(Some(&json!("perceptual"))).unwrap(), Some(Dedup::Perceptual(4)));
+    }
+}
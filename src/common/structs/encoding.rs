@@ -0,0 +1,336 @@
+use common::video_error::VideoError;
+use common::structs::media_info::StreamInfo;
+use serde_json::Value;
+
+static X26X_PRESETS: &'static [&'static str] = &["ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow"];
+//used with video_compression, which predates output_encoding.
+static DEFAULT_PRESET: &'static str = "veryfast";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    H264,
+    H265,
+    Vp9,
+    Av1,
+    ProRes,
+    Ffv1,
+}
+
+impl Codec {
+    pub fn from_name(name: &str) -> Result<Codec, VideoError> {
+        match name.trim().to_lowercase().as_str() {
+            "h264" | "avc" | "x264" => Ok(Codec::H264),
+            "h265" | "hevc" | "x265" => Ok(Codec::H265),
+            "vp9" => Ok(Codec::Vp9),
+            "av1" => Ok(Codec::Av1),
+            "prores" => Ok(Codec::ProRes),
+            "ffv1" => Ok(Codec::Ffv1),
+            _ => Err(format!("early exit:\nunknown codec '{}', must be one of: h264, h265, vp9, av1, prores, ffv1.", name).into())
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Codec::H264 => "h264",
+            Codec::H265 => "h265",
+            Codec::Vp9 => "vp9",
+            Codec::Av1 => "av1",
+            Codec::ProRes => "prores",
+            Codec::Ffv1 => "ffv1",
+        }
+    }
+
+    fn encoder(&self) -> &'static str {
+        match *self {
+            Codec::H264 => "libx264",
+            Codec::H265 => "libx265",
+            Codec::Vp9 => "libvpx-vp9",
+            Codec::Av1 => "libaom-av1",
+            Codec::ProRes => "prores_ks",
+            Codec::Ffv1 => "ffv1",
+        }
+    }
+
+    fn default_pixel_format(&self) -> &'static str {
+        match *self {
+            Codec::ProRes => "yuv422p10le",
+            //frames are rgb, so this keeps ffv1 lossless.
+            Codec::Ffv1 => "bgr0",
+            _ => "yuv420p"
+        }
+    }
+
+    //the highest crf the encoder accepts, None if it has no crf.
+    fn max_crf(&self) -> Option<u64> {
+        match *self {
+            Codec::H264 | Codec::H265 => Some(51),
+            Codec::Vp9 | Codec::Av1 => Some(63),
+            Codec::ProRes | Codec::Ffv1 => None
+        }
+    }
+
+    fn containers(&self) -> &'static [&'static str] {
+        match *self {
+            Codec::H264 => &["mp4", "mkv", "mov", "avi"],
+            Codec::H265 => &["mp4", "mkv", "mov"],
+            Codec::Vp9 => &["webm", "mkv", "mp4"],
+            Codec::Av1 => &["mp4", "mkv", "webm"],
+            Codec::ProRes => &["mov", "mkv"],
+            Codec::Ffv1 => &["mkv", "avi"],
+        }
+    }
+}
+
+//how transformed frames are encoded into the output video, checked against the output file's extension before any frames are processed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputEncoding {
+    codec: Codec,
+    pixel_format: String,
+    preset: Option<String>,
+    crf: Option<u64>,
+    bitrate: Option<String>,
+    gop: Option<u64>,
+    container: String,
+    //only set when the output is joined with untouched parts of the original, so it encodes the way they were.
+    profile: Option<String>,
+    size: Option<(u64, u64)>,
+    timescale: Option<u64>,
+}
+
+impl OutputEncoding {
+    //output_encoding looks like {"codec": "h265", "pixel_format": "yuv420p10le", "preset": "slow", "crf": 24, "gop": 48, "container": "mkv"}, every field is optional.
+    //video_compression (0-100) is scaled onto the codec's crf range when output_encoding doesn't set a crf or bitrate.
+    pub fn from_request(json: Option<&Value>, video_compression: Option<u64>, output_file: &str) -> Result<OutputEncoding, VideoError> {
+        let empty = json!({});
+        let json = json.unwrap_or(&empty);
+        if !json.is_object() {
+            return Err(format!("early exit:\noutput_encoding must be a json object.").into())
+        }
+        let codec = match json.get("codec") {
+            Some(codec) => Codec::from_name(codec.as_str().ok_or(format!("early exit:\noutput_encoding codec must be a string."))?)?,
+            None => Codec::H264
+        };
+        let extension = output_file.rsplit('.').next().unwrap_or("").to_lowercase();
+        let container = container_for(&extension)
+            .ok_or(format!("early exit:\ncan't write video to a '.{}' file, use one of: mp4, mkv, webm, mov, avi.", extension))?;
+        if let Some(requested) = json.get("container") {
+            let requested = requested.as_str().ok_or(format!("early exit:\noutput_encoding container must be a string."))?;
+            if container_for(&requested.to_lowercase()) != Some(container) {
+                return Err(format!("early exit:\noutput_encoding container '{}' doesn't match the output file extension '.{}'.", requested, extension).into())
+            }
+        }
+        if !codec.containers().contains(&container) {
+            return Err(format!("early exit:\n{} can't be stored in a {} file, use one of: {}.", codec.name(), container, codec.containers().join(", ")).into())
+        }
+        let pixel_format = match json.get("pixel_format") {
+            Some(format) => format.as_str().ok_or(format!("early exit:\noutput_encoding pixel_format must be a string."))?.to_string(),
+            None => codec.default_pixel_format().to_string()
+        };
+        let preset = match json.get("preset") {
+            Some(preset) => {
+                let preset = preset.as_str().ok_or(format!("early exit:\noutput_encoding preset must be a string."))?;
+                if codec != Codec::H264 && codec != Codec::H265 {
+                    return Err(format!("early exit:\npresets are only supported for h264 & h265, not {}.", codec.name()).into())
+                }
+                if !X26X_PRESETS.contains(&preset) {
+                    return Err(format!("early exit:\nunknown preset '{}', must be one of: {}.", preset, X26X_PRESETS.join(", ")).into())
+                }
+                Some(preset.to_string())
+            }
+            None => None
+        };
+        let bitrate = match json.get("bitrate") {
+            Some(&Value::Number(ref bitrate)) => Some(bitrate.to_string()),
+            Some(&Value::String(ref bitrate)) if valid_bitrate(bitrate) => Some(bitrate.clone()),
+            Some(other) => return Err(format!("early exit:\noutput_encoding bitrate must be bits per second or a string like '5M', got {}.", other).into()),
+            None => None
+        };
+        let crf = match json.get("crf") {
+            Some(crf) => {
+                let crf = crf.as_u64().ok_or(format!("early exit:\noutput_encoding crf must be a whole number."))?;
+                match codec.max_crf() {
+                    Some(max) if crf <= max => Some(crf),
+                    Some(max) => return Err(format!("early exit:\ncrf for {} must be between 0 and {}, got {}.", codec.name(), max, crf).into()),
+                    None => return Err(format!("early exit:\n{} doesn't use a crf.", codec.name()).into())
+                }
+            }
+            None => None
+        };
+        if crf.is_some() && bitrate.is_some() {
+            return Err(format!("early exit:\nuse either crf or bitrate in output_encoding, not both.").into())
+        }
+        let crf = match (crf, bitrate.is_some(), video_compression) {
+            (None, false, Some(compression)) => {
+                if compression > 100 {
+                    return Err(format!("early exit:\nvideo_compression must be between 0 and 100, got {}.", compression).into())
+                }
+                match codec.max_crf() {
+                    Some(max) => Some(((compression * max) as f64 / 100f64).round() as u64),
+                    None => return Err(format!("early exit:\nvideo_compression can't be used with {}.", codec.name()).into())
+                }
+            }
+            (crf, _, _) => crf
+        };
+        let preset = match (preset, video_compression, codec) {
+            (None, Some(_), Codec::H264) => Some(DEFAULT_PRESET.to_string()),
+            (preset, _, _) => preset
+        };
+        let gop = match json.get("gop") {
+            Some(gop) => match gop.as_u64() {
+                Some(gop) if gop > 0 => Some(gop),
+                _ => return Err(format!("early exit:\noutput_encoding gop must be a positive whole number.").into())
+            },
+            None => None
+        };
+        Ok(OutputEncoding { codec: codec, pixel_format: pixel_format, preset: preset, crf: crf, bitrate: bitrate, gop: gop, container: container.to_string(),
+            profile: None, size: None, timescale: None })
+    }
+
+    pub fn codec(&self) -> Codec {self.codec}
+    pub fn container(&self) -> &str {&self.container}
+    pub fn pixel_format(&self) -> &str {&self.pixel_format}
+    pub fn size(&self) -> Option<(u64, u64)> {self.size}
+
+    //the same encoding at the original's stored size & timescale, so parts encoded with it all share them with each other.
+    pub fn spliced(&self, original: &StreamInfo) -> OutputEncoding {
+        let mut spliced = self.clone();
+        spliced.size = match (original.width(), original.height()) {
+            (Some(width), Some(height)) => Some((width, height)),
+            _ => None
+        };
+        //only mp4 & mov let the track's timescale be picked, mkv & webm always count in milliseconds.
+        if self.container == "mp4" || self.container == "mov" {
+            spliced.timescale = original.time_base().and_then(|base| base.splitn(2, '/').nth(1)).and_then(|scale| scale.parse::<u64>().ok());
+        }
+        spliced
+    }
+
+    //an encoding whose output can be joined with a stream copy of original, None if the codec differs or the original's profile can't be encoded.
+    pub fn matching(&self, original: &StreamInfo) -> Option<OutputEncoding> {
+        match original.codec().and_then(|codec| Codec::from_name(codec).ok()) {
+            Some(codec) if codec == self.codec => {}
+            _ => return None
+        }
+        let pixel_format = match original.pixel_format() {
+            Some(format) => format.to_string(),
+            None => return None
+        };
+        let profile = match original.profile() {
+            Some(profile) => match encoder_profile(self.codec, profile) {
+                Some(profile) => Some(profile.to_string()),
+                None => return None
+            },
+            None => None
+        };
+        let mut matching = self.spliced(original);
+        matching.pixel_format = pixel_format;
+        matching.profile = profile;
+        Some(matching)
+    }
+
+    //the ffmpeg output options for encoding the video stream.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-c:v".to_string(), self.codec.encoder().to_string(),
+                                         "-pix_fmt".to_string(), self.pixel_format.clone()];
+        if let Some(ref preset) = self.preset {
+            args.push("-preset".to_string());
+            args.push(preset.clone());
+        }
+        if let Some(crf) = self.crf {
+            args.push("-crf".to_string());
+            args.push(crf.to_string());
+            //vp9 & av1 are only constant quality without a target bitrate.
+            if self.codec == Codec::Vp9 || self.codec == Codec::Av1 {
+                args.push("-b:v".to_string());
+                args.push("0".to_string());
+            }
+        }
+        if let Some(ref bitrate) = self.bitrate {
+            args.push("-b:v".to_string());
+            args.push(bitrate.clone());
+        }
+        if let Some(gop) = self.gop {
+            args.push("-g".to_string());
+            args.push(gop.to_string());
+        }
+        if let Some(ref profile) = self.profile {
+            args.push("-profile:v".to_string());
+            args.push(profile.clone());
+        }
+        if let Some(timescale) = self.timescale {
+            args.push("-video_track_timescale".to_string());
+            args.push(timescale.to_string());
+        }
+        args
+    }
+}
+
+//ffprobe's name for a profile, as the codec's encoder takes it.
+fn encoder_profile(codec: Codec, profile: &str) -> Option<&'static str> {
+    match (codec, profile) {
+        (Codec::H264, "Baseline") | (Codec::H264, "Constrained Baseline") => Some("baseline"),
+        (Codec::H264, "Main") => Some("main"),
+        (Codec::H264, "High") => Some("high"),
+        (Codec::H264, "High 10") => Some("high10"),
+        (Codec::H264, "High 4:2:2") => Some("high422"),
+        (Codec::H264, "High 4:4:4 Predictive") => Some("high444"),
+        (Codec::H265, "Main") => Some("main"),
+        (Codec::H265, "Main 10") => Some("main10"),
+        (Codec::Vp9, "Profile 0") => Some("0"),
+        (Codec::Vp9, "Profile 1") => Some("1"),
+        (Codec::Vp9, "Profile 2") => Some("2"),
+        (Codec::Vp9, "Profile 3") => Some("3"),
+        _ => None
+    }
+}
+
+fn container_for(extension: &str) -> Option<&'static str> {
+    match extension {
+        "mp4" | "m4v" => Some("mp4"),
+        "mkv" | "matroska" => Some("mkv"),
+        "webm" => Some("webm"),
+        "mov" | "qt" => Some("mov"),
+        "avi" => Some("avi"),
+        _ => None
+    }
+}
+
+fn valid_bitrate(bitrate: &str) -> bool {
+    let digits = bitrate.trim_right_matches(|c: char| c == 'k' || c == 'K' || c == 'm' || c == 'M');
+    !digits.is_empty() && bitrate.len() - digits.len() <= 1 && digits.parse::<f64>().map(|rate| rate > 0f64).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn video_compression_scales_to_crf() {
+        let encoding = OutputEncoding::from_request(None, Some(100), "data://.my/out.mp4").unwrap();
+        assert_eq!(encoding.ffmpeg_args(), vec!["-c:v", "libx264", "-pix_fmt", "yuv420p", "-preset", "veryfast", "-crf", "51"]);
+        let vp9 = OutputEncoding::from_request(Some(&json!({"codec": "vp9"})), Some(50), "out.webm").unwrap();
+        assert_eq!(vp9.ffmpeg_args(), vec!["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p", "-crf", "32", "-b:v", "0"]);
+    }
+
+    #[test]
+    fn containers_are_checked() {
+        assert!(OutputEncoding::from_request(Some(&json!({"codec": "prores"})), None, "out.mp4").is_err());
+        assert!(OutputEncoding::from_request(Some(&json!({"container": "mkv"})), None, "out.mp4").is_err());
+        assert!(OutputEncoding::from_request(Some(&json!({"codec": "ffv1", "container": "matroska"})), None, "out.mkv").is_ok());
+        assert!(OutputEncoding::from_request(Some(&json!({"codec": "vp9", "preset": "slow"})), None, "out.webm").is_err());
+    }
+
+    #[test]
+    fn splices_match_the_original() {
+        let encoding = OutputEncoding::from_request(Some(&json!({"crf": 20})), None, "out.mp4").unwrap();
+        let original = StreamInfo::from_json(&json!({"codec_type": "video", "codec_name": "h264", "profile": "High 4:4:4 Predictive",
+                                                      "pix_fmt": "yuv444p", "time_base": "1/12800", "width": 320, "height": 240}));
+        let matching = encoding.matching(&original).unwrap();
+        assert_eq!(matching.size(), Some((320, 240)));
+        assert_eq!(matching.ffmpeg_args(), vec!["-c:v", "libx264", "-pix_fmt", "yuv444p", "-crf", "20",
+                                                "-profile:v", "high444", "-video_track_timescale", "12800"]);
+        let hevc = StreamInfo::from_json(&json!({"codec_type": "video", "codec_name": "hevc", "profile": "Main", "pix_fmt": "yuv420p"}));
+        assert!(encoding.matching(&hevc).is_none());
+        assert_eq!(encoding.spliced(&hevc).pixel_format(), "yuv420p");
+    }
+}
@@ -0,0 +1,208 @@
+use common::video_error::VideoError;
+use common::structs::segments::TimeRange;
+use common::structs::extract_format::timestamps;
+use serde_json::{Map, Value};
+
+//frames this close together are treated as back to back.
+static EPSILON: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trigger {
+    //an event starts once the value goes above the threshold.
+    Above(f64),
+    //an event starts once the value goes below the threshold.
+    Below(f64),
+}
+
+//one kind of event, found by thresholding a value in every frame's result.
+#[derive(Debug, Clone, PartialEq)]
+struct EventRule {
+    label: String,
+    //json pointer into each frame's data.
+    field: String,
+    trigger: Trigger,
+    //an event lasts until the value crosses back past this, which defaults to the trigger's threshold.
+    release: f64,
+    //shorter events are dropped.
+    min_duration: f64,
+}
+
+//turns per-frame extract results into labelled time segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRules {
+    rules: Vec<EventRule>,
+    //whether frame_data is kept next to the events.
+    keep_frames: bool,
+}
+
+impl EventRules {
+    //accepts an array of rules or an object like {"rules": [...], "keep_frames": false}, a rule looks like
+    //{"label": "nudity", "field": "/nude", "above": 0.8, "release": 0.6, "min_duration": 2}.
+    pub fn from_request(json: Option<&Value>) -> Result<Option<EventRules>, VideoError> {
+        let (rules, keep_frames) = match json {
+            None => return Ok(None),
+            Some(&Value::Array(ref rules)) => (rules, true),
+            Some(&Value::Object(ref object)) => {
+                let rules = object.get("rules").and_then(|rules| rules.as_array())
+                    .ok_or(format!("early exit:\nevents must have an array of 'rules'."))?;
+                let keep_frames = match object.get("keep_frames") {
+                    Some(&Value::Bool(keep_frames)) => keep_frames,
+                    Some(_) => return Err(format!("early exit:\nevents keep_frames must be true or false.").into()),
+                    None => true
+                };
+                (rules, keep_frames)
+            }
+            Some(_) => return Err(format!("early exit:\nevents must be an array of rules or an object with 'rules'.").into())
+        };
+        if rules.is_empty() {
+            return Err(format!("early exit:\nevents must have at least one rule.").into())
+        }
+        let rules = rules.iter().map(|rule| match rule.as_object() {
+            Some(rule) => EventRule::from_json(rule),
+            None => Err(format!("early exit:\nevery event rule must be an object, got {}.", rule).into())
+        }).collect::<Result<Vec<EventRule>, VideoError>>()?;
+        Ok(Some(EventRules { rules: rules, keep_frames: keep_frames }))
+    }
+
+    //adds an "events" array to extract's combined result, sorted by start time. frames last until the next one, the end of
+    //the range they were sampled from, or duration.
+    pub fn aggregate(&self, results: Value, duration: f64, ranges: Option<&Vec<TimeRange>>) -> Result<Value, VideoError> {
+        let events: Vec<Value> = {
+            let frames = results["frame_data"].as_array().ok_or(format!("extract result did not contain any frame_data."))?;
+            let times = timestamps(frames)?;
+            let ends = TimeRange::frame_ends(&times, duration, ranges);
+            let mut events: Vec<(f64, Value)> = Vec::new();
+            for rule in &self.rules {
+                events.extend(rule.events(frames, &times, &ends).into_iter().map(|event| (event["start"].as_f64().unwrap_or(0f64), event)));
+            }
+            events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            events.into_iter().map(|(_, event)| event).collect()
+        };
+        let mut combined = match results {
+            Value::Object(object) => if self.keep_frames { object } else { Map::new() },
+            _ => Map::new()
+        };
+        combined.insert("events".to_string(), Value::Array(events));
+        Ok(Value::Object(combined))
+    }
+}
+
+impl EventRule {
+    fn from_json(rule: &Map<String, Value>) -> Result<EventRule, VideoError> {
+        let label = rule.get("label").and_then(|label| label.as_str())
+            .ok_or(format!("early exit:\nevery event rule needs a 'label'."))?.to_string();
+        let field = match rule.get("field").and_then(|field| field.as_str()) {
+            Some(field) if field.is_empty() || field.starts_with('/') => field.to_string(),
+            Some(field) => format!("/{}", field),
+            None => return Err(format!("early exit:\nevent rule '{}' needs a 'field', a json pointer like '/nude'.", label).into())
+        };
+        let number = |name: &str| -> Result<Option<f64>, VideoError> {
+            match rule.get(name) {
+                Some(value) => value.as_f64().map(|value| Some(value)).ok_or(format!("early exit:\nevent rule '{}' {} must be a number.", label, name).into()),
+                None => Ok(None)
+            }
+        };
+        let trigger = match (number("above")?, number("below")?) {
+            (Some(above), None) => Trigger::Above(above),
+            (None, Some(below)) => Trigger::Below(below),
+            _ => return Err(format!("early exit:\nevent rule '{}' needs exactly one of 'above' or 'below'.", label).into())
+        };
+        let release = match (trigger, number("release")?) {
+            (Trigger::Above(above), Some(release)) if release > above => {
+                return Err(format!("early exit:\nevent rule '{}' release can't be above its 'above' threshold.", label).into())
+            }
+            (Trigger::Below(below), Some(release)) if release < below => {
+                return Err(format!("early exit:\nevent rule '{}' release can't be below its 'below' threshold.", label).into())
+            }
+            (_, Some(release)) => release,
+            (Trigger::Above(threshold), None) | (Trigger::Below(threshold), None) => threshold
+        };
+        let min_duration = number("min_duration")?.unwrap_or(0f64);
+        if min_duration < 0f64 {
+            return Err(format!("early exit:\nevent rule '{}' min_duration can't be negative.", label).into())
+        }
+        Ok(EventRule { label: label, field: field, trigger: trigger, release: release, min_duration: min_duration })
+    }
+
+    //true if value starts an event, or keeps one going when active.
+    fn passes(&self, value: f64, active: bool) -> bool {
+        match (self.trigger, active) {
+            (Trigger::Above(above), false) => value > above,
+            (Trigger::Above(_), true) => value > self.release,
+            (Trigger::Below(below), false) => value < below,
+            (Trigger::Below(_), true) => value < self.release,
+        }
+    }
+
+    //true if value is further past the threshold than peak.
+    fn more_extreme(&self, value: f64, peak: f64) -> bool {
+        match self.trigger {
+            Trigger::Above(_) => value > peak,
+            Trigger::Below(_) => value < peak,
+        }
+    }
+
+    fn events(&self, frames: &Vec<Value>, times: &Vec<f64>, ends: &Vec<f64>) -> Vec<Value> {
+        let mut events: Vec<Value> = Vec::new();
+        //start, end, peak & peak time of the event in progress.
+        let mut current: Option<(f64, f64, f64, f64)> = None;
+        for (index, frame) in frames.iter().enumerate() {
+            let value = frame["data"].pointer(&self.field).and_then(|value| value.as_f64());
+            //a gap between sampled ranges ends an event too.
+            let contiguous = current.map(|(_, end, _, _)| times[index] - end < EPSILON).unwrap_or(true);
+            current = match (current, value) {
+                (Some((start, _, peak, peak_time)), Some(value)) if contiguous && self.passes(value, true) => {
+                    if self.more_extreme(value, peak) {
+                        Some((start, ends[index], value, times[index]))
+                    } else {
+                        Some((start, ends[index], peak, peak_time))
+                    }
+                }
+                (previous, value) => {
+                    if let Some(event) = previous {
+                        self.finish(event, &mut events);
+                    }
+                    match value {
+                        Some(value) if self.passes(value, false) => Some((times[index], ends[index], value, times[index])),
+                        _ => None
+                    }
+                }
+            };
+        }
+        if let Some(event) = current {
+            self.finish(event, &mut events);
+        }
+        events
+    }
+
+    fn finish(&self, (start, end, peak, peak_time): (f64, f64, f64, f64), events: &mut Vec<Value>) {
+        if end - start + EPSILON >= self.min_duration {
+            events.push(json!({
+                "label": self.label.clone(),
+                "start": start,
+                "end": end,
+                "duration": end - start,
+                "peak": peak,
+                "peak_time": peak_time
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_use_hysteresis_and_minimum_duration() {
+        let scores = [0.1, 0.9, 0.7, 0.95, 0.5, 0.85, 0.2];
+        let frames: Vec<Value> = scores.iter().enumerate().map(|(index, score)| json!({"timestamp": index as f64, "data": {"nude": score}})).collect();
+        let rules = EventRules::from_request(Some(&json!({"rules": [{"label": "nudity", "field": "/nude", "above": 0.8, "release": 0.6, "min_duration": 2}],
+                                                          "keep_frames": false}))).unwrap().unwrap();
+        let result = rules.aggregate(json!({"frame_data": frames}), 7.0, None).unwrap();
+        assert!(result.get("frame_data").is_none());
+        //0.85 at 5s passes the threshold but only lasts a second.
+        assert_eq!(result["events"], json!([{"label": "nudity", "start": 1.0, "end": 4.0, "duration": 3.0, "peak": 0.95, "peak_time": 3.0}]));
+        assert!(EventRules::from_request(Some(&json!([{"label": "x", "field": "/x", "above": 0.5, "below": 0.2}]))).is_err());
+    }
+}
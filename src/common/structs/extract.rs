@@ -1,29 +1,31 @@
-use algorithmia::Algorithmia;
+use common::structs::processor::ImageProcessor;
+use common::structs::frame_context::FrameContext;
+use std::sync::Arc;
 use std::path::*;
 
 #[derive(Clone)]
 pub struct Extract {
-    client: Algorithmia,
+    processor: Arc<ImageProcessor>,
     input_regex: String,
     local_input_directory: PathBuf,
-    remote_working_directory: String,
+    context: Arc<FrameContext>,
 }
 
 impl Extract {
-    pub fn new(client: Algorithmia,
+    pub fn new(processor: Arc<ImageProcessor>,
                input_regex: &str,
                local_input_directory: &Path,
-               remote_working_directory: &str) -> Extract {
+               context: FrameContext) -> Extract {
         Extract {
-            client: client,
+            processor: processor,
             input_regex: String::from(input_regex),
             local_input_directory: PathBuf::from(local_input_directory),
-            remote_working_directory: String::from(remote_working_directory),
+            context: Arc::new(context),
         }
     }
 
-    pub fn client(&self) -> &Algorithmia {&self.client}
+    pub fn processor(&self) -> &ImageProcessor {self.processor.as_ref()}
     pub fn input_regex(&self) -> &str {self.input_regex.as_ref()}
     pub fn local_input(&self) -> &Path {self.local_input_directory.as_path()}
-    pub fn remote_working(&self) -> &str {self.remote_working_directory.as_ref()}
+    pub fn context(&self) -> Arc<FrameContext> {self.context.clone()}
 }
\ No newline at end of file
@@ -0,0 +1,253 @@
+use common::video_error::VideoError;
+use common::structs::segments::TimeRange;
+use serde_json::{self, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    //{"frame_data": [{timestamp, data}]}, as extract has always written it.
+    Json,
+    JsonLines,
+    Csv,
+    WebVtt,
+    Srt,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, VideoError> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "jsonl" | "json_lines" => Ok(Format::JsonLines),
+            "csv" => Ok(Format::Csv),
+            "vtt" | "webvtt" => Ok(Format::WebVtt),
+            "srt" => Ok(Format::Srt),
+            other => Err(format!("early exit:\nunknown output format '{}', must be one of: json, jsonl, csv, vtt, srt.", other).into())
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Format::Json => "json",
+            Format::JsonLines => "jsonl",
+            Format::Csv => "csv",
+            Format::WebVtt => "vtt",
+            Format::Srt => "srt",
+        }
+    }
+
+    //the format an output file's extension implies, json for anything we don't recognise.
+    fn from_extension(extension: &str) -> Format {
+        match extension {
+            "jsonl" => Format::JsonLines,
+            "csv" => Format::Csv,
+            "vtt" => Format::WebVtt,
+            "srt" => Format::Srt,
+            _ => Format::Json
+        }
+    }
+}
+
+//a run of consecutive frames whose selected values are identical.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    start: f64,
+    end: f64,
+    value: Value,
+}
+
+impl Cue {
+    pub fn start(&self) -> f64 {self.start}
+    pub fn end(&self) -> f64 {self.end}
+    pub fn value(&self) -> &Value {&self.value}
+
+    //strings are used as they are, anything else as its json.
+    pub fn text(&self) -> String {
+        match self.value {
+            Value::String(ref text) => text.clone(),
+            ref other => other.to_string()
+        }
+    }
+}
+
+//how extract results are written to the output file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractOutput {
+    format: Format,
+    //json pointer into each frame's data, the whole data when empty.
+    field: String,
+}
+
+impl ExtractOutput {
+    //accepts a format name or an object like {"format": "vtt", "field": "/tags/0/name"}, defaults to the format output_file's extension implies.
+    pub fn from_request(json: Option<&Value>, output_file: &str) -> Result<ExtractOutput, VideoError> {
+        let extension = output_file.rsplit('.').next().unwrap_or("").to_lowercase();
+        let (format, field) = match json {
+            None => (Format::from_extension(&extension), None),
+            Some(&Value::String(ref format)) => (Format::from_name(format)?, None),
+            Some(&Value::Object(ref object)) => {
+                let format = match object.get("format") {
+                    Some(&Value::String(ref format)) => Format::from_name(format)?,
+                    Some(_) => return Err(format!("early exit:\noutput_format 'format' must be a string.").into()),
+                    None => Format::from_extension(&extension)
+                };
+                let field = match object.get("field") {
+                    Some(&Value::String(ref field)) => Some(field.clone()),
+                    Some(_) => return Err(format!("early exit:\noutput_format 'field' must be a string like '/tags/0/name'.").into()),
+                    None => None
+                };
+                (format, field)
+            }
+            Some(_) => return Err(format!("early exit:\noutput_format must be a string or an object.").into())
+        };
+        let field = match field {
+            Some(_) if format == Format::Json => {
+                return Err(format!("early exit:\noutput_format 'field' only applies to the jsonl, csv, vtt and srt formats.").into())
+            }
+            Some(ref field) if !field.is_empty() && !field.starts_with('/') => format!("/{}", field),
+            Some(field) => field,
+            None => String::new()
+        };
+        Ok(ExtractOutput { format: format, field: field })
+    }
+
+    pub fn format(&self) -> Format {self.format}
+
+    //frame_data is extract's combined result, every frame lasts until the next one, or until the end of the range it was sampled from.
+    //the last frame lasts until duration.
+    pub fn cues(&self, frame_data: &Value, duration: f64, ranges: Option<&Vec<TimeRange>>) -> Result<Vec<Cue>, VideoError> {
+        let frames = frame_data["frame_data"].as_array().ok_or(format!("extract result did not contain any frame_data."))?;
+        let times = timestamps(frames)?;
+        let ends = TimeRange::frame_ends(&times, duration, ranges);
+        let mut cues: Vec<Cue> = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            let (start, end) = (times[index], ends[index]);
+            //frames without the field, or with nothing in it, leave a gap.
+            let value = match frame["data"].pointer(&self.field) {
+                Some(&Value::Null) | None => continue,
+                Some(&Value::String(ref text)) if text.trim().is_empty() => continue,
+                Some(value) => value.clone()
+            };
+            let merged = match cues.last_mut() {
+                Some(last) => {
+                    if last.value == value && (last.end - start).abs() < 1e-6 {
+                        last.end = end;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                None => false
+            };
+            if !merged {
+                cues.push(Cue { start: start, end: end, value: value });
+            }
+        }
+        Ok(cues)
+    }
+
+    //writes the extract result to json_path in this format.
+    pub fn write(&self, frame_data: &Value, json_path: &Path, duration: f64, ranges: Option<&Vec<TimeRange>>) -> Result<PathBuf, VideoError> {
+        let contents = match self.format {
+            Format::Json => serde_json::to_string(frame_data)?,
+            format => render(format, &self.cues(frame_data, duration, ranges)?)?
+        };
+        let local_file = File::create(json_path).map_err(|err| {format!("failed to create local {} file {}\n{}", self.format.name(), json_path.display(), err)})?;
+        let mut writer = BufWriter::new(local_file);
+        try!(writer.write_all(contents.as_bytes()));
+        Ok(PathBuf::from(json_path))
+    }
+}
+
+//every frame's timestamp, in frame order.
+pub fn timestamps(frames: &Vec<Value>) -> Result<Vec<f64>, VideoError> {
+    frames.iter().enumerate()
+        .map(|(index, frame)| frame["timestamp"].as_f64().ok_or(format!("frame {} has no timestamp.", index + 1).into()))
+        .collect()
+}
+
+fn render(format: Format, cues: &Vec<Cue>) -> Result<String, VideoError> {
+    let mut output = String::new();
+    match format {
+        Format::JsonLines => {
+            for cue in cues {
+                output.push_str(&serde_json::to_string(&json!({"start": cue.start, "end": cue.end, "value": cue.value.clone()}))?);
+                output.push('\n');
+            }
+        }
+        Format::Csv => {
+            output.push_str("start,end,text\n");
+            for cue in cues {
+                output.push_str(&format!("{},{},{}\n", cue.start, cue.end, csv_field(&cue.text())));
+            }
+        }
+        Format::WebVtt => {
+            output.push_str("WEBVTT\n");
+            for cue in cues {
+                output.push_str(&format!("\n{} --> {}\n{}\n", timecode(cue.start, '.'), timecode(cue.end, '.'), vtt_text(&cue_text(&cue.text()))));
+            }
+        }
+        Format::Srt => {
+            for (index, cue) in cues.iter().enumerate() {
+                output.push_str(&format!("{}\n{} --> {}\n{}\n\n", index + 1, timecode(cue.start, ','), timecode(cue.end, ','), cue_text(&cue.text())));
+            }
+        }
+        Format::Json => return Err(format!("json output isn't made of cues.").into())
+    }
+    Ok(output)
+}
+
+//hh:mm:ss.mmm for vtt, hh:mm:ss,mmm for srt.
+fn timecode(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0f64) * 1000f64).round() as u64;
+    format!("{:02}:{:02}:{:02}{}{:03}", millis / 3600000, (millis / 60000) % 60, (millis / 1000) % 60, separator, millis % 1000)
+}
+
+//a blank line ends a cue, so they're dropped from the text.
+fn cue_text(text: &str) -> String {
+    text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect::<Vec<&str>>().join("\n")
+}
+
+//webvtt cue text is markup, so & and < have to be escaped & an arrow would be read as a new timing line.
+fn vtt_text(text: &str) -> String {
+    text.replace("&", "&amp;").replace("<", "&lt;").replace("-->", "--&gt;")
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') || text.contains('\n') || text.contains('\r') {
+        format!("\"{}\"", text.replace("\"", "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identical_frames_merge_into_one_cue() {
+        let frame_data = json!({"frame_data": [
+            {"timestamp": 0.0, "data": {"caption": "a dog"}},
+            {"timestamp": 0.5, "data": {"caption": "a dog"}},
+            {"timestamp": 1.0, "data": {"caption": null}},
+            {"timestamp": 1.5, "data": {"caption": "a dog, running"}}
+        ]});
+        let output = ExtractOutput::from_request(Some(&json!({"field": "caption"})), "captions.srt").unwrap();
+        let cues = output.cues(&frame_data, 2.25, None).unwrap();
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start(), cues[0].end()), (0.0, 1.0));
+        assert_eq!(render(Format::Srt, &cues).unwrap(), "1\n00:00:00,000 --> 00:00:01,000\na dog\n\n2\n00:00:01,500 --> 00:00:02,250\na dog, running\n\n");
+        assert_eq!(render(Format::Csv, &cues).unwrap(), "start,end,text\n0,1,a dog\n1.5,2.25,\"a dog, running\"\n");
+        assert!(ExtractOutput::from_request(Some(&json!({"format": "json", "field": "caption"})), "captions.json").is_err());
+    }
+
+    #[test]
+    fn webvtt_text_is_escaped() {
+        let frame_data = json!({"frame_data": [{"timestamp": 0.0, "data": {"caption": "<b>fish & chips</b> --> 2"}}]});
+        let output = ExtractOutput::from_request(Some(&json!({"field": "caption"})), "captions.vtt").unwrap();
+        let cues = output.cues(&frame_data, 1.0, None).unwrap();
+        assert_eq!(render(Format::WebVtt, &cues).unwrap(), "WEBVTT\n\n00:00:00.000 --> 00:00:01.000\n&lt;b>fish &amp; chips&lt;/b> --&gt; 2\n");
+    }
+}
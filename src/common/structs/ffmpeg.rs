@@ -1,10 +1,17 @@
-use algorithmia::Algorithmia;
 use std::process::Command;
 use common::video_error::VideoError;
 use std::path::*;
 use common::file_mgmt;
-use common::algo;
+use common::storage::Storage;
 use std::f64;
+use std::fs::{File, remove_file};
+use std::io::{Read, Write};
+use common::structs::segments::TimeRange;
+use common::structs::encoding::OutputEncoding;
+use common::structs::properties::VideoProperties;
+use common::structs::media_info::MediaInfo;
+use common::structs::audio::AudioTrack;
+use serde_json::{self, Value};
 //use std::ops::*;
 
 pub struct FFMpeg{
@@ -15,14 +22,26 @@ pub struct FFMpeg{
 impl FFMpeg {
 
 
-    pub fn create(ffmpeg_remote: &str, ffmpeg_directory: &Path, client: &Algorithmia) -> Result<FFMpeg, VideoError> {
+    //prefers the static ffmpeg build from storage, if it can't be fetched we fall back to an ffmpeg install on the PATH so local runs work without a data API account.
+    pub fn create(ffmpeg_remote: &str, ffmpeg_directory: &Path, storage: &Storage) -> Result<FFMpeg, VideoError> {
         let ffmpeg_file: PathBuf = PathBuf::from(format!("{}/{}", ffmpeg_directory.display(), "ffmpeg.tar.gz"));
         let checker_file: PathBuf = PathBuf::from(format!("{}/{}", ffmpeg_directory.display(), "/ffmpeg-static/ffmpeg"));
         if !checker_file.exists() {
-            let tar_file = algo::get_file_from_algorithmia(ffmpeg_remote, &ffmpeg_file, client)?;
-            println!("got file.");
-            let _ = try!(Command::new("tar").args(&["-C", ffmpeg_directory.to_str().unwrap(), "-xf", &tar_file.to_str().unwrap()]).output());
-            println!("unzipped file.");
+            match storage.get(ffmpeg_remote, &ffmpeg_file) {
+                Ok(tar_file) => {
+                    println!("got file.");
+                    let _ = try!(Command::new("tar").args(&["-C", ffmpeg_directory.to_str().unwrap(), "-xf", &tar_file.to_str().unwrap()]).output());
+                    println!("unzipped file.");
+                }
+                Err(err) => {
+                    if FFMpeg::system_available() {
+                        println!("couldn't get static ffmpeg, using the system install instead.");
+                        return Ok(FFMpeg { ffprobe_path: PathBuf::from("ffprobe"), ffmpeg_path: PathBuf::from("ffmpeg") })
+                    } else {
+                        return Err(format!("couldn't get ffmpeg from {} and no system ffmpeg was found:\n{}", ffmpeg_remote, err).into())
+                    }
+                }
+            }
         }
         Ok(
             FFMpeg{ffprobe_path: PathBuf::from(format!("{}/{}", ffmpeg_directory.display(), "/ffmpeg-static/ffprobe")),
@@ -30,29 +49,35 @@ impl FFMpeg {
         )
     }
 
+    fn system_available() -> bool {
+        let ffmpeg = Command::new("ffmpeg").arg("-version").output();
+        let ffprobe = Command::new("ffprobe").arg("-version").output();
+        match (ffmpeg, ffprobe) {
+            (Ok(ffmpeg), Ok(ffprobe)) => ffmpeg.status.success() && ffprobe.status.success(),
+            _ => false
+        }
+    }
+
     pub fn ffmpeg(&self) -> &str {self.ffmpeg_path.as_path().to_str().unwrap()}
 
     pub fn ffprobe(&self) -> &str {self.ffprobe_path.as_path().to_str().unwrap()}
 
-    pub fn get_video_duration(&self, video_path: &Path) -> Result<f64, VideoError> {
+    //everything ffprobe reports about the container & its streams.
+    pub fn probe(&self, video_path: &Path) -> Result<MediaInfo, VideoError> {
         let response = try!(Command::new(self.ffprobe())
-            .arg("-v")
-            .arg("error")
-            .arg("-show_entries")
-            .arg("format=duration")
-            .arg("-of")
-            .arg("default=noprint_wrappers=1:nokey=1")
-            .arg(video_path.to_str().unwrap())
-            .output());
-        if response.stderr.is_empty() {
-            let mut result = try!(String::from_utf8(response.stdout));
-            result.pop();
-            Ok(try!(result.parse::<f64>()))
+            .args(&["-v", "error",
+                "-show_format",
+                "-show_streams",
+                "-of", "json",
+                video_path.to_str().unwrap()]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let probed: Value = serde_json::from_slice(&response.stdout)?;
+            MediaInfo::from_json(&probed)
+        } else {
+            Err(format!("ffprobe error, could not probe {}: \n {}", video_path.display(), String::from_utf8_lossy(&response.stderr)).into())
         }
-            else {
-                Err(format!("ffprobe error, could not get duration: \n {}", String::from_utf8_lossy(&response.stderr)).into())
-            }
     }
+
     //determines a basic jpeg compression ratio between 2-19 based on how big the file is.
     pub fn get_compression_factor(&self, video_file: &Path) -> Result<usize, VideoError> {
         let file_size: f64 = try!(file_mgmt::get_filesize_mb(video_file)) as f64;
@@ -65,101 +90,146 @@ impl FFMpeg {
                 Ok(logged )
             }
     }
-    //gets the frames per second of the input video, using nb_frames and duration from ffprobe
-    pub fn get_video_fps(&self, video_file: &Path) -> Result<f64, VideoError> {
-        println!("getting fps");
-        let response = Command::new(self.ffprobe())
-            .arg("-v")
-            .arg("error")
-            .arg("-select_streams")
-            .arg("v:0")
-            .arg("-show_entries")
-            .arg("stream=r_frame_rate")
-            .arg("-of")
-            .arg("default=noprint_wrappers=1:nokey=1")
-            .arg(video_file.to_str().unwrap())
-            .output().unwrap();
-        if response.stderr.is_empty() {
-            let mut result: String = try!(String::from_utf8(response.stdout));
-            result.pop();
-            println!("{:?}", result);
-            let splits: Vec<&str> = result.splitn(2, '/').collect();
-            let numerator = try!(splits.iter().next().unwrap().parse::<f64>());
-            let denominator = try!(splits.iter().next_back().unwrap().parse::<f64>());
-            let fps: f64 = numerator / denominator;
-            println!("{}", fps);
-            Ok(fps)
-        } else {
-            Err(format!("ffprobe error, could not get duration: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+    //re-attaches the subtitle, data & attachment tracks of the original video & the chosen audio to the concatenated video file, streams are copied as is unless the output container can't hold the original subtitle codec or the audio is re-encoded.
+    //the audio is cut to the length of the concatenated video, & to the track's ranges when it has them.
+    pub fn attach_streams(&self, input_video: &Path, output_video: &Path, original_video: &Path, properties: &VideoProperties,
+                          audio: &AudioTrack) -> Result<PathBuf, VideoError> {
+        let extension = output_video.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+        let duration = self.probe(input_video)?.duration()?;
+        let mut args: Vec<String> = vec!["-loglevel", "error",
+                                         "-i", input_video.to_str().unwrap(),
+                                         "-i", original_video.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+        if let Some(replacement) = audio.local_file() {
+            args.push("-i".to_string());
+            args.push(replacement.to_str().unwrap().to_string());
         }
-    }
-
-    //re-attaches the audio track to the concatenated video file.
-    pub fn attach_streams(&self, input_video: &Path, output_video: &Path, original_vvideo: &Path) -> Result<PathBuf, VideoError> {
-        let response = try!(Command::new(self.ffmpeg())
-            .args(&["-loglevel",
-                "error",
-                "-i", input_video.to_str().unwrap(),
-                "-i", original_vvideo.to_str().unwrap(),
-                "-c", "copy",
-                "-map", "1",
-                "-map", "-1:v",
-                "-map", "0:v",
-                output_video.to_str().unwrap(), "-y"]).output());
-        if response.stderr.is_empty() {
+        args.push("-map".to_string());
+        args.push("0:v".to_string());
+        let original = self.probe(original_video)?;
+        args.extend(audio.map_args(original.audio_streams().len()));
+        args.extend(extra_stream_maps(&original, &extension));
+        args.push("-c".to_string());
+        args.push("copy".to_string());
+        if let Some(codec) = subtitle_codec(&extension) {
+            args.push("-c:s".to_string());
+            args.push(codec.to_string());
+        }
+        //trimmed audio comes out of a filter, so it's encoded even when the track would otherwise be copied.
+        if let (Some(_), None, Some(codec)) = (audio.ranges(), audio.codec(), audio_codec(&extension)) {
+            args.push("-c:a".to_string());
+            args.push(codec.to_string());
+        }
+        args.extend(audio.codec_args());
+        args.push("-t".to_string());
+        args.push(duration.to_string());
+        args.extend(properties.remux_args());
+        args.push(output_video.to_str().unwrap().to_string());
+        args.push("-y".to_string());
+        let response = try!(Command::new(self.ffmpeg()).args(&args).output());
+        if response.status.success() && response.stderr.is_empty() {
             Ok(PathBuf::from(output_video))
         } else {
             Err(format!("ffmpeg error, could not re-attach streams: \n {}", String::from_utf8_lossy(&response.stderr)).into())
         }
     }
 
-    pub fn cat_video(&self, output_file: &Path, directory: &Path, regex: &str, fps: f64, crf: Option<u64>) -> Result<PathBuf, VideoError> {
+    //encodes the frames matching regex in directory into a video, frames limits it to frame_count frames starting at first_frame.
+    pub fn cat_video(&self, output_file: &Path, directory: &Path, regex: &str, fps: f64, encoding: &OutputEncoding, properties: &VideoProperties,
+                     frames: Option<(usize, usize)>) -> Result<PathBuf, VideoError> {
         let complete_regex = format!("{}/{}", directory.display(), regex);
-        let response = if crf.is_some() {
-            Command::new(self.ffmpeg())
-                .args(&["-loglevel", "error",
-                    "-framerate", &fps.to_string(),
-                    "-i", &complete_regex,
-                    "-c:v", "libx264",
-                    "-pix_fmt", "yuv420p",
-                    "-preset", "veryfast",
-                    "-crf", &crf.unwrap().to_string(),
-                    output_file.to_str().unwrap(), "-y"]).output()?
-        } else {
-            Command::new(self.ffmpeg())
-                .args(&["-loglevel", "error",
-                    "-framerate", &fps.to_string(),
-                    "-i", &complete_regex,
-                    "-c:v", "libx264",
-                    "-pix_fmt", "yuv420p",
-                    output_file.to_str().unwrap(), "-y"]).output()?
-        };
+        let mut args: Vec<String> = vec!["-loglevel".to_string(), "error".to_string(),
+                                         "-framerate".to_string(), fps.to_string()];
+        if let Some((first_frame, _)) = frames {
+            args.push("-start_number".to_string());
+            args.push(first_frame.to_string());
+        }
+        args.push("-i".to_string());
+        args.push(complete_regex);
+        args.extend(encoding.ffmpeg_args());
+        args.extend(properties.encode_args(encoding));
+        if let Some((_, frame_count)) = frames {
+            args.push("-frames:v".to_string());
+            args.push(frame_count.to_string());
+        }
+        args.push(output_file.to_str().unwrap().to_string());
+        args.push("-y".to_string());
+        let response = Command::new(self.ffmpeg()).args(&args).output()?;
 
-        if response.stderr.is_empty() {
+        if response.status.success() && response.stderr.is_empty() {
             Ok(PathBuf::from(output_file))
         } else {
             Err(format!("ffmpeg error, could not concat frames: \n {}", String::from_utf8_lossy(&response.stderr)).into())
         }
     }
-    //splits a video into frames at a given fps using ffmpeg, if no quality we use jpeg image compression based on the input video filesize.
-    pub fn split_video(&self, video_path: &Path, frames_path: &Path, regex: &str, fps: f64, compression_factor: &Option<u64>) -> Result<Vec<PathBuf>, VideoError> {
-        let response = if compression_factor.is_some() {
-            try!(Command::new(self.ffmpeg())
-                .args(&["-loglevel", "error",
-                    "-i", video_path.to_str().unwrap(),
-                        "-q:v", &compression_factor.clone().unwrap().to_string(),
-                    "-vf",
-                    &format!("fps={}", fps),
-                    regex, "-y"]).current_dir(frames_path).output())
-        }
-        else {
-            try!(Command::new(self.ffmpeg())
-                .args(&["-loglevel", "error",
-                    "-i", video_path.to_str().unwrap(),
-                    "-vf",
-                    &format!("fps={}", fps),
-                    regex, "-y"]).current_dir(frames_path).output())
-        };
+
+    //encodes frames at their own timestamps with an ffconcat list, each frame lasts until the next one starts & the last one as long as the one before it.
+    //fallback_duration is used when there's only a single frame.
+    pub fn cat_video_timed(&self, output_file: &Path, frames: &[PathBuf], timestamps: &[f64], fallback_duration: f64,
+                           encoding: &OutputEncoding, properties: &VideoProperties) -> Result<PathBuf, VideoError> {
+        if frames.is_empty() || frames.len() != timestamps.len() {
+            return Err(format!("ffmpeg error, can't time {} frames with {} timestamps.", frames.len(), timestamps.len()).into())
+        }
+        let mut durations: Vec<f64> = timestamps.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let last = durations.last().cloned().unwrap_or(fallback_duration);
+        durations.push(last);
+        let mut list = String::from("ffconcat version 1.0\n");
+        for (frame, duration) in frames.iter().zip(durations.iter()) {
+            list.push_str(&format!("file '{}'\nduration {:.6}\n", frame.display(), duration));
+        }
+        //the concat demuxer ignores the last duration unless the last file is listed again.
+        list.push_str(&format!("file '{}'\n", frames[frames.len() - 1].display()));
+        let list_file = output_file.with_extension("ffconcat");
+        File::create(&list_file).and_then(|mut file| file.write_all(list.as_bytes()))
+            .map_err(|err| format!("failed to write frame list {}\n{}", list_file.display(), err))?;
+        let mut args: Vec<String> = vec!["-loglevel", "error",
+                                         "-f", "concat",
+                                         "-safe", "0",
+                                         "-i", list_file.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+        args.extend(encoding.ffmpeg_args());
+        args.extend(properties.encode_args(encoding));
+        //without this mp4 style outputs are made constant frame rate again by duplicating frames.
+        for arg in &["-vsync", "vfr", "-frames:v", &frames.len().to_string(), output_file.to_str().unwrap(), "-y"] {
+            args.push(arg.to_string());
+        }
+        let response = Command::new(self.ffmpeg()).args(&args).output()?;
+        let _ = remove_file(&list_file);
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not concat timed frames: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //the input options that limit decoding to a range, -ss before -i seeks accurately when decoding.
+    fn range_args(range: Option<&TimeRange>) -> Vec<String> {
+        match range {
+            Some(range) => vec!["-ss".to_string(), range.start().to_string(), "-t".to_string(), range.duration().to_string()],
+            None => Vec::new()
+        }
+    }
+
+    //splits a video (or just range of it) into frames at a given fps using ffmpeg, numbered from start_number. if no quality we use jpeg image compression based on the input video filesize.
+    pub fn split_video(&self, video_path: &Path, frames_path: &Path, regex: &str, fps: f64, compression_factor: &Option<u64>,
+                       range: Option<&TimeRange>, start_number: usize) -> Result<Vec<PathBuf>, VideoError> {
+        let mut args: Vec<String> = vec!["-loglevel".to_string(), "error".to_string()];
+        args.extend(FFMpeg::range_args(range));
+        args.push("-i".to_string());
+        args.push(video_path.to_str().unwrap().to_string());
+        if let &Some(ref compression) = compression_factor {
+            args.push("-q:v".to_string());
+            args.push(compression.to_string());
+        }
+        for arg in &["-vf", &format!("fps={}", fps), "-start_number", &start_number.to_string()] {
+            args.push(arg.to_string());
+        }
+        //the fps filter can round up to one frame past the end of a range, which would make it a frame longer once encoded.
+        if let Some(range) = range {
+            args.push("-frames:v".to_string());
+            args.push(((range.duration() * fps).round() as u64).max(1).to_string());
+        }
+        args.push(regex.to_string());
+        args.push("-y".to_string());
+        let response = try!(Command::new(self.ffmpeg()).args(&args).current_dir(frames_path).output());
 
         if response.stderr.is_empty() {
             let frames: Vec<PathBuf> = file_mgmt::get_files_and_sort(frames_path);
@@ -168,4 +238,362 @@ impl FFMpeg {
             Err(format!("ffmpeg error, could not split video into frames: \n {}", String::from_utf8_lossy(&response.stderr)).into())
         }
     }
-}
\ No newline at end of file
+
+    //keeps every frame of the video (or range of it) without duplicating or dropping any, returns the new frames with their presentation timestamps.
+    pub fn split_video_source(&self, video_path: &Path, frames_path: &Path, regex: &str, compression_factor: &Option<u64>,
+                              range: Option<&TimeRange>, start_number: usize) -> Result<(Vec<PathBuf>, Vec<f64>), VideoError> {
+        let existing = file_mgmt::get_files_and_sort(frames_path).len();
+        let mut args: Vec<String> = vec!["-loglevel".to_string(), "error".to_string()];
+        args.extend(FFMpeg::range_args(range));
+        args.push("-i".to_string());
+        args.push(video_path.to_str().unwrap().to_string());
+        if let &Some(ref compression) = compression_factor {
+            args.push("-q:v".to_string());
+            args.push(compression.to_string());
+        }
+        for arg in &["-vsync", "passthrough", "-start_number", &start_number.to_string(), regex, "-y"] {
+            args.push(arg.to_string());
+        }
+        let response = try!(Command::new(self.ffmpeg()).args(&args).current_dir(frames_path).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let timestamps = self.frame_times(video_path, range)?;
+            let frames: Vec<PathBuf> = file_mgmt::get_files_and_sort(frames_path).into_iter().skip(existing).collect();
+            if frames.len() != timestamps.len() {
+                return Err(format!("ffmpeg error, split {} frames but the video has {} frame timestamps.", frames.len(), timestamps.len()).into())
+            }
+            Ok((frames, timestamps))
+        } else {
+            Err(format!("ffmpeg error, could not split video into frames: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //keeps the first frame and every frame whose scene change score is above threshold, returns the new frames with their presentation timestamps in seconds from the start of the video.
+    pub fn split_video_scenes(&self, video_path: &Path, frames_path: &Path, regex: &str, threshold: f64, compression_factor: &Option<u64>,
+                              range: Option<&TimeRange>, start_number: usize) -> Result<(Vec<PathBuf>, Vec<f64>), VideoError> {
+        //the metadata filter writes one entry per selected frame, it lives next to the frames directory so it isn't mistaken for a frame.
+        let timestamps_file = frames_path.with_extension("timestamps");
+        let filter = format!("select='eq(n,0)+gt(scene,{})',metadata=print:file={}", threshold, timestamps_file.display());
+        let offset = range.map(|range| range.start()).unwrap_or(0f64);
+        let existing = file_mgmt::get_files_and_sort(frames_path).len();
+        let mut args: Vec<String> = vec!["-loglevel".to_string(), "error".to_string()];
+        args.extend(FFMpeg::range_args(range));
+        args.push("-i".to_string());
+        args.push(video_path.to_str().unwrap().to_string());
+        if let &Some(ref compression) = compression_factor {
+            args.push("-q:v".to_string());
+            args.push(compression.to_string());
+        }
+        for arg in &["-vf", &filter, "-vsync", "vfr", "-start_number", &start_number.to_string(), regex, "-y"] {
+            args.push(arg.to_string());
+        }
+        let response = try!(Command::new(self.ffmpeg()).args(&args).current_dir(frames_path).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let mut contents = String::new();
+            File::open(&timestamps_file).and_then(|mut file| file.read_to_string(&mut contents))
+                .map_err(|err| format!("ffmpeg error, could not read scene timestamps from {}:\n{}", timestamps_file.display(), err))?;
+            let _ = remove_file(&timestamps_file);
+            let timestamps: Vec<f64> = contents.lines()
+                .filter_map(|line| line.split_whitespace().find(|field| field.starts_with("pts_time:")))
+                .map(|field| field.trim_left_matches("pts_time:").parse::<f64>().map(|time| time + offset))
+                .collect::<Result<Vec<f64>, _>>()?;
+            let frames: Vec<PathBuf> = file_mgmt::get_files_and_sort(frames_path).into_iter().skip(existing).collect();
+            if frames.len() != timestamps.len() {
+                return Err(format!("ffmpeg error, found {} scene frames but {} timestamps.", frames.len(), timestamps.len()).into())
+            }
+            Ok((frames, timestamps))
+        } else {
+            Err(format!("ffmpeg error, could not split video into scenes: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //splits the first audio track into wav windows of window seconds, numbered from 1, returns them with the time each one starts at.
+    pub fn split_audio(&self, video_path: &Path, windows_path: &Path, regex: &str, window: f64) -> Result<(Vec<PathBuf>, Vec<f64>), VideoError> {
+        //the segment list lives next to the windows directory so it isn't mistaken for a window.
+        let list_file = windows_path.with_extension("csv");
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-i", video_path.to_str().unwrap(),
+                "-map", "0:a:0",
+                "-c:a", "pcm_s16le",
+                "-f", "segment",
+                "-segment_time", &window.to_string(),
+                "-segment_start_number", "1",
+                "-segment_list", list_file.to_str().unwrap(),
+                "-segment_list_type", "csv",
+                "-reset_timestamps", "1",
+                regex, "-y"]).current_dir(windows_path).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let mut contents = String::new();
+            File::open(&list_file).and_then(|mut file| file.read_to_string(&mut contents))
+                .map_err(|err| format!("ffmpeg error, could not read audio windows from {}:\n{}", list_file.display(), err))?;
+            let _ = remove_file(&list_file);
+            //each line is file,start,end.
+            let starts: Vec<f64> = contents.lines()
+                .filter_map(|line| line.split(',').nth(1))
+                .map(|start| start.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()?;
+            let windows: Vec<PathBuf> = file_mgmt::get_files_and_sort(windows_path);
+            if windows.len() != starts.len() {
+                return Err(format!("ffmpeg error, found {} audio windows but {} start times.", windows.len(), starts.len()).into())
+            }
+            Ok((windows, starts))
+        } else {
+            Err(format!("ffmpeg error, could not split audio into windows: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //decodes the first audio stream of audio_file into a pcm_s16le wav at sample_rate & channels, whatever format it was in.
+    pub fn resample_audio(&self, audio_file: &Path, output_file: &Path, sample_rate: u64, channels: u64) -> Result<PathBuf, VideoError> {
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-i", audio_file.to_str().unwrap(),
+                "-map", "0:a:0",
+                "-af", &format!("aresample={}", sample_rate),
+                "-ac", &channels.to_string(),
+                "-c:a", "pcm_s16le",
+                output_file.to_str().unwrap(), "-y"]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not resample {}: \n {}", audio_file.display(), String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //writes audio_file as the only audio of output_file, which keeps the video & subtitles of the original unless it's an audio only format.
+    pub fn replace_audio(&self, original_video: &Path, audio_file: &Path, output_file: &Path) -> Result<PathBuf, VideoError> {
+        let extension = output_file.extension().and_then(|ext| ext.to_str()).unwrap_or("").to_lowercase();
+        let codec = audio_codec(&extension).ok_or(format!("can't write audio to a '.{}' file.", extension))?;
+        let mut args: Vec<String> = vec!["-loglevel", "error",
+                                         "-i", original_video.to_str().unwrap(),
+                                         "-i", audio_file.to_str().unwrap()].iter().map(|arg| arg.to_string()).collect();
+        if is_audio_only(&extension) {
+            args.extend(vec!["-map".to_string(), "1:a:0".to_string()]);
+        } else {
+            for arg in &["-map", "0:v?", "-map", "1:a:0", "-map", "0:s?", "-c", "copy"] {
+                args.push(arg.to_string());
+            }
+            if let Some(codec) = subtitle_codec(&extension) {
+                args.push("-c:s".to_string());
+                args.push(codec.to_string());
+            }
+        }
+        for arg in &["-c:a", codec, "-map_metadata", "0", "-map_chapters", "0", output_file.to_str().unwrap(), "-y"] {
+            args.push(arg.to_string());
+        }
+        let response = try!(Command::new(self.ffmpeg()).args(&args).output());
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not replace the audio: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //presentation timestamps of the video stream's keyframes, read from packet flags so nothing is decoded.
+    pub fn keyframes(&self, video_path: &Path) -> Result<Vec<f64>, VideoError> {
+        let packets = self.packets(video_path).map_err(|err| format!("ffprobe error, could not find keyframes: \n {}", err))?;
+        Ok(packets.into_iter().filter(|&(_, keyframe)| keyframe).map(|(time, _)| time).collect())
+    }
+
+    //presentation timestamps of every frame of the video stream (or range of it), in display order.
+    pub fn frame_times(&self, video_path: &Path, range: Option<&TimeRange>) -> Result<Vec<f64>, VideoError> {
+        let packets = self.packets(video_path).map_err(|err| format!("ffprobe error, could not get frame timestamps: \n {}", err))?;
+        Ok(packets.into_iter().map(|(time, _)| time).filter(|time| range.map(|range| range.contains(*time)).unwrap_or(true)).collect())
+    }
+
+    //pts & keyframe flag of each video packet sorted by pts, every packet holds one frame.
+    fn packets(&self, video_path: &Path) -> Result<Vec<(f64, bool)>, VideoError> {
+        let response = try!(Command::new(self.ffprobe())
+            .args(&["-v", "error",
+                "-select_streams", "v:0",
+                "-show_entries", "packet=pts_time,flags",
+                "-of", "csv=p=0",
+                video_path.to_str().unwrap()]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let output = try!(String::from_utf8(response.stdout));
+            let mut packets: Vec<(f64, bool)> = output.lines().filter_map(|line| {
+                let mut fields = line.split(',');
+                match (fields.next().and_then(|time| time.parse::<f64>().ok()), fields.next()) {
+                    //discarded packets are decoded but never shown.
+                    (Some(time), Some(flags)) if !flags.contains('D') => Some((time, flags.contains('K'))),
+                    _ => None
+                }
+            }).collect();
+            packets.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            Ok(packets)
+        } else {
+            Err(String::from_utf8_lossy(&response.stderr).to_string().into())
+        }
+    }
+
+    //every frame matching regex in frames_path shrunk to an 8x8 grayscale thumbnail, 64 bytes each in frame order.
+    pub fn thumbnails(&self, frames_path: &Path, regex: &str, number_of_frames: usize) -> Result<Vec<Vec<u8>>, VideoError> {
+        let complete_regex = format!("{}/{}", frames_path.display(), regex);
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-start_number", "1",
+                "-i", &complete_regex,
+                "-vf", "scale=8:8:flags=area,format=gray",
+                "-f", "rawvideo", "-"]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            let thumbnails: Vec<Vec<u8>> = response.stdout.chunks(64).map(|thumbnail| thumbnail.to_vec()).collect();
+            if thumbnails.len() != number_of_frames || response.stdout.len() % 64 != 0 {
+                return Err(format!("ffmpeg error, got {} bytes of thumbnails for {} frames.", response.stdout.len(), number_of_frames).into())
+            }
+            Ok(thumbnails)
+        } else {
+            Err(format!("ffmpeg error, could not make frame thumbnails: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //draws onto a single frame with a filter chain, jpg frames are kept at high quality so drawing doesn't add visible compression.
+    pub fn draw(&self, input_frame: &Path, output_frame: &Path, filter: &str) -> Result<PathBuf, VideoError> {
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-i", input_frame.to_str().unwrap(),
+                "-vf", filter,
+                "-q:v", "2",
+                "-frames:v", "1",
+                output_frame.to_str().unwrap(), "-y"]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_frame))
+        } else {
+            Err(format!("ffmpeg error, could not draw onto {}: \n {}", input_frame.display(), String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //copies a keyframe aligned range of the video stream without re-encoding.
+    pub fn copy_range(&self, video_path: &Path, output_file: &Path, range: &TimeRange) -> Result<PathBuf, VideoError> {
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-ss", &range.start().to_string(),
+                "-i", video_path.to_str().unwrap(),
+                "-t", &range.duration().to_string(),
+                "-map", "0:v:0",
+                "-c", "copy",
+                "-avoid_negative_ts", "make_zero",
+                output_file.to_str().unwrap(), "-y"]).output());
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not copy {}s - {}s of the original video: \n {}", range.start(), range.end(), String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //re-encodes a range of the video stream, for when a stream copy of it couldn't be joined to the processed parts.
+    //frames are decoded upright like split frames are, so properties turns them back the same way.
+    pub fn encode_range(&self, video_path: &Path, output_file: &Path, range: &TimeRange, encoding: &OutputEncoding,
+                        properties: &VideoProperties) -> Result<PathBuf, VideoError> {
+        let mut args: Vec<String> = vec!["-loglevel".to_string(), "error".to_string()];
+        args.extend(FFMpeg::range_args(Some(range)));
+        for arg in &["-i", video_path.to_str().unwrap(), "-map", "0:v:0"] {
+            args.push(arg.to_string());
+        }
+        args.extend(encoding.ffmpeg_args());
+        args.extend(properties.encode_args(encoding));
+        args.push(output_file.to_str().unwrap().to_string());
+        args.push("-y".to_string());
+        let response = Command::new(self.ffmpeg()).args(&args).output()?;
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not re-encode {}s - {}s of the original video: \n {}", range.start(), range.end(), String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+
+    //joins video files end to end with the concat demuxer, the parts must share a codec.
+    pub fn concat(&self, parts: &Vec<PathBuf>, list_file: &Path, output_file: &Path) -> Result<PathBuf, VideoError> {
+        let list: String = parts.iter().map(|part| format!("file '{}'\n", part.display())).collect();
+        File::create(list_file).and_then(|mut file| file.write_all(list.as_bytes()))
+            .map_err(|err| format!("failed to write concat list {}\n{}", list_file.display(), err))?;
+        let response = try!(Command::new(self.ffmpeg())
+            .args(&["-loglevel", "error",
+                "-f", "concat",
+                "-safe", "0",
+                "-i", list_file.to_str().unwrap(),
+                "-c", "copy",
+                output_file.to_str().unwrap(), "-y"]).output());
+        let _ = remove_file(list_file);
+        if response.status.success() && response.stderr.is_empty() {
+            Ok(PathBuf::from(output_file))
+        } else {
+            Err(format!("ffmpeg error, could not join video parts: \n {}", String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
+}
+
+//the audio encoder used for processed audio in each container.
+pub fn audio_codec(extension: &str) -> Option<&'static str> {
+    match extension {
+        "mp4" | "m4v" | "mov" | "m4a" | "aac" => Some("aac"),
+        "webm" | "ogg" | "opus" => Some("libopus"),
+        "mkv" | "flac" => Some("flac"),
+        "mp3" => Some("libmp3lame"),
+        "wav" | "avi" => Some("pcm_s16le"),
+        _ => None
+    }
+}
+
+pub fn is_audio_only(extension: &str) -> bool {
+    match extension {
+        "m4a" | "aac" | "ogg" | "opus" | "flac" | "mp3" | "wav" => true,
+        _ => false
+    }
+}
+
+//maps the original's subtitle, data & attachment streams that the output container can hold.
+//when subtitles have to be converted only text ones can be, bitmap subtitles like pgs or dvb are dropped.
+//data streams like tmcd or a camera's bin_data only survive in mkv & mov, mp4 & webm refuse the whole file if they're mapped.
+fn extra_stream_maps(original: &MediaInfo, extension: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+    for stream in original.streams() {
+        let keep = match stream.kind() {
+            "subtitle" => subtitle_codec(extension).is_none() || is_text_subtitle(stream.codec()),
+            "data" => extension == "mkv" || extension == "mov",
+            "attachment" => extension == "mkv",
+            _ => continue
+        };
+        if keep {
+            args.push("-map".to_string());
+            args.push(format!("1:{}", stream.index()));
+        } else {
+            println!("dropping {} stream {} ({}), a .{} file can't hold it.", stream.kind(), stream.index(), stream.codec().unwrap_or("unknown codec"), extension);
+        }
+    }
+    args
+}
+
+fn is_text_subtitle(codec: Option<&str>) -> bool {
+    match codec {
+        Some("subrip") | Some("srt") | Some("ass") | Some("ssa") | Some("webvtt") | Some("mov_text") | Some("text") => true,
+        _ => false
+    }
+}
+
+//mp4 style containers can't hold text subtitles like subrip or ass, so they need to be converted when remuxing.
+fn subtitle_codec(extension: &str) -> Option<&'static str> {
+    match extension {
+        "mp4" | "m4v" | "mov" => Some("mov_text"),
+        "webm" => Some("webvtt"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_streams_the_container_can_hold_are_mapped() {
+        let original = MediaInfo::from_json(&json!({"streams": [
+            {"index": 0, "codec_type": "video", "codec_name": "h264"},
+            {"index": 1, "codec_type": "audio", "codec_name": "aac"},
+            {"index": 2, "codec_type": "subtitle", "codec_name": "subrip"},
+            {"index": 3, "codec_type": "subtitle", "codec_name": "hdmv_pgs_subtitle"},
+            {"index": 4, "codec_type": "data", "codec_name": "bin_data"},
+            {"index": 5, "codec_type": "attachment", "codec_name": "ttf"}
+        ]})).unwrap();
+        assert_eq!(extra_stream_maps(&original, "mp4"), vec!["-map", "1:2"]);
+        assert_eq!(extra_stream_maps(&original, "mov"), vec!["-map", "1:2", "-map", "1:4"]);
+        assert_eq!(extra_stream_maps(&original, "mkv"), vec!["-map", "1:2", "-map", "1:3", "-map", "1:4", "-map", "1:5"]);
+    }
+}
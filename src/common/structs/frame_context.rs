@@ -0,0 +1,87 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+//consts rather than statics so the lists below can be built from them.
+pub const FRAME_INDEX: &'static str = "$FRAME_INDEX";
+pub const TIMESTAMP: &'static str = "$TIMESTAMP";
+pub const PREVIOUS_FRAME: &'static str = "$PREVIOUS_FRAME";
+pub const FPS: &'static str = "$FPS";
+pub const WIDTH: &'static str = "$WIDTH";
+pub const HEIGHT: &'static str = "$HEIGHT";
+pub const JOB_ID: &'static str = "$JOB_ID";
+pub const BATCH_INDICES: &'static str = "$BATCH_INDICES";
+pub const BATCH_TIMESTAMPS: &'static str = "$BATCH_TIMESTAMPS";
+
+//the variables that describe a single frame, they can't be used when frames are sent as a batch.
+pub static SINGLE_VARIABLES: [&'static str; 3] = [FRAME_INDEX, TIMESTAMP, PREVIOUS_FRAME];
+pub static BATCH_VARIABLES: [&'static str; 2] = [BATCH_INDICES, BATCH_TIMESTAMPS];
+pub static VARIABLES: [&'static str; 9] = [FRAME_INDEX, TIMESTAMP, PREVIOUS_FRAME, FPS, WIDTH, HEIGHT, JOB_ID,
+    BATCH_INDICES, BATCH_TIMESTAMPS];
+
+//keyword -> the json value it's replaced with.
+pub type Variables = BTreeMap<&'static str, Value>;
+
+//what an advanced input can refer to besides the frame files, shared by every batch of a job.
+#[derive(Debug, Clone)]
+pub struct FrameContext {
+    job_id: String,
+    fps: f64,
+    //of the frames as the algorithm gets them, None for audio.
+    dimensions: Option<(u64, u64)>,
+    //presentation time of every frame, in frame order.
+    timestamps: Vec<f64>,
+}
+
+impl FrameContext {
+    pub fn new(job_id: &str, fps: f64, dimensions: Option<(u64, u64)>, timestamps: Vec<f64>) -> FrameContext {
+        FrameContext { job_id: job_id.to_string(), fps: fps, dimensions: dimensions, timestamps: timestamps }
+    }
+
+    //frame is the frame number, starting at 1, previous is the frame before it as the algorithm sees it.
+    pub fn single(&self, frame: usize, previous: Option<&str>) -> Variables {
+        let mut variables = self.common();
+        variables.insert(FRAME_INDEX, json!(frame - 1));
+        variables.insert(TIMESTAMP, self.timestamp(frame));
+        variables.insert(PREVIOUS_FRAME, previous.map(|previous| Value::String(previous.to_string())).unwrap_or(Value::Null));
+        variables
+    }
+
+    pub fn batch(&self, frames: &Vec<usize>) -> Variables {
+        let mut variables = self.common();
+        variables.insert(BATCH_INDICES, Value::Array(frames.iter().map(|frame| json!(frame - 1)).collect()));
+        variables.insert(BATCH_TIMESTAMPS, Value::Array(frames.iter().map(|frame| self.timestamp(*frame)).collect()));
+        variables
+    }
+
+    fn common(&self) -> Variables {
+        let mut variables: Variables = BTreeMap::new();
+        variables.insert(FPS, json!(self.fps));
+        variables.insert(WIDTH, self.dimensions.map(|(width, _)| json!(width)).unwrap_or(Value::Null));
+        variables.insert(HEIGHT, self.dimensions.map(|(_, height)| json!(height)).unwrap_or(Value::Null));
+        variables.insert(JOB_ID, Value::String(self.job_id.clone()));
+        variables
+    }
+
+    fn timestamp(&self, frame: usize) -> Value {
+        self.timestamps.get(frame - 1).map(|timestamp| json!(timestamp)).unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::structs::advanced_input::AdvancedInput;
+    use either::Right;
+
+    #[test]
+    fn variables_are_replaced_with_typed_values() {
+        let context = FrameContext::new("job", 2.0, Some((640, 480)), vec![0.0, 0.5, 1.0]);
+        let template = AdvancedInput::create_extract(&json!({"image": "$SINGLE_INPUT", "previous": "$PREVIOUS_FRAME",
+                                                             "meta": {"index": "$FRAME_INDEX", "time": "$TIMESTAMP", "size": ["$WIDTH", "$HEIGHT"]}})).unwrap();
+        assert!(template.uses_previous_frame());
+        let json = template.replace_variables_extract(Right("frame-2.png"), &context.single(2, Some("frame-1.png"))).unwrap();
+        assert_eq!(json, json!({"image": "frame-2.png", "previous": "frame-1.png", "meta": {"index": 1, "time": 0.5, "size": [640, 480]}}));
+        assert_eq!(context.batch(&vec![2, 3])[BATCH_TIMESTAMPS], json!([0.5, 1.0]));
+        assert!(AdvancedInput::create_extract(&json!({"images": "$BATCH_INPUT", "time": "$TIMESTAMP"})).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use common::video_error::VideoError;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+//one stream of a probed file, fields ffprobe doesn't report for that kind of stream are None.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamInfo {
+    index: u64,
+    //video, audio, subtitle, data or attachment.
+    kind: String,
+    codec: Option<String>,
+    profile: Option<String>,
+    pixel_format: Option<String>,
+    //like "1/15360", the unit the stream's timestamps are counted in.
+    time_base: Option<String>,
+    width: Option<u64>,
+    height: Option<u64>,
+    r_frame_rate: Option<f64>,
+    avg_frame_rate: Option<f64>,
+    duration: Option<f64>,
+    bit_rate: Option<u64>,
+    frame_count: Option<u64>,
+    channels: Option<u64>,
+    sample_rate: Option<u64>,
+    sample_aspect_ratio: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    color_space: Option<String>,
+    color_range: Option<String>,
+    //cover art is stored as a single frame video stream.
+    attached_picture: bool,
+    tags: BTreeMap<String, String>,
+    side_data: Vec<Value>,
+}
+
+//what ffprobe knows about a media file, from its json output.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaInfo {
+    format: Option<String>,
+    duration: Option<f64>,
+    bit_rate: Option<u64>,
+    tags: BTreeMap<String, String>,
+    streams: Vec<StreamInfo>,
+}
+
+impl StreamInfo {
+    //stream is one entry of ffprobe's "streams" array, numbers come through as strings so both are accepted.
+    pub fn from_json(stream: &Value) -> StreamInfo {
+        StreamInfo {
+            index: number(&stream["index"]).map(|index| index as u64).unwrap_or(0),
+            kind: text(&stream["codec_type"]).unwrap_or("unknown".to_string()),
+            codec: text(&stream["codec_name"]),
+            profile: text(&stream["profile"]),
+            pixel_format: text(&stream["pix_fmt"]),
+            time_base: text(&stream["time_base"]),
+            width: whole(&stream["width"]),
+            height: whole(&stream["height"]),
+            r_frame_rate: rate(&stream["r_frame_rate"]),
+            avg_frame_rate: rate(&stream["avg_frame_rate"]),
+            duration: number(&stream["duration"]),
+            bit_rate: whole(&stream["bit_rate"]),
+            frame_count: whole(&stream["nb_frames"]),
+            channels: whole(&stream["channels"]),
+            sample_rate: whole(&stream["sample_rate"]),
+            sample_aspect_ratio: text(&stream["sample_aspect_ratio"]),
+            color_primaries: text(&stream["color_primaries"]),
+            color_transfer: text(&stream["color_transfer"]),
+            color_space: text(&stream["color_space"]),
+            color_range: text(&stream["color_range"]),
+            attached_picture: whole(&stream["disposition"]["attached_pic"]) == Some(1),
+            tags: tags(&stream["tags"]),
+            side_data: stream["side_data_list"].as_array().cloned().unwrap_or(Vec::new()),
+        }
+    }
+
+    pub fn index(&self) -> u64 {self.index}
+    pub fn kind(&self) -> &str {&self.kind}
+    pub fn codec(&self) -> Option<&str> {self.codec.as_ref().map(|codec| codec.as_str())}
+    pub fn profile(&self) -> Option<&str> {self.profile.as_ref().map(|profile| profile.as_str())}
+    pub fn pixel_format(&self) -> Option<&str> {self.pixel_format.as_ref().map(|format| format.as_str())}
+    pub fn time_base(&self) -> Option<&str> {self.time_base.as_ref().map(|base| base.as_str())}
+    pub fn width(&self) -> Option<u64> {self.width}
+    pub fn height(&self) -> Option<u64> {self.height}
+    pub fn r_frame_rate(&self) -> Option<f64> {self.r_frame_rate}
+    pub fn avg_frame_rate(&self) -> Option<f64> {self.avg_frame_rate}
+    pub fn duration(&self) -> Option<f64> {self.duration}
+    pub fn bit_rate(&self) -> Option<u64> {self.bit_rate}
+    pub fn frame_count(&self) -> Option<u64> {self.frame_count}
+    pub fn channels(&self) -> Option<u64> {self.channels}
+    pub fn sample_rate(&self) -> Option<u64> {self.sample_rate}
+    pub fn sample_aspect_ratio(&self) -> Option<&str> {self.sample_aspect_ratio.as_ref().map(|ratio| ratio.as_str())}
+    pub fn color_primaries(&self) -> Option<&str> {self.color_primaries.as_ref().map(|color| color.as_str())}
+    pub fn color_transfer(&self) -> Option<&str> {self.color_transfer.as_ref().map(|color| color.as_str())}
+    pub fn color_space(&self) -> Option<&str> {self.color_space.as_ref().map(|color| color.as_str())}
+    pub fn color_range(&self) -> Option<&str> {self.color_range.as_ref().map(|color| color.as_str())}
+    pub fn tags(&self) -> &BTreeMap<String, String> {&self.tags}
+    pub fn side_data(&self) -> &Vec<Value> {&self.side_data}
+
+    //true if other's packets can be joined to this stream's without re-encoding, the concat demuxer keeps the first part's parameters for all of them.
+    pub fn joins_with(&self, other: &StreamInfo) -> bool {
+        self.codec.is_some() && self.codec == other.codec && self.profile == other.profile && self.pixel_format == other.pixel_format
+            && self.width == other.width && self.height == other.height && self.time_base == other.time_base
+    }
+}
+
+impl MediaInfo {
+    //probed is ffprobe's output with -show_format & -show_streams.
+    pub fn from_json(probed: &Value) -> Result<MediaInfo, VideoError> {
+        let streams = probed["streams"].as_array().ok_or(format!("ffprobe error, no streams were reported:\n{}", probed))?;
+        let format = &probed["format"];
+        Ok(MediaInfo {
+            format: text(&format["format_name"]),
+            duration: number(&format["duration"]),
+            bit_rate: whole(&format["bit_rate"]),
+            tags: tags(&format["tags"]),
+            streams: streams.iter().map(StreamInfo::from_json).collect(),
+        })
+    }
+
+    pub fn format(&self) -> Option<&str> {self.format.as_ref().map(|format| format.as_str())}
+    pub fn bit_rate(&self) -> Option<u64> {self.bit_rate}
+    pub fn tags(&self) -> &BTreeMap<String, String> {&self.tags}
+    pub fn streams(&self) -> &Vec<StreamInfo> {&self.streams}
+
+    //the first real video stream, cover art doesn't count.
+    pub fn video_stream(&self) -> Result<&StreamInfo, VideoError> {
+        self.streams.iter().find(|stream| stream.kind == "video" && !stream.attached_picture)
+            .ok_or(format!("ffprobe error, no video stream was found.").into())
+    }
+
+    pub fn audio_streams(&self) -> Vec<&StreamInfo> {
+        self.streams.iter().filter(|stream| stream.kind == "audio").collect()
+    }
+
+    //the container's duration, or the video stream's if the container doesn't have one.
+    pub fn duration(&self) -> Result<f64, VideoError> {
+        match self.duration {
+            Some(duration) => Ok(duration),
+            None => self.video_stream()?.duration.ok_or(format!("ffprobe error, could not get duration.").into())
+        }
+    }
+
+    //the video stream's base frame rate, falling back to its average frame rate.
+    pub fn fps(&self) -> Result<f64, VideoError> {
+        let stream = self.video_stream()?;
+        stream.r_frame_rate.or(stream.avg_frame_rate).ok_or(format!("ffprobe error, could not get fps.").into())
+    }
+
+    //the number of frames the video stream reports, estimated from duration & fps when the container doesn't store it.
+    pub fn frame_count(&self) -> Result<u64, VideoError> {
+        match self.video_stream()?.frame_count {
+            Some(count) => Ok(count),
+            None => Ok((self.duration()? * self.fps()?).ceil() as u64)
+        }
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value.as_str() {
+        Some(text) if !text.is_empty() && text != "N/A" => Some(text.to_string()),
+        _ => None
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::String(ref text) => text.parse::<f64>().ok(),
+        ref other => other.as_f64()
+    }
+}
+
+fn whole(value: &Value) -> Option<u64> {
+    match *value {
+        Value::String(ref text) => text.parse::<u64>().ok(),
+        ref other => other.as_u64()
+    }
+}
+
+//frame rates are fractions like 30000/1001, 0/0 means unknown.
+fn rate(value: &Value) -> Option<f64> {
+    let text = value.as_str()?;
+    let mut parts = text.splitn(2, '/');
+    let numerator = parts.next()?.parse::<f64>().ok()?;
+    let denominator = parts.next().unwrap_or("1").parse::<f64>().ok()?;
+    if numerator > 0f64 && denominator > 0f64 { Some(numerator / denominator) } else { None }
+}
+
+fn tags(value: &Value) -> BTreeMap<String, String> {
+    match value.as_object() {
+        Some(tags) => tags.iter().filter_map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_string()))).collect(),
+        None => BTreeMap::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_ffprobe_output() {
+        let probed = json!({
+            "streams": [
+                {"index": 0, "codec_type": "video", "codec_name": "mjpeg", "r_frame_rate": "90000/1", "disposition": {"attached_pic": 1}},
+                {"index": 1, "codec_type": "video", "codec_name": "h264", "profile": "High", "pix_fmt": "yuv420p", "time_base": "1/30000",
+                 "width": 1920, "height": 1080, "r_frame_rate": "30000/1001",
+                 "avg_frame_rate": "30000/1001", "duration": "10.010000", "nb_frames": "300", "tags": {"rotate": "90"}},
+                {"index": 2, "codec_type": "audio", "codec_name": "aac", "channels": 2, "sample_rate": "48000", "bit_rate": "128000"}
+            ],
+            "format": {"format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "10.026667", "bit_rate": "2200000", "tags": {"title": "lounge"}}
+        });
+        let info = MediaInfo::from_json(&probed).unwrap();
+        let video = info.video_stream().unwrap();
+        assert_eq!(video.codec(), Some("h264"));
+        assert_eq!((video.profile(), video.pixel_format(), video.time_base()), (Some("High"), Some("yuv420p"), Some("1/30000")));
+        assert!(video.joins_with(video));
+        assert!(!video.joins_with(&StreamInfo::from_json(&json!({"codec_type": "video", "codec_name": "h264", "profile": "High 4:4:4 Predictive",
+                                                                   "pix_fmt": "yuv444p", "time_base": "1/30000", "width": 1920, "height": 1080}))));
+        assert_eq!(video.tags().get("rotate").map(|rotate| rotate.as_str()), Some("90"));
+        assert!((info.fps().unwrap() - 29.97).abs() < 0.01);
+        assert_eq!(info.duration().unwrap(), 10.026667);
+        assert_eq!(info.frame_count().unwrap(), 300);
+        assert_eq!(info.audio_streams()[0].channels(), Some(2));
+        assert_eq!(info.tags().get("title").map(|title| title.as_str()), Some("lounge"));
+    }
+}
@@ -4,4 +4,17 @@ pub mod alter;
 pub mod extract;
 pub mod prelude;
 pub mod advanced_input;
-pub mod ffmpeg;
\ No newline at end of file
+pub mod ffmpeg;
+pub mod objective;
+pub mod processor;
+pub mod sampling;
+pub mod segments;
+pub mod encoding;
+pub mod properties;
+pub mod media_info;
+pub mod audio;
+pub mod extract_format;
+pub mod overlay;
+pub mod events;
+pub mod frame_context;
+pub mod dedup;
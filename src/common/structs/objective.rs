@@ -0,0 +1,137 @@
+use serde_json::Value;
+use common::video_error::VideoError;
+use common::structs::advanced_input::AdvancedInput;
+use processing;
+
+static TRANSFORM: &'static str = "transform";
+static EXTRACT: &'static str = "extract";
+static AUDIO_TRANSFORM: &'static str = "audio_transform";
+static AUDIO_EXTRACT: &'static str = "audio_extract";
+static OVERLAY: &'static str = "overlay";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    Transform,
+    Extract,
+    //windows of the audio track are altered & reassembled into the output's audio.
+    AudioTransform,
+    //each window of the audio track is described, results are saved as json.
+    AudioExtract,
+    //frames are described like extract, then the results are drawn onto them & encoded like transform.
+    Overlay,
+}
+
+impl Objective {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Objective::Transform => TRANSFORM,
+            Objective::Extract => EXTRACT,
+            Objective::AudioTransform => AUDIO_TRANSFORM,
+            Objective::AudioExtract => AUDIO_EXTRACT,
+            Objective::Overlay => OVERLAY,
+        }
+    }
+
+    //the audio objectives process windows of the audio track instead of video frames.
+    pub fn is_audio(&self) -> bool {
+        *self == Objective::AudioTransform || *self == Objective::AudioExtract
+    }
+
+    //the objectives that encode processed frames into the output video.
+    pub fn encodes_frames(&self) -> bool {
+        *self == Objective::Transform || *self == Objective::Overlay
+    }
+
+    pub fn from_name(mode: &str) -> Result<Objective, VideoError> {
+        let lowered = mode.trim().to_lowercase();
+        if lowered == TRANSFORM {
+            Ok(Objective::Transform)
+        } else if lowered == EXTRACT {
+            Ok(Objective::Extract)
+        } else if lowered == AUDIO_TRANSFORM {
+            Ok(Objective::AudioTransform)
+        } else if lowered == AUDIO_EXTRACT {
+            Ok(Objective::AudioExtract)
+        } else if lowered == OVERLAY {
+            Ok(Objective::Overlay)
+        } else {
+            Err(format!("early exit:\nmode '{}' is not supported, must be one of: {}, {}, {}, {}, {}.", mode, TRANSFORM, EXTRACT, AUDIO_TRANSFORM, AUDIO_EXTRACT, OVERLAY).into())
+        }
+    }
+
+    //picks the objective for a request, if a mode was provided it's checked against the algorithm's defaults and the advanced_input keywords, otherwise we infer it from them.
+    pub fn select(mode: Option<&str>, algorithm: &str, advanced_input: Option<&Value>) -> Result<Objective, VideoError> {
+        match mode {
+            Some(mode) => {
+                let objective = Objective::from_name(mode)?;
+                objective.validate(algorithm, advanced_input)?;
+                Ok(objective)
+            }
+            None => Objective::infer(algorithm, advanced_input)
+        }
+    }
+
+    fn infer(algorithm: &str, advanced_input: Option<&Value>) -> Result<Objective, VideoError> {
+        match advanced_input {
+            Some(json) => {
+                if AdvancedInput::has_audio_input(json)? {
+                    let objective = if AdvancedInput::has_audio_output(json)? { Objective::AudioTransform } else { Objective::AudioExtract };
+                    objective.validate(algorithm, advanced_input)?;
+                    Ok(objective)
+                } else if AdvancedInput::create_transform(json).is_ok() {
+                    Ok(Objective::Transform)
+                } else {
+                    Objective::Extract.validate(algorithm, advanced_input)?;
+                    Ok(Objective::Extract)
+                }
+            }
+            None => {
+                if processing::transform_default(algorithm).is_some() {
+                    Ok(Objective::Transform)
+                } else if processing::extract_default(algorithm).is_some() {
+                    Ok(Objective::Extract)
+                } else {
+                    Err(format!("early exit:\nNo default algorithm definition for {}, advanced_input required.", algorithm).into())
+                }
+            }
+        }
+    }
+
+    fn validate(&self, algorithm: &str, advanced_input: Option<&Value>) -> Result<(), VideoError> {
+        match (*self, advanced_input) {
+            (Objective::Transform, Some(json)) => {
+                AdvancedInput::create_transform(json)
+                    .map_err(|err| format!("early exit:\nadvanced_input is not valid for {} mode:\n{}", self.name(), err))?;
+                Ok(())
+            }
+            (Objective::Extract, Some(json)) | (Objective::Overlay, Some(json)) => {
+                AdvancedInput::create_extract(json)
+                    .map_err(|err| format!("early exit:\nadvanced_input is not valid for {} mode:\n{}", self.name(), err))?;
+                if AdvancedInput::has_output_keywords(json)? {
+                    Err(format!("early exit:\nadvanced_input contains $SINGLE_OUTPUT or $BATCH_OUTPUT, which are not used in {} mode.", self.name()).into())
+                } else {
+                    Ok(())
+                }
+            }
+            (Objective::AudioTransform, Some(json)) => {
+                AdvancedInput::create_audio_transform(json)
+                    .map_err(|err| format!("early exit:\nadvanced_input is not valid for {} mode:\n{}", self.name(), err))?;
+                Ok(())
+            }
+            (Objective::AudioExtract, Some(json)) => {
+                AdvancedInput::create_audio_extract(json)
+                    .map_err(|err| format!("early exit:\nadvanced_input is not valid for {} mode:\n{}", self.name(), err))?;
+                if AdvancedInput::has_audio_output(json)? {
+                    Err(format!("early exit:\nadvanced_input contains $AUDIO_OUTPUT, which is not used in {} mode.", self.name()).into())
+                } else {
+                    Ok(())
+                }
+            }
+            (Objective::Transform, None) if processing::transform_default(algorithm).is_some() => Ok(()),
+            (Objective::Extract, None) | (Objective::Overlay, None) if processing::extract_default(algorithm).is_some() => Ok(()),
+            (_, None) => {
+                Err(format!("early exit:\nNo default {} definition for {}, advanced_input required.", self.name(), algorithm).into())
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use common::video_error::VideoError;
+use serde_json::{Map, Value};
+
+//where the boxes, labels & scores are in each frame's extract result, and how they're drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    //json pointers, boxes into the frame's data and the rest into each box.
+    boxes: String,
+    left: String,
+    top: String,
+    //a box is either left, top, width & height or left, top, right & bottom.
+    width: Option<String>,
+    height: Option<String>,
+    right: Option<String>,
+    bottom: Option<String>,
+    label: Option<String>,
+    score: Option<String>,
+    //coordinates are fractions of the frame's width & height rather than pixels.
+    normalized: bool,
+    color: String,
+    thickness: u64,
+    font_size: u64,
+}
+
+impl Overlay {
+    //json is an object like {"boxes": "/objects", "left": "/bbox/x0", "top": "/bbox/y0", "right": "/bbox/x1", "bottom": "/bbox/y1", "label": "/class"},
+    //every field is optional, by default boxes look like {"x": 10, "y": 20, "width": 100, "height": 50, "label": "dog", "score": 0.9}.
+    pub fn from_request(json: Option<&Value>) -> Result<Overlay, VideoError> {
+        let empty = Map::new();
+        let object = match json {
+            None => &empty,
+            Some(&Value::Object(ref object)) => object,
+            Some(_) => return Err(format!("early exit:\noverlay must be an object mapping the result's fields, like {{\"boxes\": \"/objects\", \"label\": \"/class\"}}.").into())
+        };
+        let pointer = |name: &str| -> Result<Option<String>, VideoError> {
+            match object.get(name) {
+                Some(&Value::String(ref field)) if field.is_empty() || field.starts_with('/') => Ok(Some(field.clone())),
+                Some(&Value::String(ref field)) => Ok(Some(format!("/{}", field))),
+                Some(&Value::Null) | None => Ok(None),
+                Some(other) => Err(format!("early exit:\noverlay {} must be a json pointer like '/bbox/x0', got {}.", name, other).into())
+            }
+        };
+        let (width, height, right, bottom) = (pointer("width")?, pointer("height")?, pointer("right")?, pointer("bottom")?);
+        let (width, height) = match (width, height, right.is_some() || bottom.is_some()) {
+            (None, None, true) => {
+                if right.is_none() || bottom.is_none() {
+                    return Err(format!("early exit:\noverlay needs both right & bottom.").into())
+                }
+                (None, None)
+            }
+            (_, _, true) => return Err(format!("early exit:\noverlay boxes take either width & height or right & bottom, not both.").into()),
+            (width, height, false) => (Some(width.unwrap_or("/width".to_string())), Some(height.unwrap_or("/height".to_string())))
+        };
+        let number = |name: &str, default: u64| -> Result<u64, VideoError> {
+            match object.get(name) {
+                Some(value) => value.as_u64().ok_or(format!("early exit:\noverlay {} must be a positive whole number.", name).into()),
+                None => Ok(default)
+            }
+        };
+        let color = match object.get("color") {
+            Some(&Value::String(ref color)) if !color.is_empty() && color.chars().all(|c| c.is_alphanumeric() || c == '#' || c == '@' || c == '.') => color.clone(),
+            Some(other) => return Err(format!("early exit:\noverlay color must be a color name or hex code like '#00ff00', got {}.", other).into()),
+            None => "red".to_string()
+        };
+        let normalized = match object.get("normalized") {
+            Some(&Value::Bool(normalized)) => normalized,
+            Some(_) => return Err(format!("early exit:\noverlay normalized must be true or false.").into()),
+            None => false
+        };
+        Ok(Overlay {
+            boxes: pointer("boxes")?.unwrap_or("/boxes".to_string()),
+            left: pointer("left")?.unwrap_or("/x".to_string()),
+            top: pointer("top")?.unwrap_or("/y".to_string()),
+            width: width,
+            height: height,
+            right: right,
+            bottom: bottom,
+            label: match object.get("label") { Some(_) => pointer("label")?, None => Some("/label".to_string()) },
+            score: match object.get("score") { Some(_) => pointer("score")?, None => Some("/score".to_string()) },
+            normalized: normalized,
+            color: color,
+            thickness: number("thickness", 3)?,
+            font_size: number("font_size", 16)?,
+        })
+    }
+
+    //the drawbox & drawtext filters for one frame's extract result, None if it has no boxes to draw.
+    pub fn filters(&self, data: &Value) -> Option<String> {
+        let boxes: Vec<&Value> = match data.pointer(&self.boxes) {
+            Some(&Value::Array(ref boxes)) => boxes.iter().collect(),
+            //a single box doesn't have to be in an array.
+            Some(single @ &Value::Object(_)) => vec![single],
+            _ => return None
+        };
+        let mut filters: Vec<String> = Vec::new();
+        for detection in boxes {
+            let coordinate = |field: &String| detection.pointer(field).and_then(|value| value.as_f64());
+            let (left, top) = match (coordinate(&self.left), coordinate(&self.top)) {
+                (Some(left), Some(top)) => (left, top),
+                //boxes without a position are skipped rather than failing the frame.
+                _ => continue
+            };
+            let size = match (self.width.as_ref(), self.height.as_ref(), self.right.as_ref(), self.bottom.as_ref()) {
+                (Some(width), Some(height), _, _) => coordinate(width).and_then(|width| coordinate(height).map(|height| (width, height))),
+                (_, _, Some(right), Some(bottom)) => coordinate(right).and_then(|right| coordinate(bottom).map(|bottom| (right - left, bottom - top))),
+                _ => None
+            };
+            let (width, height) = match size {
+                Some((width, height)) if width > 0f64 && height > 0f64 => (width, height),
+                _ => continue
+            };
+            filters.push(format!("drawbox=x={}:y={}:w={}:h={}:color={}:t={}",
+                                 self.position(left, "iw"), self.position(top, "ih"), self.position(width, "iw"), self.position(height, "ih"),
+                                 self.color, self.thickness));
+            let label = self.label.as_ref().and_then(|field| detection.pointer(field)).and_then(|label| text(label));
+            let score = self.score.as_ref().and_then(|field| detection.pointer(field)).and_then(|score| score.as_f64());
+            let caption = match (label, score) {
+                (Some(label), Some(score)) => Some(format!("{} {:.2}", label, score)),
+                (Some(label), None) => Some(label),
+                (None, Some(score)) => Some(format!("{:.2}", score)),
+                (None, None) => None
+            };
+            if let Some(caption) = caption {
+                //the caption sits on top of the box, or just inside it when the box touches the top of the frame.
+                let y = format!("max(0\\,{}-text_h-{})", self.position(top, "h"), self.thickness);
+                filters.push(format!("drawtext=text={}:expansion=none:x={}:y={}:fontsize={}:fontcolor=white:box=1:boxcolor={}:boxborderw={}",
+                                     escape(&caption), self.position(left, "w"), y, self.font_size, self.color, self.thickness));
+            }
+        }
+        if filters.is_empty() { None } else { Some(filters.join(",")) }
+    }
+
+    //pixels as they are, fractions as an expression of the frame size.
+    fn position(&self, value: f64, size: &str) -> String {
+        if self.normalized { format!("{}*{}", size, value) } else { format!("{}", value.round()) }
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        Value::String(_) | Value::Null => None,
+        ref other => Some(other.to_string())
+    }
+}
+
+//drawtext's text is escaped once as an option value & again as part of the filtergraph.
+fn escape(text: &str) -> String {
+    let mut value = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | '\'' | ':' => { value.push('\\'); value.push(c); }
+            '\n' | '\r' => value.push(' '),
+            _ => value.push(c)
+        }
+    }
+    let mut graph = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' | '\'' | '[' | ']' | ',' | ';' => { graph.push('\\'); graph.push(c); }
+            _ => graph.push(c)
+        }
+    }
+    graph
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boxes_are_mapped_onto_filters() {
+        let overlay = Overlay::from_request(Some(&json!({"boxes": "objects", "left": "/bbox/x0", "top": "/bbox/y0", "right": "/bbox/x1",
+                                                          "bottom": "/bbox/y1", "label": "/class", "score": "/confidence"}))).unwrap();
+        let data = json!({"objects": [{"bbox": {"x0": 10, "y0": 20, "x1": 110, "y1": 70}, "class": "dog", "confidence": 0.912}]});
+        assert_eq!(overlay.filters(&data).unwrap(),
+                   "drawbox=x=10:y=20:w=100:h=50:color=red:t=3,drawtext=text=dog 0.91:expansion=none:x=10:y=max(0\\,20-text_h-3):fontsize=16:fontcolor=white:box=1:boxcolor=red:boxborderw=3");
+        assert!(overlay.filters(&json!({"objects": []})).is_none());
+        assert!(Overlay::from_request(Some(&json!({"width": "/w", "right": "/x1"}))).is_err());
+    }
+}
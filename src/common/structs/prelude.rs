@@ -3,4 +3,20 @@ pub use super::alter::Altered as Altered;
 pub use super::extract::Extract as Extract;
 pub use super::gathered::Gathered as Gathered;
 pub use super::scattered::Scattered as Scattered;
-pub use super::advanced_input::AdvancedInput as AdvancedInput;
\ No newline at end of file
+pub use super::advanced_input::AdvancedInput as AdvancedInput;
+pub use super::objective::Objective as Objective;
+pub use super::processor::ImageProcessor as ImageProcessor;
+pub use super::processor::FrameBatch as FrameBatch;
+pub use super::sampling::Sampling as Sampling;
+pub use super::segments::TimeRange as TimeRange;
+pub use super::segments::Part as Part;
+pub use super::encoding::OutputEncoding as OutputEncoding;
+pub use super::properties::VideoProperties as VideoProperties;
+pub use super::media_info::MediaInfo as MediaInfo;
+pub use super::media_info::StreamInfo as StreamInfo;
+pub use super::audio::AudioTrack as AudioTrack;
+pub use super::extract_format::ExtractOutput as ExtractOutput;
+pub use super::overlay::Overlay as Overlay;
+pub use super::events::EventRules as EventRules;
+pub use super::frame_context::FrameContext as FrameContext;
+pub use super::dedup::Dedup as Dedup;
@@ -1,26 +1,245 @@
-
 use algorithmia::Algorithmia;
+use algorithmia::algo::*;
 use common::video_error::VideoError;
-use common::json_utils::AdvancedInput;
-use std_semaphore::Semaphore;
+use common::structs::advanced_input::AdvancedInput;
+use common::algo::{try_algorithm, batch_upload_file, batch_get_file};
+use common::storage::{self, Storage};
+use common::threading::Terminator;
+use common::concurrency::ConcurrencyController;
+use std::process::Command;
+use std::sync::Arc;
+use std::path::*;
+use serde_json::{self, Value};
+use either::{Left, Right};
+use common::structs::frame_context::{FrameContext, Variables};
+
+static LOCAL_SCHEME: &'static str = "exec";
+
+//the local frames a single batch works on, outputs are empty for extract.
+pub struct FrameBatch {
+    pub inputs: Vec<PathBuf>,
+    pub outputs: Vec<PathBuf>,
+    //frame numbers of the inputs, starting at 1.
+    pub frames: Vec<usize>,
+    //the local input frame before each input, None for the first frame.
+    pub previous: Vec<Option<PathBuf>>,
+    pub context: Arc<FrameContext>,
+}
+
+impl FrameBatch {
+    //the template's frame context keywords for input index of this batch, previous being the frame before it as the algorithm sees it.
+    fn single_variables(&self, index: usize, previous: &Vec<Option<String>>) -> Variables {
+        self.context.single(self.frames[index], previous[index].as_ref().map(|previous| previous.as_str()))
+    }
+}
+
+//everything that runs an algorithm over frames goes through this, the advanced input decides whether frames are sent one at a time or as a batch.
+pub trait ImageProcessor: Send + Sync {
+    //alters each input frame, writing the result to the matching output path.
+    fn transform(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+                 error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<PathBuf>, VideoError>;
+    //returns one json result per input frame.
+    fn extract(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+               error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<Value>, VideoError>;
+}
+
+//uploads frames to a remote working directory and pipes them through an Algorithmia algorithm.
+pub struct AlgorithmiaProcessor {
+    client: Algorithmia,
+    storage: Arc<Storage>,
+    remote_working_directory: String,
+}
+
+//runs a local executable over the frame files directly, the advanced input is its list of arguments.
+pub struct LocalProcessor;
+
+impl AlgorithmiaProcessor {
+    pub fn new(client: Algorithmia, storage: Arc<Storage>, remote_working_directory: &str) -> AlgorithmiaProcessor {
+        AlgorithmiaProcessor { client: client, storage: storage, remote_working_directory: String::from(remote_working_directory) }
+    }
+
+    //the remote paths of the frames before each input, which are uploaded too if the template refers to them.
+    fn upload_previous(&self, template: &AdvancedInput, frames: &FrameBatch, error_poll: Terminator) -> Result<Vec<Option<String>>, VideoError> {
+        if !template.uses_previous_frame() {
+            return Ok(frames.previous.iter().map(|_| None).collect())
+        }
+        let missing: Vec<PathBuf> = frames.previous.iter().filter_map(|previous| previous.clone())
+            .filter(|previous| !frames.inputs.contains(previous)).collect();
+        if !missing.is_empty() {
+            batch_upload_file(&missing, &self.remote_paths(&missing), self.storage.as_ref(), error_poll)?;
+        }
+        Ok(frames.previous.iter().map(|previous| previous.as_ref().map(|previous| self.remote_paths(&vec![previous.clone()]).remove(0))).collect())
+    }
+
+    fn remote_paths(&self, local: &Vec<PathBuf>) -> Vec<String> {
+        local.iter().map(|path| {
+            format!("{}/{}", self.remote_working_directory, path.file_name().unwrap().to_str().unwrap())
+        }).collect::<Vec<String>>()
+    }
+}
 
-struct ProcessorDefault<T, J> {
-    algo: Fn(&T, Vec<usize>, Arc<Semaphore>) -> Result<Vec<J>, VideoError> + Sync,
-    payload: T,
-    batch: Vec<usize>,
-    semaphore: Arc<Semaphore>,
+impl ImageProcessor for AlgorithmiaProcessor {
+    fn transform(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+                 error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<PathBuf>, VideoError> {
+        let remote_pre_frames = self.remote_paths(&frames.inputs);
+        let remote_post_frames = self.remote_paths(&frames.outputs);
+        batch_upload_file(&frames.inputs, &remote_pre_frames, self.storage.as_ref(), error_poll.clone())?;
+        let previous = self.upload_previous(template, frames, error_poll.clone())?;
+        if template.option() == "batch" {
+            let json: Value = template.replace_variables_transform(Left(&remote_pre_frames), Left(&remote_post_frames), &frames.context.batch(&frames.frames))?;
+            try_algorithm(&self.client, algorithm, &json, error_poll.clone(), &controller)?;
+        } else {
+            for (index, (pre, post)) in remote_pre_frames.iter().zip(remote_post_frames.iter()).enumerate() {
+                let json: Value = template.replace_variables_transform(Right(pre), Right(post), &frames.single_variables(index, &previous))?;
+                try_algorithm(&self.client, algorithm, &json, error_poll.clone(), &controller)?;
+            }
+        }
+        batch_get_file(&frames.outputs, &remote_post_frames, self.storage.as_ref(), error_poll)
+    }
+
+    fn extract(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+               error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<Value>, VideoError> {
+        let remote_frames = self.remote_paths(&frames.inputs);
+        batch_upload_file(&frames.inputs, &remote_frames, self.storage.as_ref(), error_poll.clone())?;
+        let previous = self.upload_previous(template, frames, error_poll.clone())?;
+        if template.option() == "batch" {
+            let json: Value = template.replace_variables_extract(Left(&remote_frames), &frames.context.batch(&frames.frames))?;
+            let response: AlgoResponse = try_algorithm(&self.client, algorithm, &json, error_poll, &controller)?;
+            let output_json: Value = response.into_json()
+                .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
+            batch_results(output_json, remote_frames.len())
+        } else {
+            let mut output: Vec<Value> = Vec::new();
+            for (index, frame) in remote_frames.iter().enumerate() {
+                let json: Value = template.replace_variables_extract(Right(frame), &frames.single_variables(index, &previous))?;
+                let response: AlgoResponse = try_algorithm(&self.client, algorithm, &json, error_poll.clone(), &controller)?;
+                let output_json: Value = response.into_json()
+                    .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
+                output.push(output_json);
+            }
+            Ok(output)
+        }
+    }
+}
+
+impl LocalProcessor {
+    //exec://convert -> convert, exec:///usr/local/bin/filter.py -> /usr/local/bin/filter.py
+    fn program(algorithm: &str) -> &str {
+        storage::strip_scheme(algorithm)
+    }
+
+    fn run(algorithm: &str, arguments: &Value, error_poll: Terminator, controller: &ConcurrencyController) -> Result<Vec<u8>, VideoError> {
+        if error_poll.check_signal().is_some() { return Err(format!("already received an error.").into()) }
+        let args = flatten_arguments(arguments)?;
+        let permit = controller.acquire();
+        let response = Command::new(LocalProcessor::program(algorithm)).args(&args).output();
+        let response = response.map_err(|err| format!("local process {} failed to start:\n{}", algorithm, err))?;
+        if response.status.success() {
+            permit.succeeded();
+            Ok(response.stdout)
+        } else {
+            Err(format!("local process {} failed: \n{}", algorithm, String::from_utf8_lossy(&response.stderr)).into())
+        }
+    }
 }
 
-struct ProcessorAdavnced<T, J> {
-    algo: Fn(&T, Vec<usize>, String, &AdvancedInput, Arc<Semaphore>) -> Result<Vec<J>, VideoError> + Sync,
-    algo_name: String,
-    payload: T,
-    batch: Vec<usize>,
-    json: AdvancedInput,
-    semaphore: Arc<Semaphore>,
+impl ImageProcessor for LocalProcessor {
+    fn transform(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+                 error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<PathBuf>, VideoError> {
+        let inputs = path_strings(&frames.inputs);
+        let outputs = path_strings(&frames.outputs);
+        let previous = local_previous(frames);
+        if template.option() == "batch" {
+            let arguments: Value = template.replace_variables_transform(Left(&inputs), Left(&outputs), &frames.context.batch(&frames.frames))?;
+            LocalProcessor::run(algorithm, &arguments, error_poll, &controller)?;
+        } else {
+            for (index, (input, output)) in inputs.iter().zip(outputs.iter()).enumerate() {
+                let arguments: Value = template.replace_variables_transform(Right(input), Right(output), &frames.single_variables(index, &previous))?;
+                LocalProcessor::run(algorithm, &arguments, error_poll.clone(), &controller)?;
+            }
+        }
+        match frames.outputs.iter().find(|output| !output.exists()) {
+            Some(missing) => Err(format!("local process {} did not create the output frame {}", algorithm, missing.display()).into()),
+            None => Ok(frames.outputs.clone())
+        }
+    }
+
+    fn extract(&self, algorithm: &str, template: &AdvancedInput, frames: &FrameBatch,
+               error_poll: Terminator, controller: Arc<ConcurrencyController>) -> Result<Vec<Value>, VideoError> {
+        let inputs = path_strings(&frames.inputs);
+        let previous = local_previous(frames);
+        if template.option() == "batch" {
+            let arguments: Value = template.replace_variables_extract(Left(&inputs), &frames.context.batch(&frames.frames))?;
+            let stdout = LocalProcessor::run(algorithm, &arguments, error_poll, &controller)?;
+            let output_json: Value = serde_json::from_slice(&stdout)
+                .map_err(|err| format!("local process {} did not print valid json:\n{}", algorithm, err))?;
+            batch_results(output_json, inputs.len())
+        } else {
+            let mut output: Vec<Value> = Vec::new();
+            for (index, input) in inputs.iter().enumerate() {
+                let arguments: Value = template.replace_variables_extract(Right(input), &frames.single_variables(index, &previous))?;
+                let stdout = LocalProcessor::run(algorithm, &arguments, error_poll.clone(), &controller)?;
+                let output_json: Value = serde_json::from_slice(&stdout)
+                    .map_err(|err| format!("local process {} did not print valid json:\n{}", algorithm, err))?;
+                output.push(output_json);
+            }
+            Ok(output)
+        }
+    }
+}
+
+//exec:// algorithms run locally, everything else is an Algorithmia algorithm.
+pub fn processor_for(algorithm: &str, client: &Algorithmia, storage: Arc<Storage>, remote_working_directory: &str) -> Arc<ImageProcessor> {
+    if is_local(algorithm) {
+        Arc::new(LocalProcessor)
+    } else {
+        Arc::new(AlgorithmiaProcessor::new(client.clone(), storage, remote_working_directory))
+    }
 }
 
+pub fn is_local(algorithm: &str) -> bool {
+    storage::scheme(algorithm).map(|scheme| scheme == LOCAL_SCHEME).unwrap_or(false)
+}
 
-trait Executable<J> {
-    fn execute(&self) -> Result<Vec<J>, VideoError> + Sync;
-}
\ No newline at end of file
+//a batch call must return an array with one result per frame, otherwise we can't tell which result belongs to which frame.
+fn batch_results(output_json: Value, expected: usize) -> Result<Vec<Value>, VideoError> {
+    let output: Vec<Value> = output_json.as_array()
+        .ok_or(format!("algorithm failed, ending early:\n batch response was not a json array."))?
+        .iter().map(|dat| { dat.clone() }).collect::<Vec<_>>();
+    if output.len() != expected {
+        Err(format!("algorithm failed, ending early:\n batch response contained {} results for {} frames.", output.len(), expected).into())
+    } else {
+        Ok(output)
+    }
+}
+
+//local processes read the previous frame straight from the frames directory.
+fn local_previous(frames: &FrameBatch) -> Vec<Option<String>> {
+    frames.previous.iter().map(|previous| previous.as_ref().map(|previous| previous.to_str().unwrap().to_string())).collect()
+}
+
+fn path_strings(paths: &Vec<PathBuf>) -> Vec<String> {
+    paths.iter().map(|path| path.to_str().unwrap().to_string()).collect::<Vec<String>>()
+}
+
+//the advanced input for a local process is an array of arguments, batch keywords expand into one argument per frame.
+fn flatten_arguments(arguments: &Value) -> Result<Vec<String>, VideoError> {
+    let array = arguments.as_array()
+        .ok_or(format!("advanced_input for exec:// algorithms must be an array of command line arguments."))?;
+    let mut args: Vec<String> = Vec::new();
+    for argument in array.iter() {
+        match argument {
+            &Value::String(ref text) => args.push(text.clone()),
+            &Value::Array(ref batch) => {
+                for item in batch.iter() {
+                    args.push(item.as_str().map(|text| text.to_string()).unwrap_or(item.to_string()));
+                }
+            }
+            &Value::Object(_) => {
+                return Err(format!("advanced_input for exec:// algorithms can't contain json objects.").into())
+            }
+            other => args.push(other.to_string()),
+        }
+    }
+    Ok(args)
+}
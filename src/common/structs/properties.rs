@@ -0,0 +1,139 @@
+use common::structs::media_info::StreamInfo;
+use common::structs::encoding::OutputEncoding;
+
+//properties of the original video stream that frames don't carry, so they're re-applied when the frames are encoded & remuxed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VideoProperties {
+    //degrees clockwise a player turns the stored picture, one of 0, 90, 180 or 270.
+    rotation: u64,
+    sample_aspect_ratio: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    color_space: Option<String>,
+    color_range: Option<String>,
+}
+
+impl VideoProperties {
+    pub fn from_stream(stream: &StreamInfo) -> VideoProperties {
+        //older files carry a rotate tag, newer ones a display matrix that turns counter clockwise.
+        let tagged = stream.tags().get("rotate").and_then(|rotate| rotate.parse::<i64>().ok());
+        let matrix = stream.side_data().iter().filter_map(|data| data["rotation"].as_i64()).next().map(|rotation| -rotation);
+        let rotation = match tagged.or(matrix).map(|rotation| ((rotation % 360) + 360) % 360) {
+            Some(rotation) if rotation == 90 || rotation == 180 || rotation == 270 => rotation as u64,
+            _ => 0
+        };
+        let sample_aspect_ratio = match stream.sample_aspect_ratio() {
+            Some(ratio) if ratio != "1:1" && ratio != "0:1" => Some(ratio.to_string()),
+            _ => None
+        };
+        VideoProperties {
+            rotation: rotation,
+            sample_aspect_ratio: sample_aspect_ratio,
+            color_primaries: known(stream.color_primaries()),
+            color_transfer: known(stream.color_transfer()),
+            color_space: known(stream.color_space()),
+            color_range: known(stream.color_range()),
+        }
+    }
+
+    pub fn rotation(&self) -> u64 {self.rotation}
+
+    //ffmpeg turns frames upright when it splits a rotated video, so they're turned back before encoding & the rotation is restored when remuxing.
+    //that keeps the output's stored picture, sar & color matrix identical to the original, which untouched parts copied from it rely on.
+    //a spliced encoding also scales the frames to the original's stored size.
+    pub fn encode_args(&self, encoding: &OutputEncoding) -> Vec<String> {
+        let pixel_format = encoding.pixel_format();
+        let mut filters: Vec<String> = Vec::new();
+        match self.rotation {
+            90 => filters.push("transpose=cclock".to_string()),
+            180 => filters.push("hflip,vflip".to_string()),
+            270 => filters.push("transpose=clock".to_string()),
+            _ => {}
+        }
+        if let Some((width, height)) = encoding.size() {
+            filters.push(format!("scale={}:{}", width, height));
+        }
+        if let Some(ref ratio) = self.sample_aspect_ratio {
+            filters.push(format!("setsar={}", ratio.replace(":", "/")));
+        }
+        let mut args: Vec<String> = Vec::new();
+        //frames are rgb, the matrix & range only mean something when converting to yuv.
+        if pixel_format.starts_with("yuv") {
+            let matrix = self.color_space.as_ref().and_then(|space| color_matrix(space));
+            if matrix.is_some() || self.color_range.is_some() {
+                let mut scale: Vec<String> = Vec::new();
+                if let Some(matrix) = matrix {
+                    scale.push(format!("out_color_matrix={}", matrix));
+                }
+                if let Some(ref range) = self.color_range {
+                    scale.push(format!("out_range={}", range));
+                }
+                filters.push(format!("scale={}", scale.join(":")));
+                filters.push(format!("format={}", pixel_format));
+            }
+            let tags = [("-color_primaries", &self.color_primaries), ("-color_trc", &self.color_transfer),
+                        ("-colorspace", &self.color_space), ("-color_range", &self.color_range)];
+            for &(flag, value) in tags.iter() {
+                if let &Some(ref value) = value {
+                    args.push(flag.to_string());
+                    args.push(value.clone());
+                }
+            }
+        }
+        if !filters.is_empty() {
+            args.push("-vf".to_string());
+            args.push(filters.join(","));
+        }
+        args
+    }
+
+    //container metadata & chapters come from input 1, the original video, when remuxing.
+    pub fn remux_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = vec!["-map_metadata".to_string(), "1".to_string(), "-map_chapters".to_string(), "1".to_string()];
+        if self.rotation != 0 {
+            args.push("-metadata:s:v:0".to_string());
+            args.push(format!("rotate={}", self.rotation));
+        }
+        args
+    }
+}
+
+fn known(value: Option<&str>) -> Option<String> {
+    match value {
+        Some(value) if value != "unknown" && value != "reserved" => Some(value.to_string()),
+        _ => None
+    }
+}
+
+//ffprobe's color space names, as the scale filter knows them.
+fn color_matrix(color_space: &str) -> Option<&'static str> {
+    match color_space {
+        "bt709" => Some("bt709"),
+        "smpte170m" => Some("smpte170m"),
+        "bt470bg" => Some("bt470"),
+        "smpte240m" => Some("smpte240m"),
+        "fcc" => Some("fcc"),
+        "bt2020nc" | "bt2020c" => Some("bt2020"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::structs::media_info::StreamInfo;
+    use common::structs::encoding::OutputEncoding;
+
+    #[test]
+    fn rotated_stream_is_turned_back() {
+        let stream = json!({"sample_aspect_ratio": "4:3", "color_space": "bt709", "color_primaries": "bt709", "color_range": "tv",
+                            "color_transfer": "unknown", "side_data_list": [{"side_data_type": "Display Matrix", "rotation": -90}]});
+        let properties = VideoProperties::from_stream(&StreamInfo::from_json(&stream));
+        assert_eq!(properties.rotation(), 90);
+        let encoding = OutputEncoding::from_request(None, None, "out.mp4").unwrap();
+        assert_eq!(properties.encode_args(&encoding), vec!["-color_primaries", "bt709", "-colorspace", "bt709", "-color_range", "tv", "-vf",
+                                                          "transpose=cclock,setsar=4/3,scale=out_color_matrix=bt709:out_range=tv,format=yuv420p"]);
+        assert_eq!(VideoProperties::from_stream(&StreamInfo::from_json(&json!({"tags": {"rotate": "270"}}))).remux_args(),
+                   vec!["-map_metadata", "1", "-map_chapters", "1", "-metadata:s:v:0", "rotate=270"]);
+    }
+}
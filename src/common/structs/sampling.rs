@@ -0,0 +1,60 @@
+use common::video_error::VideoError;
+use serde_json::Value;
+
+static DEFAULT_SCENE_THRESHOLD: f64 = 0.3;
+
+//how frames are picked from the input video before processing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sampling {
+    //evenly spaced frames at the requested fps.
+    Uniform,
+    //the first frame and every frame whose scene change score (0-1) passes the threshold, so roughly one frame per shot.
+    Scene(f64),
+    //every frame of the original at its own presentation time, for variable frame rate video.
+    Source,
+}
+
+impl Sampling {
+    //accepts "uniform", "scene", "source" or an object like {"strategy": "scene", "threshold": 0.4}, defaults to uniform.
+    pub fn from_json(json: Option<&Value>) -> Result<Sampling, VideoError> {
+        match json {
+            None => Ok(Sampling::Uniform),
+            Some(&Value::String(ref strategy)) => Sampling::from_parts(strategy, None),
+            Some(&Value::Object(ref object)) => {
+                let strategy = object.get("strategy").and_then(|strategy| strategy.as_str())
+                    .ok_or(format!("early exit:\nsampling must have a 'strategy' of 'uniform', 'scene' or 'source'."))?;
+                let threshold = match object.get("threshold") {
+                    Some(threshold) => Some(threshold.as_f64().ok_or(format!("early exit:\nsampling threshold must be a number."))?),
+                    None => None
+                };
+                Sampling::from_parts(strategy, threshold)
+            }
+            Some(_) => Err(format!("early exit:\nsampling must be a string or an object.").into())
+        }
+    }
+
+    fn from_parts(strategy: &str, threshold: Option<f64>) -> Result<Sampling, VideoError> {
+        match strategy {
+            "uniform" => Ok(Sampling::Uniform),
+            "source" if threshold.is_none() => Ok(Sampling::Source),
+            "source" => Err(format!("early exit:\nsource sampling keeps every frame, it doesn't take a threshold.").into()),
+            "scene" => {
+                let threshold = threshold.unwrap_or(DEFAULT_SCENE_THRESHOLD);
+                if threshold > 0f64 && threshold < 1f64 {
+                    Ok(Sampling::Scene(threshold))
+                } else {
+                    Err(format!("early exit:\nscene threshold must be between 0 and 1, got {}.", threshold).into())
+                }
+            }
+            other => Err(format!("early exit:\nunknown sampling strategy '{}', must be 'uniform', 'scene' or 'source'.", other).into())
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            &Sampling::Uniform => "uniform".to_string(),
+            &Sampling::Scene(threshold) => format!("scene({})", threshold),
+            &Sampling::Source => "source".to_string()
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use std::path::*;
+use std::collections::BTreeMap;
+use common::structs::segments::Part;
+use common::structs::properties::VideoProperties;
 
 #[derive(Debug, Clone)]
 pub struct Scattered {
@@ -7,6 +10,14 @@ pub struct Scattered {
     num_frames: usize,
     frames_dir: PathBuf,
     frame_regex: String,
+    timestamps: Vec<f64>,
+    passthrough: Vec<usize>,
+    parts: Option<Vec<Part>>,
+    properties: VideoProperties,
+    exact_timing: bool,
+    audio: bool,
+    dimensions: Option<(u64, u64)>,
+    duplicates: BTreeMap<usize, usize>,
 }
 
 impl Scattered {
@@ -15,7 +26,33 @@ impl Scattered {
     pub fn regex(&self) -> &str {&self.frame_regex}
     pub fn num_frames(&self) -> usize {self.num_frames}
     pub fn original_video(&self) ->&Path {&self.original_video}
-    pub fn new(frames_dir: PathBuf, num_frames: usize, original_video: PathBuf, fps: f64, regex: String) -> Scattered{
-        Scattered {frames_dir: frames_dir, original_video: original_video, fps: fps, frame_regex: regex, num_frames: num_frames}
+    //presentation time in seconds of each frame, in frame order.
+    pub fn timestamps(&self) -> &Vec<f64> {&self.timestamps}
+    //frames that are only there to fill out a keyframe aligned region, they're kept as is rather than processed.
+    pub fn passthrough(&self) -> &Vec<usize> {&self.passthrough}
+    //how to rebuild the output video when only parts of it are processed, None when every frame is.
+    pub fn parts(&self) -> Option<&Vec<Part>> {self.parts.as_ref()}
+    //what the original video stream carries besides its frames, re-applied in gather.
+    pub fn properties(&self) -> &VideoProperties {&self.properties}
+    //true when frames keep the original's own timestamps, so they have to be encoded at those instead of at a constant fps.
+    pub fn exact_timing(&self) -> bool {self.exact_timing}
+    //true when the "frames" are windows of the audio track rather than images.
+    pub fn is_audio(&self) -> bool {self.audio}
+    //width & height of the frames as they were split out, None for audio windows.
+    pub fn dimensions(&self) -> Option<(u64, u64)> {self.dimensions}
+    //frames that are the same as an earlier frame, mapped to that frame. only the earlier frame is processed.
+    pub fn duplicates(&self) -> &BTreeMap<usize, usize> {&self.duplicates}
+    pub fn new(frames_dir: PathBuf, num_frames: usize, original_video: PathBuf, fps: f64, regex: String, timestamps: Vec<f64>,
+               passthrough: Vec<usize>, parts: Option<Vec<Part>>, properties: VideoProperties, exact_timing: bool,
+               dimensions: Option<(u64, u64)>, duplicates: BTreeMap<usize, usize>) -> Scattered{
+        Scattered {frames_dir: frames_dir, original_video: original_video, fps: fps, frame_regex: regex, num_frames: num_frames, timestamps: timestamps,
+            passthrough: passthrough, parts: parts, properties: properties, exact_timing: exact_timing, audio: false, dimensions: dimensions,
+            duplicates: duplicates}
+    }
+    //audio windows of window seconds each, timestamps are when each window starts.
+    pub fn audio_windows(windows_dir: PathBuf, num_windows: usize, original_video: PathBuf, window: f64, regex: String, timestamps: Vec<f64>) -> Scattered {
+        Scattered {frames_dir: windows_dir, original_video: original_video, fps: 1f64 / window, frame_regex: regex, num_frames: num_windows, timestamps: timestamps,
+            passthrough: Vec::new(), parts: None, properties: VideoProperties::default(), exact_timing: false, audio: true, dimensions: None,
+            duplicates: BTreeMap::new()}
     }
 }
\ No newline at end of file
@@ -0,0 +1,181 @@
+use common::video_error::VideoError;
+use serde_json::Value;
+
+//anything shorter than this is treated as empty, it's well below a frame at MAX_FPS.
+static EPSILON: f64 = 0.001;
+
+//a span of the original video in seconds, start inclusive & end exclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    start: f64,
+    end: f64,
+}
+
+//how the output video is rebuilt when only parts of it are transformed, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    //frames first_frame..first_frame + frame_count from the processed frames directory, split from region of the original.
+    Processed { first_frame: usize, frame_count: usize, region: TimeRange },
+    //a keyframe aligned span of the original video stream, copied as is.
+    Original(TimeRange),
+}
+
+impl Part {
+    //the span of the original video the part stands in for.
+    pub fn region(&self) -> &TimeRange {
+        match self {
+            &Part::Processed { ref region, .. } => region,
+            &Part::Original(ref range) => range
+        }
+    }
+}
+
+impl TimeRange {
+    pub fn new(start: f64, end: f64) -> TimeRange {
+        TimeRange { start: start, end: end }
+    }
+
+    pub fn start(&self) -> f64 {self.start}
+    pub fn end(&self) -> f64 {self.end}
+    pub fn duration(&self) -> f64 {self.end - self.start}
+    pub fn contains(&self, time: f64) -> bool {time >= self.start && time < self.end}
+
+    //start_time/end_time or segments like [{"start": 10, "end": 20.5}], None means the whole video.
+    //ranges are clamped to the video's duration, sorted and overlapping ranges are merged.
+    pub fn from_request(start_time: Option<f64>, end_time: Option<f64>, segments: Option<&Value>, duration: f64) -> Result<Option<Vec<TimeRange>>, VideoError> {
+        let ranges: Vec<TimeRange> = match (start_time, end_time, segments) {
+            (None, None, None) => return Ok(None),
+            (Some(_), _, Some(_)) | (_, Some(_), Some(_)) => {
+                return Err(format!("early exit:\nuse either start_time & end_time or segments, not both.").into())
+            }
+            (start, end, None) => vec![TimeRange::new(start.unwrap_or(0f64), end.unwrap_or(duration))],
+            (None, None, Some(segments)) => {
+                let array = segments.as_array().ok_or(format!("early exit:\nsegments must be an array of {{\"start\": seconds, \"end\": seconds}} objects."))?;
+                if array.is_empty() {
+                    return Err(format!("early exit:\nsegments must contain at least one segment.").into())
+                }
+                let mut ranges: Vec<TimeRange> = Vec::new();
+                for segment in array {
+                    let start = segment["start"].as_f64().ok_or(format!("early exit:\nevery segment needs a numeric 'start', got {}.", segment))?;
+                    let end = segment["end"].as_f64().ok_or(format!("early exit:\nevery segment needs a numeric 'end', got {}.", segment))?;
+                    ranges.push(TimeRange::new(start, end));
+                }
+                ranges
+            }
+        };
+        let mut checked: Vec<TimeRange> = Vec::new();
+        for range in ranges {
+            if range.start < 0f64 || range.end <= range.start {
+                return Err(format!("early exit:\ninvalid time range {}s - {}s, start must be at least 0 and before end.", range.start, range.end).into())
+            }
+            if range.start >= duration {
+                return Err(format!("early exit:\ntime range {}s - {}s starts after the end of the {}s video.", range.start, range.end, duration).into())
+            }
+            checked.push(TimeRange::new(range.start, range.end.min(duration)));
+        }
+        Ok(Some(merge(checked)))
+    }
+
+    //widens each range out to the keyframes around it, so everything outside them can be stream copied.
+    pub fn align_to_keyframes(ranges: &Vec<TimeRange>, keyframes: &Vec<f64>, duration: f64) -> Vec<TimeRange> {
+        let aligned = ranges.iter().map(|range| {
+            let start = keyframes.iter().cloned().filter(|keyframe| *keyframe <= range.start + EPSILON).fold(0f64, f64::max);
+            let end = keyframes.iter().cloned().filter(|keyframe| *keyframe >= range.end - EPSILON).fold(duration, f64::min);
+            TimeRange::new(start, end)
+        }).collect();
+        merge(aligned)
+    }
+
+    //when each sampled frame stops being the latest one, at the next frame or the end of the range it was sampled from, the last lasts until duration.
+    pub fn frame_ends(timestamps: &Vec<f64>, duration: f64, ranges: Option<&Vec<TimeRange>>) -> Vec<f64> {
+        timestamps.iter().enumerate().map(|(index, start)| {
+            let next = timestamps.get(index + 1).cloned().unwrap_or(duration);
+            match ranges.and_then(|ranges| ranges.iter().find(|range| range.contains(*start))) {
+                Some(range) if range.end < next => range.end,
+                _ => next
+            }
+        }).collect()
+    }
+
+    //consecutive chunks of at most chunk_duration seconds covering the whole video.
+    //with a frame_rate every chunk is a whole number of frames long & starts on a frame, so encoded chunks add up to the video's length instead of drifting from it.
+    pub fn chunks(duration: f64, chunk_duration: f64, frame_rate: Option<f64>) -> Vec<TimeRange> {
+        let chunk_duration = match frame_rate {
+            Some(rate) => (chunk_duration * rate).round().max(1f64) / rate,
+            None => chunk_duration
+        };
+        let mut chunks: Vec<TimeRange> = Vec::new();
+        let mut start = 0f64;
+        while duration - start > EPSILON {
+            //multiplied out rather than added up, so rounding doesn't build up over hundreds of chunks.
+            let end = ((chunks.len() + 1) as f64 * chunk_duration).min(duration);
+            chunks.push(TimeRange::new(start, end));
+            start = end;
+        }
+        chunks
+    }
+
+    //the original video between the processed regions, regions must be sorted & not overlap.
+    pub fn gaps(regions: &Vec<TimeRange>, duration: f64) -> Vec<TimeRange> {
+        let mut gaps: Vec<TimeRange> = Vec::new();
+        let mut cursor = 0f64;
+        for region in regions {
+            if region.start - cursor > EPSILON {
+                gaps.push(TimeRange::new(cursor, region.start));
+            }
+            cursor = region.end;
+        }
+        if duration - cursor > EPSILON {
+            gaps.push(TimeRange::new(cursor, duration));
+        }
+        gaps
+    }
+}
+
+fn merge(mut ranges: Vec<TimeRange>) -> Vec<TimeRange> {
+    ranges.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    let mut merged: Vec<TimeRange> = Vec::new();
+    for range in ranges {
+        let overlaps = merged.last().map(|last| range.start <= last.end + EPSILON).unwrap_or(false);
+        if overlaps {
+            let last = merged.last_mut().unwrap();
+            last.end = last.end.max(range.end);
+        } else {
+            merged.push(range);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn segments_are_clamped_and_merged() {
+        let segments = json!([{"start": 8, "end": 30}, {"start": 1, "end": 3}, {"start": 2.5, "end": 4}]);
+        let ranges = TimeRange::from_request(None, None, Some(&segments), 20f64).unwrap().unwrap();
+        assert_eq!(ranges, vec![TimeRange::new(1f64, 4f64), TimeRange::new(8f64, 20f64)]);
+        assert!(TimeRange::from_request(Some(1f64), None, Some(&segments), 20f64).is_err());
+        assert!(TimeRange::from_request(Some(25f64), None, None, 20f64).is_err());
+    }
+
+    #[test]
+    fn regions_align_to_keyframes() {
+        let keyframes = vec![0f64, 2f64, 4f64, 6f64, 8f64];
+        let aligned = TimeRange::align_to_keyframes(&vec![TimeRange::new(2.5, 3.5), TimeRange::new(7f64, 9f64)], &keyframes, 10f64);
+        assert_eq!(aligned, vec![TimeRange::new(2f64, 4f64), TimeRange::new(6f64, 10f64)]);
+        assert_eq!(TimeRange::gaps(&aligned, 10f64), vec![TimeRange::new(0f64, 2f64), TimeRange::new(4f64, 6f64)]);
+        assert_eq!(TimeRange::chunks(10f64, 4f64, None), vec![TimeRange::new(0f64, 4f64), TimeRange::new(4f64, 8f64), TimeRange::new(8f64, 10f64)]);
+    }
+
+    #[test]
+    fn chunks_are_whole_frames() {
+        let rate = 30000f64 / 1001f64;
+        let chunks = TimeRange::chunks(3600f64, 10f64, Some(rate));
+        //10s at 29.97fps is 299.7 frames, so every chunk is 300 frames.
+        assert!((chunks[0].duration() - 300f64 / rate).abs() < 1e-9);
+        assert!((chunks[359].start() - 359f64 * 300f64 / rate).abs() < 1e-9);
+        assert_eq!(chunks.last().unwrap().end(), 3600f64);
+    }
+}
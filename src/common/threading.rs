@@ -1,13 +1,8 @@
-use std::time::{Duration, SystemTime};
-use std::ops::*;
-use std_semaphore::Semaphore;
 use std::sync::{Arc, Mutex, MutexGuard};
-use std::sync::atomic::{AtomicBool, Ordering};
 use common::video_error::*;
+use common::concurrency::ConcurrencyController;
 use common::structs::advanced_input::AdvancedInput;
-static DURATION: u64 = 5;
 
-pub type Default<T, J> = Fn(&Threadable<T>, Vec<usize>) -> Result<Vec<J>, VideoError> + Sync;
 pub type Advanced<T, J> = Fn(&Threadable<T>, Vec<usize>, String, &AdvancedInput) -> Result<Vec<J>, VideoError> + Sync;
 pub type Lockstep<T> = Arc<Mutex<T>>;
 
@@ -19,10 +14,8 @@ pub struct Terminator {
 
 #[derive(Clone)]
 pub struct Threadable<J> where J:Clone{
-    slowdown_signal: Arc<AtomicBool>,
-    semaphore: Arc<Semaphore>,
+    controller: Arc<ConcurrencyController>,
     termination_signal: Terminator,
-    time: Lockstep<SystemTime>,
     readonly_data: Arc<J>
 }
 
@@ -57,34 +50,18 @@ impl Terminator {
 }
 
 impl<J> Threadable<J> where J: Clone {
-    pub fn create(starting_th: isize, max_th: isize, data: J) -> Threadable<J> {
-        let slowdown = AtomicBool::new(false);
-        let slowdown_signal: Arc<AtomicBool> = Arc::new(slowdown);
-        let semaphore: Arc<Semaphore> = prepare_semaphore(starting_th, max_th);
+    //the controller is shared by every executor of a job, so what it learns about the algorithm carries over between chunks.
+    pub fn create(controller: Arc<ConcurrencyController>, data: J) -> Threadable<J> {
         let termination_signal: Terminator = Terminator::create();
-        let time: Lockstep<SystemTime> = Arc::new(Mutex::new(SystemTime::now()));
         let data = Arc::new(data);
-        Threadable{slowdown_signal: slowdown_signal, semaphore:semaphore,
-            termination_signal: termination_signal, time: time,  readonly_data: data}
+        Threadable{controller: controller, termination_signal: termination_signal, readonly_data: data}
     }
 
-    pub fn arc_semaphore(&self) -> Arc<Semaphore> {self.semaphore.clone()}
-
-    pub fn emergency_release(&self) -> () {self.semaphore.release(); println!("released semaphore due to emergency.")}
+    pub fn arc_controller(&self) -> Arc<ConcurrencyController> {self.controller.clone()}
 
     pub fn arc_data(&self) -> Arc<J> {self.readonly_data.clone()}
     pub fn arc_term_signal(&self) -> Terminator {self.termination_signal.clone()}
 
-    fn acquire_time(&self) -> MutexGuard<SystemTime> {
-        self.time.lock().unwrap()
-    }
-    fn acquire_slow_signal(&self) -> &AtomicBool {self.slowdown_signal.deref()}
-    fn slow_down(&self) -> () {
-        (*self.slowdown_signal).store(true, Ordering::Relaxed);
-    }
-    fn set_time(&self, time: SystemTime) -> () {
-        *self.time.lock().unwrap() = time;
-    }
     fn check_term_signal(&self) -> MutexGuard<Option<VideoError>> {
         self.termination_signal.check_signal()
     }
@@ -98,43 +75,10 @@ impl<J> Threadable<J> where J: Clone {
 }
 
 
-pub fn try_algorithm_default<T, J>(function: &Default<T, J>, batch: &Vec<usize>, threadable: &Threadable<T>) -> Result<Vec<J>, ()> where T: Clone {
-    let current_time = SystemTime::now();
-    match threading_strategizer(&threadable, current_time) {
-        Ok(()) => {}
-        Err(err) => {threadable.set_term_signal(err)}
-    };
-    if let &Some(ref err) = threadable.check_term_signal().deref() {
-        return Err(())
-    }
-    match function(&threadable, batch.clone()) {
-        Ok(result) => {
-            Ok(result)
-        },
-        Err(err) => {
-            if err.to_string().contains("algorithm hit max number of active calls per session") {
-                threadable.slow_down();
-                try_algorithm_default(function, batch, threadable)
-            } else if threadable.check_term_signal().is_none() {
-                let terminate_err = VideoError::MsgError(format!("algorithm thread failed, ending early: \n{}", err).into());
-                threadable.set_term_signal(terminate_err);
-                Err(())
-            } else {
-                    println!("already received an error!");
-                    Err(())
-                }
-        }
-    }
-}
-
+//throttling is handled per call by the ConcurrencyController, so any error that reaches here ends the job.
 pub fn try_algorithm_advanced<T, J>(function: &Advanced<T, J>, batch: &Vec<usize>, algo: &str,
                                     json: &AdvancedInput, threadable: &Threadable<T>) -> Result<Vec<J>, ()> where T: Clone {
-    let current_time = SystemTime::now();
-    match threading_strategizer(&threadable, current_time) {
-        Ok(()) => {}
-        Err(err) => {threadable.set_term_signal(err)}
-    };
-    if let &Some(ref err) = threadable.check_term_signal().deref() {
+    if threadable.check_term_signal().is_some() {
         println!("failing early, already got an error");
         return Err(())
     }
@@ -144,47 +88,13 @@ pub fn try_algorithm_advanced<T, J>(function: &Advanced<T, J>, batch: &Vec<usize
             Ok(result)
         },
         Err(err) => {
-            threadable.emergency_release();
-            if err.to_string().contains("algorithm hit max number of active calls per session") {
-                threadable.slow_down();
-                try_algorithm_advanced(function,  batch, algo, json,threadable)
-            } else if threadable.check_term_signal().is_none() {
+            if threadable.check_term_signal().is_none() {
                 let terminate_err = VideoError::MsgError(format!("algorithm thread failed, ending early: \n{}", err).into());
                 threadable.set_term_signal(terminate_err);
-                Err(())
             } else {
                 println!("already received an error!");
-                Err(())
             }
+            Err(())
         }
     }
 }
-
-pub fn prepare_semaphore(starting_threads: isize, max_threads: isize) -> Arc<Semaphore> {
-    let semaphore = Semaphore::new(max_threads);
-    for _ in 0..(max_threads-starting_threads) {
-        semaphore.acquire();
-    }
-    Arc::new(semaphore)
-}
-
-fn threading_strategizer<J>(threadable: &Threadable<J>, current: SystemTime) -> Result<(), VideoError> where J: Clone {
-    let time_diff: Duration = current.duration_since(threadable.acquire_time().clone())?;
-    // println!("time difference is... {}", time_diff.as_secs());
-    if time_diff.as_secs() > DURATION {
-        threadable.set_time(current);
-        // println!("time check...");
-        let sem = threadable.arc_semaphore();
-        let slow = threadable.acquire_slow_signal();
-        if slow.load(Ordering::Relaxed) == true {
-            // println!("We're slowing down.");
-            sem.acquire();
-        } else {
-            // println!("No need to slow down, lets speed up.");
-            sem.release();
-        }
-        //maybe we don't want to reset this to
-        slow.store(false, Ordering::Relaxed);
-    }
-    Ok(())
-}
@@ -10,12 +10,16 @@ use std::thread::JoinHandle;
 static MAX_TIME: f64 = 3000f64;
 static ADJUSTMENT_TIME: f64 = 60f64;
 
+//gets the number of finished jobs and the total number of jobs every time a job succeeds.
+pub type Progress = Fn(usize, usize) + Send + Sync;
+
 #[derive(Clone)]
 pub struct WatchdogComms {
     watchdog_rx: Lockstep<Receiver<usize>>,
     watchdog_tx: Lockstep<Sender<usize>>,
     terminate_tx: Terminator,
     total_jobs: usize,
+    progress: Option<Arc<Progress>>,
 }
 
 pub struct Watchdog {
@@ -24,8 +28,8 @@ pub struct Watchdog {
 }
 
 impl Watchdog {
-    pub fn create(term_obj: Terminator, total_jobs: usize) -> Watchdog {
-        let wdc = WatchdogComms::create(term_obj, total_jobs);
+    pub fn create(term_obj: Terminator, total_jobs: usize, progress: Option<Arc<Progress>>) -> Watchdog {
+        let wdc = WatchdogComms::create(term_obj, total_jobs, progress);
         let wdcc = wdc.clone();
         println!("starting up watchdog thread.");
         let callback = thread::spawn(move || {
@@ -46,11 +50,11 @@ impl Watchdog {
 }
 
 impl WatchdogComms {
-    fn create(term_obj: Terminator, total_jobs: usize) -> WatchdogComms {
+    fn create(term_obj: Terminator, total_jobs: usize, progress: Option<Arc<Progress>>) -> WatchdogComms {
         let (s, r) = channel();
         let locked_s = Arc::new(Mutex::new(s));
         let locked_r = Arc::new(Mutex::new(r));
-        WatchdogComms { watchdog_rx:locked_r, watchdog_tx:locked_s, terminate_tx: term_obj, total_jobs: total_jobs}
+        WatchdogComms { watchdog_rx:locked_r, watchdog_tx:locked_s, terminate_tx: term_obj, total_jobs: total_jobs, progress: progress}
     }
 
     pub fn send_success_signal(&self) -> () {
@@ -80,6 +84,9 @@ impl WatchdogComms {
             if signal == 1 {
                 let current_time = SystemTime::now();
                 finished_jobs = finished_jobs + 1f64;
+                if let Some(ref progress) = self.progress {
+                    progress(finished_jobs as usize, self.total_jobs);
+                }
                 let current_time_delta = current_time.duration_since(start_time.clone())
                     .map_err(|d| { return failure_mgmt(&self, format!("failed to check time: {}", d))}).unwrap();
                 println!("current time is: {}", current_time_delta.as_secs());
@@ -1,91 +1,75 @@
-use algorithmia::Algorithmia;
 use common::video_error::VideoError;
 use common::structs::prelude::*;
 use super::functions;
 use common::json_utils::combine_data_extract;
 use rayon::prelude::*;
 use common::threading::*;
-use common::watchdog::Watchdog;
+use common::watchdog::{Watchdog, Progress};
 use serde_json::Value;
 use std::sync::{Arc};
+use std::collections::BTreeMap;
 use common::misc;
+use common::checkpoint::Checkpoint;
+use common::cache::CacheScope;
+use common::concurrency::ConcurrencyController;
 
 static FPSMAX: f64 = 60f64;
 
 
-pub fn default(data: Extract,
-               num_of_frames: usize,
-               batch_size: usize,
-               duration: f64,
-               starting_threads: isize,
-                max_threads: isize,
-               function: &Default<Extract, Value>) -> Result<Value, VideoError> {
-    //generate batches of frames by number, based on the batch size.
-    let frame_stamp: f64 = duration / num_of_frames as f64;
-    let frame_batches: Box<Vec<Vec<usize>>> = misc::frame_batches_simple(batch_size, num_of_frames);
-    let mut result: Vec<Result<Vec<Value>, ()>> = Vec::new();
-
-    let global_threadable = Threadable::create(starting_threads, max_threads, data);
-    let inner_threadable = global_threadable.clone();
-    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len());
-    let wd_t = wd.get_comms();
-    frame_batches.par_iter().map(move |batch| {
-//        let thread_t = inner_threadable.clone();
-        let res = try_algorithm_default(function, &batch, &inner_threadable);
-        if res.is_ok() {
-            wd_t.send_success_signal();
-        }
-        res
-    }).weight_max().collect_into(&mut result);
-    wd.terminate();
-    match global_threadable.extract_term_signal() {
-        None => {
-            let processed_frames: Vec<Value> = result.into_iter().collect::<Result<Vec<Vec<_>>, _>>().unwrap().concat();
-            let processed: Value = combine_data_extract(&processed_frames, frame_stamp)?;
-            Ok(processed)
-        }
-        Some(err) => Err(format!("error, video processing failed: {}", err).into())
-    }
-}
-
+//results of finished batches are kept in the checkpoint, so the output is rebuilt from it rather than from this run alone.
 pub fn advanced(data: Extract,
-                num_of_frames: usize,
+                timestamps: &Vec<f64>,
+                pending_frames: Vec<usize>,
+                duplicates: &BTreeMap<usize, usize>,
                 algorithm: &str,
                 batch_size: usize,
-                duration: f64,
-                starting_threads: isize,
-                max_threads: isize,
-                input: AdvancedInput) -> Result<Value, VideoError> {
-    let frame_stamp: f64 = duration / num_of_frames as f64;
+                controller: Arc<ConcurrencyController>,
+                input: AdvancedInput,
+                progress: Option<Arc<Progress>>,
+                checkpoint: Arc<Checkpoint>,
+                cache: Option<CacheScope>) -> Result<Value, VideoError> {
     let search: Arc<AdvancedInput> = Arc::new(input);
-    let frame_batches = misc::frame_batches_advanced(batch_size, num_of_frames, search.option());
+    //cached results are recorded like any other finished frame.
+    let pending_frames: Vec<usize> = match cache {
+        Some(ref cache) => {
+            let (restored, results): (Vec<usize>, Vec<Value>) = cache.restore_results(&pending_frames, &data).into_iter().unzip();
+            if !restored.is_empty() {
+                checkpoint.record(&restored, Some(&results));
+            }
+            pending_frames.into_iter().filter(|frame| restored.binary_search(frame).is_err()).collect()
+        }
+        None => pending_frames
+    };
+    let frame_batches = misc::frame_batches_advanced(batch_size, &pending_frames, search.option());
 
     let mut result: Vec<Result<Vec<Value>, ()>> = Vec::new();
 
-    let global_threadable = Threadable::create(starting_threads, max_threads, data);
+    let global_threadable = Threadable::create(controller.clone(), data);
     let sharable_threadable = global_threadable.clone();
-    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len());
+    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len(), progress);
     let wd_t = wd.get_comms();
+    let batch_checkpoint = checkpoint.clone();
 
     frame_batches.par_iter().map(move |batch| {
 //        let thread_t = sharable_threadable.clone();
-        let res = if search.option() == "batch" {
-            try_algorithm_advanced(&functions::advanced_batch,  &batch,
-                                   algorithm, &search, &sharable_threadable)
-        } else {
-            try_algorithm_advanced(&functions::advanced_single, &batch,
-                                   algorithm, &search, &sharable_threadable)
-        };
-        if res.is_ok() {
+        let res = try_algorithm_advanced(&functions::process, &batch,
+                                         algorithm, &search, &sharable_threadable);
+        if let Ok(ref values) = res {
+            if let Some(ref cache) = cache {
+                cache.store_results(&batch, values, &sharable_threadable.arc_data());
+            }
+            batch_checkpoint.record(&batch, Some(values));
             wd_t.send_success_signal();
         }
         res
     }).weight_max().collect_into(&mut result);
     wd.terminate();
+    checkpoint.flush();
     match global_threadable.extract_term_signal() {
         None => {
-            let processed_frames: Vec<Value> = result.into_iter().collect::<Result<Vec<Vec<_>>, _>>().unwrap().concat();
-            let processed: Value = combine_data_extract(&processed_frames, frame_stamp)?;
+            checkpoint.share_results(duplicates);
+            let processed_frames: Vec<Value> = checkpoint.results();
+            let processed: Value = combine_data_extract(&processed_frames, timestamps)?;
             Ok(processed)
         }
         Some(err) => Err(format!("error, video processing failed: {}", err).into())
@@ -1,96 +1,31 @@
-
-use algorithmia::algo::*;
 use std::path::*;
 use serde_json::Value;
-use std::string::String;
 use common::video_error::VideoError;
 use common::structs::prelude::*;
+use common::algo::{batch_file_path, previous_file_path};
 use common::threading::Threadable;
-use common::algo::{batch_file_path, try_algorithm, batch_upload_file};
-use std_semaphore::Semaphore;
-use std::sync::Arc;
-use either::{Left, Right};
-pub fn nudity_detection(input: &Threadable<Extract>, batch: Vec<usize>) -> Result<Vec<Value>, VideoError> {
-    let algorithm = "algo://sfw/NudityDetectioni2v/0.2.4";
-    let data = input.arc_data().clone();
-    let local_pre_frames: Vec<PathBuf> = batch_file_path(&batch, data.input_regex(), data.local_input().to_str().unwrap())?
-        .iter().map(|str| {PathBuf::from(str.to_owned())}).collect::<Vec<PathBuf>>();
-    let remote_pre_frames: Vec<String> = batch_file_path(&batch, data.input_regex(), data.remote_working())?;
-
-    batch_upload_file(&local_pre_frames, &remote_pre_frames,
-                      data.client(), input.arc_term_signal())?;
-    let json = json!({
-    "image": remote_pre_frames.iter()
-        .map(|frame| {Value::String(frame.clone())}).collect::<Vec<Value>>(),
-    });
-
-    let response: AlgoResponse = try_algorithm(data.client(), &algorithm, &json, input.arc_term_signal(), input.arc_semaphore())?;
-
-    let output_json: Value = response.into_json()
-        .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
-    let output: Vec<Value> = output_json.as_array().unwrap().iter().map(|dat| {dat.clone()}).collect::<Vec<_>>();
-    Ok(output)
-}
 
-pub fn illustration_tagger(input: &Threadable<Extract>, batch: Vec<usize>) -> Result<Vec<Value>, VideoError> {
-    let algorithm = "algo://deeplearning/IllustrationTagger/0.2.3";
+pub fn process(input: &Threadable<Extract>, batch: Vec<usize>, algorithm: String, algo_input: &AdvancedInput) -> Result<Vec<Value>, VideoError> {
     let data = input.arc_data();
-    let local_pre_frames: Vec<PathBuf> = batch_file_path(&batch, data.input_regex(), data.local_input().to_str().unwrap())?
-        .iter().map(|str| {PathBuf::from(str.to_owned())}).collect::<Vec<PathBuf>>();
-    let remote_pre_frames: Vec<String> = batch_file_path(&batch, data.input_regex(), data.remote_working())?;
-
-    batch_upload_file(&local_pre_frames, &remote_pre_frames, data.client(), input.arc_term_signal())?;
-    let mut output: Vec<Value> = Vec::new();
-    for _ in 0..remote_pre_frames.len() {
-        let json = json!({
-        "image": remote_pre_frames.iter()
-            .map(|frame| {Value::String(frame.clone())}).collect::<Vec<Value>>(),
-        });
-
-        let response: AlgoResponse = try_algorithm(data.client(), &algorithm, &json, input.arc_term_signal(), input.arc_semaphore())?;
-
-        let output_json: Value = response.into_json()
-            .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
-        output.push(output_json);
-    }
-    Ok(output)
-}
-
-pub fn advanced_single(input: &Threadable<Extract>, batch: Vec<usize>, algorithm: String, algo_input: &AdvancedInput) -> Result< Vec<Value>, VideoError> {
-    let mut output: Vec<Value> = Vec::new();
-    let data = input.arc_data().clone();
     let local_frames: Vec<PathBuf> = batch_file_path(&batch, data.input_regex(), data.local_input().to_str().unwrap())?
         .iter().map(|str| {PathBuf::from(str.to_owned())}).collect::<Vec<PathBuf>>();
-    let remote_frames: Vec<String> = batch_file_path(&batch, data.input_regex(), data.remote_working())?;
-    batch_upload_file(&local_frames, &remote_frames, data.client(), input.arc_term_signal())?;
-    for _ in 0..remote_frames.len() {
-        let json: Value = algo_input.replace_variables_extract(Right(remote_frames.iter().next().unwrap()))?;
-
-        let response: AlgoResponse = try_algorithm(data.client(), &algorithm, &json, input.arc_term_signal(), input.arc_semaphore())?;
+    let previous = previous_file_path(&batch, data.input_regex(), data.local_input().to_str().unwrap())?;
+    let frames = FrameBatch { inputs: local_frames, outputs: Vec::new(), frames: batch, previous: previous, context: data.context() };
+    data.processor().extract(&algorithm, algo_input, &frames, input.arc_term_signal(), input.arc_controller())
+}
 
-        let output_json: Value = response.into_json()
-            .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
-        output.push(output_json);
+//the algorithms we know how to call without an advanced_input, with their pinned version & input template.
+pub fn default_input(algorithm: &str) -> Option<(&'static str, Value)> {
+    let lowered = algorithm.to_ascii_lowercase();
+    if lowered.contains("nuditydetection") {
+        Some(("algo://sfw/NudityDetectioni2v/0.2.4", json!({
+        "image": "$BATCH_INPUT"
+        })))
+    } else if lowered.contains("illustrationtagger") {
+        Some(("algo://deeplearning/IllustrationTagger/0.2.3", json!({
+        "image": "$SINGLE_INPUT"
+        })))
+    } else {
+        None
     }
-    Ok(output)
 }
-
-pub fn advanced_batch(input: &Threadable<Extract>, batch: Vec<usize>, algorithm: String, algo_input: &AdvancedInput) -> Result< Vec<Value>, VideoError> {
-    let data = input.arc_data().clone();
-
-    let local_frames: Vec<PathBuf> = batch_file_path(&batch, data.input_regex(), data.local_input().to_str().unwrap())?
-        .iter().map(|str| {PathBuf::from(str.to_owned())}).collect::<Vec<PathBuf>>();
-    let remote_frames: Vec<String> = batch_file_path(&batch, data.input_regex(), data.remote_working())?;
-
-    batch_upload_file(&local_frames, &remote_frames, data.client(), input.arc_term_signal())?;
-    let json: Value = algo_input.replace_variables_extract(Left(&remote_frames))?;
-
-    //println!("acquiring semaphore");
-    let response: AlgoResponse = try_algorithm(data.client(), &algorithm, &json, input.arc_term_signal(), input.arc_semaphore())?;
-    //println!("releasing semaphore");
-
-    let output_json: Value = response.into_json()
-        .ok_or(format!("algorithm failed, ending early:\n algorithm response did not parse as valid json."))?;
-    let output: Vec<Value> = output_json.as_array().unwrap().iter().map(|dat| {dat.clone()}).collect::<Vec<_>>();
-    Ok(output)
-}
\ No newline at end of file
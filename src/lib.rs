@@ -12,7 +12,8 @@ extern crate regex;
 extern crate rayon;
 extern crate uuid;
 extern crate either;
-extern crate std_semaphore;
+extern crate toml;
+extern crate sha1;
 
 use algorithmia::prelude::*;
 use serde_json::Value;
@@ -23,11 +24,32 @@ mod common;
 mod extract;
 mod transform;
 mod processing;
+pub mod tools;
 
 use common::algo::{early_exit, get_file, upload_file};
 use common::misc::json_to_file;
-use common::structs::prelude::{Gathered, Scattered};
-use common::preprocess::{PreDefines, ExecutionStyle};
+use common::json_utils::concat_data_extract;
+use common::file_mgmt::create_directory;
+use common::video_error::VideoError;
+use std::fs::{File, rename, remove_dir_all};
+use common::structs::prelude::{Gathered, Scattered, Objective, Sampling, TimeRange, OutputEncoding, MediaInfo, VideoProperties, AudioTrack, ExtractOutput, Overlay, EventRules, Dedup};
+use common::structs::audio::AudioSource;
+use common::structs::advanced_input::AdvancedInput;
+use common::structs::ffmpeg;
+use common::structs::extract_format::Format;
+use common::preprocess::PreDefines;
+use common::config::Config;
+use common::checkpoint::Checkpoint;
+use common::cache::ResultCache;
+use common::concurrency::ConcurrencyController;
+use common::structs::processor;
+use common::storage::Storage;
+use std::sync::Arc;
+
+pub use common::watchdog::Progress;
+
+//seconds of audio sent to the algorithm at a time in the audio modes.
+static DEFAULT_AUDIO_WINDOW: f64 = 10f64;
 
 #[derive(Debug, Deserialize)]
 pub struct Entry {
@@ -38,72 +60,426 @@ pub struct Entry {
     fps: Option<Number>,
     image_compression: Option<Number>,
     video_compression: Option<Number>,
+    output_encoding: Option<Value>,
+    audio: Option<Value>,
+    output_format: Option<Value>,
+    overlay: Option<Value>,
+    events: Option<Value>,
+    mode: Option<String>,
+    sampling: Option<Value>,
+    dedup: Option<Value>,
+    start_time: Option<Number>,
+    end_time: Option<Number>,
+    segments: Option<Value>,
+    chunk_duration: Option<Number>,
+    audio_window: Option<Number>,
+    cache: Option<String>,
+    max_requests_per_second: Option<Number>,
 }
 
 #[derive(Debug, Serialize)]
 struct Exit {
-    output_file: String
+    output_file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<Value>,
 }
 
 
-pub struct Algo;
+pub struct Algo {
+    config: Option<Config>,
+    progress: Option<Arc<Progress>>,
+}
 // this version doesn't auto-create Algo, so you can create it yourself
 algo_entrypoint!(Entry => Algo::helper);
 
-enum Objective {
-    Transform,
-    Extract,
-}
-
-
 impl Algo {
     fn helper(&self, entry: Entry) -> Result<AlgoOutput, Box<std::error::Error>> {
         let batch_size = 5;
         let starting_threads = 5;
         let max_threads = 35;
-        let format = ExecutionStyle::ProdLocal;
-        let objective = Objective::Transform;
-        let parameters: PreDefines = PreDefines::create(format, batch_size, starting_threads, max_threads,
+        let config = match self.config {
+            Some(ref config) => config.clone(),
+            None => Config::load()?
+        };
+        if processor::is_local(&entry.algorithm) && !config.allows_local_processes() {
+            return Err(format!("early exit:\nexec:// algorithms run on the local machine, they're only allowed in the prod_local & test_local execution styles.").into())
+        }
+        let objective = Objective::select(entry.mode.as_ref().map(|mode| mode.as_str()), &entry.algorithm, entry.advanced_input.as_ref())?;
+        println!("running in {} mode.", objective.name());
+        let sampling = Sampling::from_json(entry.sampling.as_ref())?;
+        if let (true, &Sampling::Scene(_)) = (objective.encodes_frames(), &sampling) {
+            return Err(format!("early exit:\n{} sampling skips frames, so it can only be used in extract mode.", sampling.name()).into())
+        }
+        if let (&Sampling::Scene(_), Some(_)) = (&sampling, entry.fps.as_ref()) {
+            return Err(format!("early exit:\nfps can't be combined with scene sampling, frames are picked where the scene changes.").into())
+        }
+        if objective.is_audio() && sampling != Sampling::Uniform {
+            return Err(format!("early exit:\n{} sampling only applies to video frames, not in {} mode.", sampling.name(), objective.name()).into())
+        }
+        let dedup: Option<Dedup> = Dedup::from_json(entry.dedup.as_ref())?;
+        if let (true, Some(_)) = (objective.is_audio(), dedup) {
+            return Err(format!("early exit:\ndedup only applies to video frames, not in {} mode.", objective.name()).into())
+        }
+        if let (Some(_), Some(variable)) = (dedup, entry.advanced_input.as_ref().and_then(|input| AdvancedInput::frame_variable(input))) {
+            return Err(format!("early exit:\ndedup can't be combined with {} in advanced_input, duplicate frames would get a result made for another frame.", variable).into())
+        }
+        let audio_window: f64 = entry.audio_window.as_ref().and_then(|num| num.as_f64()).unwrap_or(DEFAULT_AUDIO_WINDOW);
+        if audio_window <= 0f64 {
+            return Err(format!("early exit:\naudio_window must be a positive number of seconds.").into())
+        }
+        if objective == Objective::AudioTransform {
+            let extension = entry.output_file.rsplit('.').next().unwrap_or("").to_lowercase();
+            if ffmpeg::audio_codec(&extension).is_none() {
+                return Err(format!("early exit:\nprocessed audio can't be written to a '.{}' file.", extension).into())
+            }
+        }
+        let max_requests_per_second: Option<f64> = entry.max_requests_per_second.as_ref().and_then(|num| num.as_f64());
+        if let Some(rate) = max_requests_per_second {
+            if rate <= 0f64 {
+                return Err(format!("early exit:\nmax_requests_per_second must be a positive number.").into())
+            }
+        }
+        let video_compression: Option<u64> = entry.video_compression.map(|num: Number| { num.as_u64() }).and_then(|x| x);
+        //checked before anything is downloaded, so a codec the output container can't hold fails straight away.
+        let encoding: Option<OutputEncoding> = match objective {
+            Objective::Transform | Objective::Overlay => Some(OutputEncoding::from_request(entry.output_encoding.as_ref(), video_compression, &entry.output_file)?),
+            Objective::Extract if entry.output_encoding.is_some() => {
+                return Err(format!("early exit:\noutput_encoding only applies in transform mode.").into())
+            }
+            Objective::Extract => None,
+            _ if entry.output_encoding.is_some() => {
+                return Err(format!("early exit:\noutput_encoding only applies in transform mode.").into())
+            }
+            Objective::AudioTransform | Objective::AudioExtract => None
+        };
+        let audio: Option<AudioTrack> = match objective {
+            Objective::Transform | Objective::Overlay => Some(AudioTrack::from_request(entry.audio.as_ref(), &entry.output_file)?),
+            Objective::Extract if entry.audio.is_some() => {
+                return Err(format!("early exit:\naudio only applies in transform mode.").into())
+            }
+            Objective::Extract => None,
+            //the audio objectives make their own audio track.
+            _ if entry.audio.is_some() => {
+                return Err(format!("early exit:\naudio can't be combined with {} mode.", objective.name()).into())
+            }
+            Objective::AudioTransform | Objective::AudioExtract => None
+        };
+        let extract_output: Option<ExtractOutput> = match objective {
+            Objective::Extract | Objective::AudioExtract => Some(ExtractOutput::from_request(entry.output_format.as_ref(), &entry.output_file)?),
+            _ if entry.output_format.is_some() => {
+                return Err(format!("early exit:\noutput_format only applies in the extract modes.").into())
+            }
+            Objective::Transform | Objective::AudioTransform | Objective::Overlay => None
+        };
+        let events: Option<EventRules> = match objective {
+            Objective::Extract | Objective::AudioExtract => EventRules::from_request(entry.events.as_ref())?,
+            _ if entry.events.is_some() => {
+                return Err(format!("early exit:\nevents only apply in the extract modes.").into())
+            }
+            _ => None
+        };
+        //events aren't per frame, so they're only written as json.
+        if let (Some(_), Some(extract_output)) = (events.as_ref(), extract_output.as_ref()) {
+            if extract_output.format() != Format::Json {
+                return Err(format!("early exit:\nevents can only be written as json, not {}.", extract_output.format().name()).into())
+            }
+        }
+        let overlay: Option<Overlay> = match objective {
+            Objective::Overlay => Some(Overlay::from_request(entry.overlay.as_ref())?),
+            _ if entry.overlay.is_some() => {
+                return Err(format!("early exit:\noverlay only applies in overlay mode.").into())
+            }
+            _ => None
+        };
+        let parameters: PreDefines = PreDefines::create(&config, batch_size, starting_threads, max_threads,
                                                         &entry.output_file, &entry.input_file,
                                                         entry.image_compression.clone().is_some())?;
 
         let fps: Option<f64> = entry.fps.map(|num: Number| { num.as_f64() }).and_then(|x| x);
         let image_compression: Option<u64> = entry.image_compression.map(|num: Number| { num.as_u64() }).and_then(|x| x);
-        let video_compression: Option<u64> = entry.video_compression.map(|num: Number| { num.as_u64() }).and_then(|x| x);
-        early_exit(&parameters.client, &entry.output_file)?;
-        let video = get_file(&entry.input_file, &parameters.local_input_file, &parameters.data_api_work_directory, &parameters.client)?;
-        let scatter_data: Scattered = processing::scatter(&parameters.ffmpeg, &video, &parameters.scattered_working_directory,
-                                                          &parameters.scatter_regex, fps, image_compression)?;
-
-        let video_file: PathBuf = match objective {
-            Objective::Transform => {
-                let processed_data = processing::transform(&parameters.client, &entry.algorithm, entry.advanced_input.as_ref(),
-                                                           &scatter_data, &parameters.data_api_work_directory, &parameters.processed_working_directory,
-                                                           &parameters.process_regex, parameters.max_threads, parameters.starting_threads, parameters.batch_size)?;
-                let gathered: Gathered = processing::gather(&parameters.ffmpeg, &parameters.video_working_directory, &parameters.local_output_file, processed_data,
-                                                            scatter_data.original_video(), video_compression)?;
-                gathered.video_file().clone()
-            }
-            Objective::Extract => {
-                let duration: f64 = parameters.ffmpeg.get_video_duration(&parameters.local_input_file)?;
-                let processed_data: Value = processing::extract(&parameters.client, &entry.algorithm,
-                                                                entry.advanced_input.as_ref(), &scatter_data,
-                                                                &parameters.data_api_work_directory,
-                                                                parameters.starting_threads, parameters.max_threads,
-                                                                duration, batch_size)?;
-                let saved_file: PathBuf = json_to_file(&processed_data, &parameters.local_output_file)?;
-                saved_file
+        let output_storage = parameters.storages.resolve(&entry.output_file)?;
+        let work_storage = parameters.storages.resolve(&parameters.data_api_work_directory)?;
+        early_exit(output_storage.as_ref(), &entry.output_file)?;
+        let cache: Option<Arc<ResultCache>> = match entry.cache {
+            Some(ref directory) => Some(Arc::new(ResultCache::new(parameters.storages.resolve(directory)?, directory, &parameters.cache_working_directory))),
+            None => None
+        };
+        //shared by every chunk, so the limit it settles on carries over.
+        let controller = Arc::new(ConcurrencyController::new(parameters.starting_threads as usize, parameters.max_threads as usize, max_requests_per_second));
+        let video = get_file(&entry.input_file, &parameters.local_input_file, &parameters.data_api_work_directory, &parameters.storages, &parameters.client)?;
+        //a replacement soundtrack is fetched up front, so a bad uri fails before any frames are processed.
+        let audio: Option<AudioTrack> = match audio {
+            Some(audio) => match audio.source().clone() {
+                AudioSource::Replaced(uri) => {
+                    let extension = match uri.rsplit('.').next() {
+                        Some(ext) if !ext.contains('/') => ext.to_string(),
+                        _ => "audio".to_string()
+                    };
+                    create_directory(&parameters.video_working_directory);
+                    let local = parameters.video_working_directory.join(format!("replacement_audio.{}", extension));
+                    let local = get_file(&uri, &local, &parameters.data_api_work_directory, &parameters.storages, &parameters.client)?;
+                    Some(audio.with_local_file(local))
+                }
+                _ => Some(audio)
+            },
+            None => None
+        };
+        let info: MediaInfo = parameters.ffmpeg.probe(&video)?;
+        let duration: f64 = info.duration()?;
+        let ranges: Option<Vec<TimeRange>> = TimeRange::from_request(entry.start_time.as_ref().and_then(|num| num.as_f64()),
+                                                                     entry.end_time.as_ref().and_then(|num| num.as_f64()),
+                                                                     entry.segments.as_ref(), duration)?;
+        let chunk_duration: Option<f64> = entry.chunk_duration.as_ref().and_then(|num| num.as_f64());
+        if let Some(chunk_duration) = chunk_duration {
+            if chunk_duration <= 0f64 {
+                return Err(format!("early exit:\nchunk_duration must be a positive number of seconds.").into())
+            }
+            if ranges.is_some() {
+                return Err(format!("early exit:\nchunk_duration can't be combined with start_time, end_time or segments.").into())
+            }
+        }
+        if objective.is_audio() {
+            if ranges.is_some() || chunk_duration.is_some() {
+                return Err(format!("early exit:\n{} mode processes the whole audio track, it can't be combined with start_time, end_time, segments or chunk_duration.", objective.name()).into())
+            }
+            if info.audio_streams().is_empty() {
+                return Err(format!("early exit:\n{} has no audio track to process.", entry.input_file).into())
+            }
+        }
+        let job_id = Checkpoint::job_id(&video, &json!({
+            "algorithm": &entry.algorithm,
+            "advanced_input": &entry.advanced_input,
+            "mode": objective.name(),
+            "fps": fps,
+            "image_compression": image_compression,
+            "sampling": sampling.name(),
+            "dedup": dedup.map(|dedup| dedup.name()),
+            "ranges": ranges.as_ref().map(|ranges| ranges.iter().map(|range| (range.start(), range.end())).collect::<Vec<(f64, f64)>>()),
+            "chunk_duration": chunk_duration,
+            "audio_window": if objective.is_audio() { json!(audio_window) } else { Value::Null },
+            //finished chunks are already encoded, so a chunked job can only resume with the same encoding.
+            "output_encoding": if chunk_duration.is_some() { json!([&entry.output_encoding, video_compression]) } else { Value::Null }
+        }))?;
+        //local processes never write anything remotely, so there's nothing to resume from there.
+        //the manifest & processed frames are kept next to the output, the data work directory only lasts for one session when running as an algorithm.
+        let checkpoint_directory = format!("{}/.checkpoints", entry.output_file.rsplitn(2, '/').nth(1).unwrap_or(&entry.output_file));
+        let remote_checkpoint = if processor::is_local(&entry.algorithm) { None } else { Some((output_storage.clone(), checkpoint_directory.as_str())) };
+        let checkpoint = Arc::new(Checkpoint::create(&job_id, &parameters.processed_working_directory, remote_checkpoint)?);
+
+        let video_file: PathBuf = match chunk_duration {
+            Some(chunk_duration) => {
+                self.chunked(&entry, &parameters, objective, work_storage.clone(), &video, &info, chunk_duration,
+                             fps, image_compression, encoding.as_ref(), audio.as_ref(), extract_output.as_ref(), events.as_ref(), overlay.as_ref(), &sampling, dedup.as_ref(), cache.clone(), controller.clone(), &checkpoint)?
+            }
+            None => {
+                let scatter_data: Scattered = if objective.is_audio() {
+                    processing::scatter_audio(&parameters.ffmpeg, &video, &parameters.scattered_working_directory, &parameters.audio_regex, audio_window)?
+                } else {
+                    processing::scatter(&parameters.ffmpeg, &video, &parameters.scattered_working_directory,
+                                        &parameters.scatter_regex, fps, image_compression, &sampling,
+                                        ranges.as_ref(), objective.encodes_frames(), dedup.as_ref())?
+                };
+                match objective {
+                    Objective::Transform => {
+                        let processed_data = processing::transform(&parameters.client, work_storage.clone(), &entry.algorithm, entry.advanced_input.as_ref(),
+                                                                   &scatter_data, &parameters.data_api_work_directory, checkpoint.clone(),
+                                                                   controller.clone(), parameters.batch_size,
+                                                                   self.progress.clone(), cache.clone())?;
+                        let gathered: Gathered = processing::gather(&parameters.ffmpeg, &parameters.video_working_directory, &parameters.local_output_file, processed_data,
+                                                                    &scatter_data, encoding.as_ref().expect("transform jobs always have an output encoding"),
+                                                                    audio.as_ref().expect("transform jobs always have an audio track"))?;
+                        gathered.video_file().clone()
+                    }
+                    Objective::Overlay => {
+                        let results: Value = processing::extract(&parameters.client, work_storage.clone(), &entry.algorithm,
+                                                                 entry.advanced_input.as_ref(), &scatter_data,
+                                                                 &parameters.data_api_work_directory, checkpoint.clone(),
+                                                                 controller.clone(),
+                                                                 batch_size, self.progress.clone(), cache.clone())?;
+                        let processed_data = processing::overlay(&parameters.ffmpeg, &results, overlay.as_ref().expect("overlay jobs always have an overlay"),
+                                                                 &scatter_data, checkpoint.as_ref())?;
+                        let gathered: Gathered = processing::gather(&parameters.ffmpeg, &parameters.video_working_directory, &parameters.local_output_file, processed_data,
+                                                                    &scatter_data, encoding.as_ref().expect("overlay jobs always have an output encoding"),
+                                                                    audio.as_ref().expect("overlay jobs always have an audio track"))?;
+                        gathered.video_file().clone()
+                    }
+                    Objective::AudioTransform => {
+                        let processed_data = processing::transform(&parameters.client, work_storage.clone(), &entry.algorithm, entry.advanced_input.as_ref(),
+                                                                   &scatter_data, &parameters.data_api_work_directory, checkpoint.clone(),
+                                                                   controller.clone(), parameters.batch_size,
+                                                                   self.progress.clone(), cache.clone())?;
+                        let gathered: Gathered = processing::gather_audio(&parameters.ffmpeg, &parameters.video_working_directory, &parameters.local_output_file,
+                                                                          processed_data, &scatter_data)?;
+                        gathered.video_file().clone()
+                    }
+                    //each window's timestamp is when it starts.
+                    Objective::Extract | Objective::AudioExtract => {
+                        let processed_data: Value = processing::extract(&parameters.client, work_storage.clone(), &entry.algorithm,
+                                                                        entry.advanced_input.as_ref(), &scatter_data,
+                                                                        &parameters.data_api_work_directory, checkpoint.clone(),
+                                                                        controller.clone(),
+                                                                        batch_size, self.progress.clone(), cache.clone())?;
+                        let extract_output = extract_output.as_ref().expect("extract jobs always have an output format");
+                        let processed_data: Value = match events {
+                            Some(ref events) => events.aggregate(processed_data, duration, ranges.as_ref())?,
+                            None => processed_data
+                        };
+                        let saved_file: PathBuf = extract_output.write(&processed_data, &parameters.local_output_file, duration, ranges.as_ref())?;
+                        saved_file
+                    }
+                }
             }
         };
-        let uploaded = upload_file(&entry.output_file, &video_file, &parameters.client)?;
-        let result = Exit { output_file: uploaded };
+        let uploaded = upload_file(&entry.output_file, &video_file, output_storage.as_ref())?;
+        checkpoint.finish();
+        //hits & misses count frames, summed over every chunk.
+        let cache = cache.map(|cache| {
+            println!("result cache: {} hits, {} misses.", cache.hits(), cache.misses());
+            json!({"hits": cache.hits(), "misses": cache.misses()})
+        });
+        let metrics = controller.metrics();
+        println!("concurrency limit ended at {}, between {} & {}, {} of {} calls were throttled.",
+                 metrics.limit, metrics.lowest_limit, metrics.highest_limit, metrics.throttled, metrics.calls);
+        let result = Exit { output_file: uploaded, cache: cache };
         Ok(AlgoOutput::from(&result))
     }
 }
 
+impl Algo {
+    //splits the video into chunks of chunk_duration seconds that are scattered, processed & encoded one at a time, so disk use & MAX_FRAMES only apply per chunk.
+    //finished chunks are kept in the job's checkpoint directory, a resumed job skips them.
+    fn chunked(&self,
+               entry: &Entry,
+               parameters: &PreDefines,
+               objective: Objective,
+               work_storage: Arc<Storage>,
+               video: &Path,
+               info: &MediaInfo,
+               chunk_duration: f64,
+               fps: Option<f64>,
+               image_compression: Option<u64>,
+               encoding: Option<&OutputEncoding>,
+               audio: Option<&AudioTrack>,
+               extract_output: Option<&ExtractOutput>,
+               events: Option<&EventRules>,
+               overlay: Option<&Overlay>,
+               sampling: &Sampling,
+               dedup: Option<&Dedup>,
+               cache: Option<Arc<ResultCache>>,
+               controller: Arc<ConcurrencyController>,
+               checkpoint: &Checkpoint) -> Result<PathBuf, VideoError> {
+        if objective.is_audio() {
+            return Err(format!("early exit:\n{} mode can't be chunked.", objective.name()).into())
+        }
+        //uniformly sampled chunks are cut on frame boundaries, so the encoded chunks stay in step with the remuxed audio.
+        let frame_rate: Option<f64> = match sampling {
+            &Sampling::Uniform => Some(processing::uniform_fps(fps, info.fps()?)),
+            _ => None
+        };
+        let chunks = TimeRange::chunks(info.duration()?, chunk_duration, frame_rate);
+        let finished_directory = checkpoint.local_directory().join("chunks");
+        let chunk_checkpoints = checkpoint.local_directory().join("chunk_frames");
+        create_directory(&finished_directory);
+        let extension = match objective {
+            Objective::Transform | Objective::Overlay => parameters.local_output_file.extension().and_then(|ext| ext.to_str()).unwrap_or("mp4").to_string(),
+            _ => "json".to_string()
+        };
+        let properties = VideoProperties::from_stream(info.video_stream()?);
+        let mut finished: Vec<PathBuf> = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let chunk_file = finished_directory.join(format!("chunk-{:05}.{}", index, extension));
+            if chunk_file.exists() {
+                println!("chunk {} of {} was already processed.", index + 1, chunks.len());
+                finished.push(chunk_file);
+                continue
+            }
+            println!("processing chunk {} of {}, {}s - {}s.", index + 1, chunks.len(), chunk.start(), chunk.end());
+            let _ = remove_dir_all(&parameters.scattered_working_directory);
+            let scatter_data: Scattered = processing::scatter(&parameters.ffmpeg, video, &parameters.scattered_working_directory,
+                                                              &parameters.scatter_regex, fps, image_compression, sampling,
+                                                              Some(&vec![chunk.clone()]), false, dedup)?;
+            if scatter_data.num_frames() == 0 {
+                println!("chunk {} has no frames, skipping it.", index + 1);
+                continue
+            }
+            //each chunk gets its own checkpoint inside the job's, creating it removes the frames of the chunk before.
+            let chunk_checkpoint = Arc::new(Checkpoint::create(&format!("{}-{:05}", checkpoint.id(), index), &chunk_checkpoints, checkpoint.remote())?);
+            //written next to the finished file & renamed, so a chunk killed mid write isn't mistaken for a finished one.
+            let partial_file = finished_directory.join(format!("partial-{:05}.{}", index, extension));
+            match objective {
+                Objective::Transform => {
+                    let processed_data = processing::transform(&parameters.client, work_storage.clone(), &entry.algorithm, entry.advanced_input.as_ref(),
+                                                               &scatter_data, &parameters.data_api_work_directory, chunk_checkpoint.clone(),
+                                                               controller.clone(), parameters.batch_size,
+                                                               self.progress.clone(), cache.clone())?;
+                    let encoding = encoding.expect("transform jobs always have an output encoding");
+                    processing::encode_frames(&parameters.ffmpeg, &partial_file, &processed_data, &scatter_data, encoding, None)
+                        .map_err(|err| format!("failed to encode chunk {}:\n{}", index + 1, err))?;
+                }
+                Objective::Overlay => {
+                    let results: Value = processing::extract(&parameters.client, work_storage.clone(), &entry.algorithm,
+                                                             entry.advanced_input.as_ref(), &scatter_data,
+                                                             &parameters.data_api_work_directory, chunk_checkpoint.clone(),
+                                                             controller.clone(),
+                                                             parameters.batch_size, self.progress.clone(), cache.clone())?;
+                    let processed_data = processing::overlay(&parameters.ffmpeg, &results, overlay.expect("overlay jobs always have an overlay"),
+                                                             &scatter_data, chunk_checkpoint.as_ref())?;
+                    let encoding = encoding.expect("overlay jobs always have an output encoding");
+                    processing::encode_frames(&parameters.ffmpeg, &partial_file, &processed_data, &scatter_data, encoding, None)
+                        .map_err(|err| format!("failed to encode chunk {}:\n{}", index + 1, err))?;
+                }
+                _ => {
+                    let processed_data: Value = processing::extract(&parameters.client, work_storage.clone(), &entry.algorithm,
+                                                                    entry.advanced_input.as_ref(), &scatter_data,
+                                                                    &parameters.data_api_work_directory, chunk_checkpoint.clone(),
+                                                                    controller.clone(),
+                                                                    parameters.batch_size, self.progress.clone(), cache.clone())?;
+                    json_to_file(&processed_data, &partial_file)?;
+                }
+            }
+            rename(&partial_file, &chunk_file)?;
+            chunk_checkpoint.finish();
+            finished.push(chunk_file);
+        }
+        match objective {
+            Objective::Transform | Objective::Overlay => {
+                let gathered: Gathered = processing::gather_chunks(&parameters.ffmpeg, &parameters.video_working_directory, &parameters.local_output_file,
+                                                                   &finished, video, &properties,
+                                                                   audio.expect("transform jobs always have an audio track"))?;
+                Ok(gathered.video_file().clone())
+            }
+            _ => {
+                let mut parts: Vec<Value> = Vec::new();
+                for chunk_file in &finished {
+                    let file = File::open(chunk_file)?;
+                    parts.push(serde_json::from_reader(file)?);
+                }
+                let extract_output = extract_output.expect("extract jobs always have an output format");
+                let combined: Value = match events {
+                    Some(events) => events.aggregate(concat_data_extract(parts)?, info.duration()?, None)?,
+                    None => concat_data_extract(parts)?
+                };
+                extract_output.write(&combined, &parameters.local_output_file, info.duration()?, None)
+            }
+        }
+    }
+}
+
+impl Algo {
+    //uses this config instead of loading one from the environment for every request.
+    pub fn with_config(config: Config) -> Algo {
+        Algo { config: Some(config), progress: None }
+    }
+
+    //reports every finished batch of frames, used by the cli to draw a progress bar.
+    pub fn with_progress(self, progress: Arc<Progress>) -> Algo {
+        Algo { progress: Some(progress), ..self }
+    }
+}
+
 impl Default for Algo {
     fn default() -> Algo {
-        Algo
+        Algo { config: None, progress: None }
     }
 }
 
@@ -111,72 +487,623 @@ impl Default for Algo {
 mod test {
     use super::Algo;
     use super::algorithmia::prelude::*;
+    use common::mock_server::{MockServer, MockResponse, identity};
+    use common::preprocess::ExecutionStyle;
+    use common::config::Config;
+    use common::storage::Storage;
+    use serde_json::{self, Value};
     use std::borrow::Cow;
+    use std::error::Error;
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::{Mutex, MutexGuard};
 
+    static TEST_KEY: &'static str = "simTestKey";
+    static INPUT_FILE: &'static str = "data://.my/test/input.mp4";
+
+    lazy_static! {
+        //every run shares the /tmp working directories & rayon's global pool, so only one can run at a time.
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    //held for the whole of every end to end test, None when ffmpeg or ffprobe isn't installed, those tests are skipped then.
+    fn serial() -> Option<MutexGuard<'static, ()>> {
+        let installed = |program: &str| Command::new(program).arg("-version").output().map(|output| output.status.success()).unwrap_or(false);
+        if !installed("ffmpeg") || !installed("ffprobe") {
+            println!("skipping, the end to end tests need ffmpeg & ffprobe on the PATH.");
+            return None
+        }
+        Some(SERIAL.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    //2 seconds of 10fps test pattern with a sine wave audio track.
+    fn upload_sample_video(server: &MockServer) {
+        upload_video(server, &["-c:v", "libx264", "-pix_fmt", "yuv420p"]);
+    }
+
+    fn upload_video(server: &MockServer, video_args: &[&str]) {
+        let local = Path::new("/tmp/mock_test/input.mp4");
+        ::common::file_mgmt::create_directory(local.parent().unwrap());
+        let mut args: Vec<&str> = vec!["-loglevel", "error",
+                                       "-f", "lavfi", "-i", "testsrc=duration=2:size=64x64:rate=10",
+                                       "-f", "lavfi", "-i", "sine=duration=2"];
+        args.extend(video_args);
+        args.extend(&["-c:a", "aac", "-shortest", local.to_str().unwrap(), "-y"]);
+        let response = Command::new("ffmpeg").args(&args).output().unwrap();
+        assert!(response.status.success(), "failed to create sample video: {}", String::from_utf8_lossy(&response.stderr));
+        server.data().put(INPUT_FILE, local).unwrap();
+    }
+
+    fn run(server: &MockServer, raw: Value) -> Result<AlgoOutput, Box<Error>> {
+        let json = AlgoInput::Json(Cow::Owned(raw));
+        let config = Config::new(ExecutionStyle::TestLocal, Some(server.url().to_string()), Some(TEST_KEY.to_string()), None, None).unwrap();
+        Algo::with_config(config).apply(json)
+    }
+
+    #[test]
+    fn local_process_refused_in_algo_style_test() {
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/blurred.mp4",
+    "algorithm" : "exec://convert",
+    "advanced_input" : ["$SINGLE_INPUT", "-blur", "0x8", "$SINGLE_OUTPUT"]
+    });
+        let config = Config::new(ExecutionStyle::Algo, None, None, None, None).unwrap();
+        let result = Algo::with_config(config).apply(AlgoInput::Json(Cow::Owned(raw)));
+        let err = result.err().expect("exec:// ran in algo style").to_string();
+        assert!(err.contains("early exit"), "unexpected error: {}", err);
+    }
+
+    fn count_streams(file: &Path, kind: &str) -> usize {
+        let response = Command::new("ffprobe")
+            .args(&["-v", "error", "-select_streams", kind, "-show_entries", "stream=index",
+                "-of", "csv=p=0", file.to_str().unwrap()]).output().unwrap();
+        String::from_utf8_lossy(&response.stdout).lines().filter(|line| !line.trim().is_empty()).count()
+    }
 
     #[test]
     fn basic_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/SalNet", identity("image", "location"));
         let raw = json!({
-    "input_file" : " data://zeryx/streaming_collection/stream_file_2.mp4",
-    "output_file" : "data://quality/Videos/stream_file.mp4",
-    "algorithm":"algo://algorithmiahq/deepfashion",
-    "advanced_input": {  "image": "$SINGLE_INPUT","output": "$SINGLE_OUTPUT","model":"small","tags_only": false},
-    "fps":10,
-//    "video_compression" : 30,
-    "image_compression" : 30
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/salnet.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 5
     });
-        println!("data: {:?}", &raw);
-        let json = AlgoInput::Json(Cow::Owned(raw));
-        let result = Algo::default().apply(json);
-        match result {
-            Ok(_) => { println!("completed success"); }
-            Err(ref err) => { println!("errored: {}", err); }
-        }
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/salnet.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/salnet.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "v"), 1);
+        assert_eq!(count_streams(local, "a"), 1);
+        assert_eq!(server.calls("deeplearning/SalNet"), 10);
     }
 
     #[test]
     fn advanced_batch_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("cv/CensorFace", identity("images", "output_loc"));
         let advanced_input = json!({
     "images": "$BATCH_INPUT",
     "output_loc": "$BATCH_OUTPUT",
     "fill_color": "blur"
     });
         let raw = json!({
-    "input_file" : "data://media/videos/kigsman.mkv",
-    "output_file" : "data://quality/Videos/kigsman_censored.mp4",
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/censored.mp4",
     "algorithm" : "algo://cv/CensorFace",
     "fps" : 10,
-//    "video_compression" : 25,
-//    "image_compression" : 35,
     "advanced_input" : advanced_input
     });
-        let json = AlgoInput::Json(Cow::Owned(raw));
-        let result = Algo::default().apply(json);
-        match result {
-            Ok(_) => { println!("completed success"); }
-            Err(ref err) => { println!("errored: {}", err); }
-        }
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        assert!(server.data().contents("data://.my/test/censored.mp4").is_some());
+        //20 frames in batches of 5
+        assert_eq!(server.calls("cv/CensorFace"), 4);
     }
 
     #[test]
     fn advanced_single_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        //the first couple of calls get throttled, they should be retried rather than failing the job.
+        let filter = identity("image", "location");
+        server.script("deeplearning/SalNet", move |input: &Value, call: usize, data: &_| {
+            if call < 2 {
+                MockResponse::Error(400, "algorithm hit max number of active calls per session".to_string())
+            } else {
+                filter(input, call, data)
+            }
+        });
         let advanced_input = json!({
     "image": "$SINGLE_INPUT",
     "location": "$SINGLE_OUTPUT",
     });
         let raw = json!({
-    "input_file" : "data://quality/videos/kenny_test.mp4",
-    "output_file" : "data://quality/Videos/kenny_filtered.mp4",
-    "algorithm" : "algo://deeplearning/SalNet",
-    "fps" : 30,
-//    "video_compression" : 25,
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/filtered.mp4",
+    "algorithm" : "algo://deeplearning/SalNet/0.2.0",
+    "fps" : 5,
     "advanced_input" : advanced_input
     });
-        let json = AlgoInput::Json(Cow::Owned(raw));
-        let result = Algo::default().apply(json);
-        match result {
-            Ok(_) => { println!("completed success"); }
-            Err(ref err) => { println!("errored: {}", err); }
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        assert!(server.data().contents("data://.my/test/filtered.mp4").is_some());
+        assert_eq!(server.calls("deeplearning/SalNet"), 12);
+    }
+
+    #[test]
+    fn advanced_extract_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/IllustrationTagger", |input: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tagged": input["image"].clone()}))
+        });
+        let advanced_input = json!({
+    "image": "$SINGLE_INPUT"
+    });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/tags.json",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "fps" : 5,
+    "advanced_input" : advanced_input
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/tags.json").expect("output json was not uploaded");
+        let parsed: Value = serde_json::from_slice(&output).unwrap();
+        let frames = parsed["frame_data"].as_array().unwrap();
+        assert_eq!(frames.len(), 10);
+        assert_eq!(frames[0]["timestamp"].as_f64(), Some(0f64));
+        assert!(frames[0]["data"]["tagged"].as_str().unwrap().starts_with("data://.my/ProcessVideo/"));
+    }
+
+    #[test]
+    fn scene_sampling_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/IllustrationTagger", |input: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tagged": input["image"].clone()}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/scenes.json",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "sampling" : {"strategy": "scene", "threshold": 0.9},
+    "advanced_input" : {"image": "$SINGLE_INPUT"}
+    });
+        let result = run(&server, raw.clone());
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/scenes.json").expect("output json was not uploaded");
+        let parsed: Value = serde_json::from_slice(&output).unwrap();
+        let timestamps: Vec<f64> = parsed["frame_data"].as_array().unwrap().iter()
+            .map(|frame| frame["timestamp"].as_f64().unwrap()).collect();
+        //the test pattern barely changes, so we should get far fewer frames than the 20 in the video.
+        assert!(!timestamps.is_empty() && timestamps.len() < 20, "got {} scene frames", timestamps.len());
+        assert_eq!(timestamps[0], 0f64);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(server.calls("deeplearning/IllustrationTagger"), timestamps.len());
+        //fps means nothing when frames are picked by scene changes, so it's rejected rather than ignored.
+        let mut with_fps = raw.clone();
+        with_fps.as_object_mut().unwrap().insert("fps".to_string(), json!(5));
+        let err = run(&server, with_fps).err().expect("fps with scene sampling should be rejected");
+        assert!(err.to_string().contains("early exit"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn time_range_transform_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/SalNet", identity("image", "location"));
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/partial.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 10,
+    "start_time": 0.5,
+    "end_time": 1
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/partial.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/partial.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "v"), 1);
+        //the audio is cut from the same spans as the video parts & re-encoded.
+        assert_eq!(count_streams(local, "a"), 1);
+        //only the frames between 0.5s & 1s go through the algorithm, the rest of the keyframe aligned region is kept as is.
+        assert_eq!(server.calls("deeplearning/SalNet"), 5);
+    }
+
+    #[test]
+    fn time_range_mismatched_input_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        server.script("deeplearning/SalNet", identity("image", "location"));
+        //an h264 input the default encoding doesn't match, & an input that isn't h264 at all. keyframes every 0.5s leave untouched parts either side.
+        let inputs: Vec<(Vec<&str>, &str, &str)> = vec![(vec!["-c:v", "libx264", "-pix_fmt", "yuv444p", "-g", "5", "-video_track_timescale", "1000"], "h264", "yuv444p"),
+                                                         (vec!["-c:v", "mpeg4", "-g", "5"], "h264", "yuv420p")];
+        for (video_args, codec, pixel_format) in inputs {
+            upload_video(&server, &video_args);
+            let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/partial.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 10,
+    "start_time": 0.5,
+    "end_time": 1
+    });
+            let result = run(&server, raw);
+            assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+            let output = server.data().contents("data://.my/test/partial.mp4").expect("output video was not uploaded");
+            let local = Path::new("/tmp/mock_test/partial.mp4");
+            ::std::fs::write(local, &output).unwrap();
+            let probed = Command::new("ffprobe")
+                .args(&["-v", "error", "-select_streams", "v", "-count_frames", "-show_entries", "stream=codec_name,pix_fmt,nb_read_frames",
+                    "-of", "csv=p=0", local.to_str().unwrap()]).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&probed.stdout).trim(), format!("{},{},20", codec, pixel_format));
+            let decoded = Command::new("ffmpeg")
+                .args(&["-v", "error", "-i", local.to_str().unwrap(), "-f", "null", "-"]).output().unwrap();
+            assert!(decoded.stderr.is_empty(), "output doesn't decode cleanly: {}", String::from_utf8_lossy(&decoded.stderr));
         }
     }
+
+    #[test]
+    fn time_range_extract_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/IllustrationTagger", |input: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tagged": input["image"].clone()}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/segment_tags.json",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "fps" : 5,
+    "segments" : [{"start": 1, "end": 1.4}],
+    "advanced_input" : {"image": "$SINGLE_INPUT"}
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/segment_tags.json").expect("output json was not uploaded");
+        let parsed: Value = serde_json::from_slice(&output).unwrap();
+        let frames = parsed["frame_data"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["timestamp"].as_f64(), Some(1f64));
+        assert_eq!(frames[1]["timestamp"].as_f64(), Some(1.2f64));
+    }
+
+    #[test]
+    fn chunked_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/SalNet", identity("image", "location"));
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/chunked.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 10,
+    "chunk_duration": 0.5
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/chunked.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/chunked.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "v"), 1);
+        assert_eq!(count_streams(local, "a"), 1);
+        //4 chunks of 5 frames.
+        assert_eq!(server.calls("deeplearning/SalNet"), 20);
+    }
+
+    #[test]
+    fn source_sampling_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/SalNet", identity("image", "location"));
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/source_timed.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "sampling": "source"
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/source_timed.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/source_timed.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "a"), 1);
+        //every original frame, none duplicated.
+        assert_eq!(server.calls("deeplearning/SalNet"), 20);
+        let frames = Command::new("ffprobe")
+            .args(&["-v", "error", "-select_streams", "v:0", "-count_packets", "-show_entries", "stream=nb_read_packets",
+                "-of", "csv=p=0", local.to_str().unwrap()]).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&frames.stdout).trim(), "20");
+    }
+
+    #[test]
+    fn algorithm_failure_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/SalNet", |_: &Value, _: usize, _: &_| {
+            MockResponse::Error(429, "too many requests".to_string())
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/never.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 5
+    });
+        let result = run(&server, raw);
+        let err = result.err().expect("job should fail when every algorithm call is rejected");
+        assert!(err.to_string().contains("video processing failed"), "unexpected error: {}", err);
+        assert!(server.data().contents("data://.my/test/never.mp4").is_none());
+    }
+
+    #[test]
+    fn resume_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        //the first attempt dies after 4 frames.
+        let filter = identity("image", "location");
+        server.script("deeplearning/SalNet", move |input: &Value, call: usize, data: &_| {
+            if call < 4 { filter(input, call, data) } else { MockResponse::Error(429, "too many requests".to_string()) }
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/resumed.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 5
+    });
+        assert!(run(&server, raw.clone()).is_err());
+        let first_attempt = server.calls("deeplearning/SalNet");
+        //a new session starts without the local checkpoint or the data work directory, so both the manifest & the frames have to come from next to the output.
+        let _ = ::std::fs::remove_dir_all("/tmp/processed_frames");
+        server.data().delete_directory("data://.my/ProcessVideo", true).unwrap();
+        assert!(!server.data().files("data://.my/test/.checkpoints").is_empty());
+        server.script("deeplearning/SalNet", identity("image", "location"));
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        assert!(server.data().contents("data://.my/test/resumed.mp4").is_some());
+        let second_attempt = server.calls("deeplearning/SalNet") - first_attempt;
+        //only the 6 frames that failed the first time are sent again.
+        assert_eq!(second_attempt, 6);
+        //nothing is left to resume, so the checkpoint is removed from the output's directory.
+        assert_eq!(server.data().files("data://.my/test/.checkpoints"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn invalid_output_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.read_only("data://.my/locked");
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/locked/output.mp4",
+    "algorithm": "algo://deeplearning/SalNet",
+    "fps": 5
+    });
+        let err = run(&server, raw).err().expect("job should fail on an unwritable output path");
+        assert!(err.to_string().contains("early exit"), "unexpected error: {}", err);
+        assert_eq!(server.calls("deeplearning/SalNet"), 0);
+    }
+
+    #[test]
+    fn audio_transform_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("audio/Denoise", identity("audio", "output"));
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/denoised.mp4",
+    "algorithm" : "algo://audio/Denoise",
+    "audio_window" : 0.5,
+    "advanced_input" : {"audio": "$AUDIO_INPUT", "output": "$AUDIO_OUTPUT"}
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/denoised.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/denoised.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "v"), 1);
+        assert_eq!(count_streams(local, "a"), 1);
+        //2 seconds of audio in half second windows
+        assert_eq!(server.calls("audio/Denoise"), 4);
+    }
+
+    #[test]
+    fn audio_windows_in_another_format_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        //sends every window back at half the sample rate, in stereo & 8 bit.
+        server.script("audio/Denoise", |input: &Value, call: usize, data: &_| {
+            let window = format!("/tmp/mock_test/window-{}.wav", call);
+            let converted = format!("/tmp/mock_test/converted-{}.wav", call);
+            ::std::fs::write(&window, &data.contents(input["audio"].as_str().unwrap()).unwrap()).unwrap();
+            let response = Command::new("ffmpeg")
+                .args(&["-loglevel", "error", "-i", window.as_str(), "-ar", "22050", "-ac", "2", "-c:a", "pcm_u8", converted.as_str(), "-y"])
+                .output().unwrap();
+            assert!(response.status.success(), "failed to convert window: {}", String::from_utf8_lossy(&response.stderr));
+            data.put(input["output"].as_str().unwrap(), Path::new(&converted)).unwrap();
+            MockResponse::Result(json!({"output": input["output"].clone()}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/resampled.mp4",
+    "algorithm" : "algo://audio/Denoise",
+    "audio_window" : 0.5,
+    "advanced_input" : {"audio": "$AUDIO_INPUT", "output": "$AUDIO_OUTPUT"}
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/resampled.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/resampled.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        let probed = Command::new("ffprobe")
+            .args(&["-v", "error", "-select_streams", "a", "-show_entries", "stream=sample_rate,channels,duration",
+                "-of", "csv=p=0", local.to_str().unwrap()]).output().unwrap();
+        let probed = String::from_utf8_lossy(&probed.stdout).trim().to_string();
+        let fields: Vec<&str> = probed.split(',').collect();
+        //back at the original's rate & layout, & still 2 seconds long.
+        assert_eq!(&fields[..2], &["44100", "1"]);
+        assert!((fields[2].parse::<f64>().unwrap() - 2f64).abs() < 0.1, "audio is {}s long", fields[2]);
+    }
+
+    #[test]
+    fn caption_output_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/IllustrationTagger", |_: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tags": [{"name": "test pattern"}]}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/tags.vtt",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "fps" : 5,
+    "output_format" : {"field": "/tags/0/name"},
+    "advanced_input" : {"image": "$SINGLE_INPUT"}
+    });
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/tags.vtt").expect("output captions were not uploaded");
+        let captions = String::from_utf8(output).unwrap();
+        //every frame has the same tag, so they all merge into a single cue.
+        assert!(captions.starts_with("WEBVTT\n\n00:00:00.000 --> "), "unexpected captions: {}", captions);
+        assert_eq!(captions.matches("-->").count(), 1);
+        assert!(captions.trim_right().ends_with("test pattern"));
+    }
+
+    #[test]
+    fn overlay_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("cv/ObjectDetection", |_: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"objects": [{"box": [0.25, 0.25, 0.75, 0.75]}]}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/annotated.mp4",
+    "algorithm" : "algo://cv/ObjectDetection",
+    "mode" : "overlay",
+    "fps" : 5,
+    "advanced_input" : {"image": "$SINGLE_INPUT"},
+    "overlay" : {"boxes": "/objects", "left": "/box/0", "top": "/box/1", "right": "/box/2", "bottom": "/box/3",
+                 "normalized": true, "label": null, "score": null}
+    });
+        let raw_overlay = raw.clone();
+        let result = run(&server, raw);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/annotated.mp4").expect("output video was not uploaded");
+        let local = Path::new("/tmp/mock_test/annotated.mp4");
+        ::std::fs::write(local, &output).unwrap();
+        assert_eq!(count_streams(local, "v"), 1);
+        assert_eq!(count_streams(local, "a"), 1);
+        assert_eq!(server.calls("cv/ObjectDetection"), 10);
+        //the whole video is one keyframe region, only the frames between 0.5s & 1s are sent to the algorithm.
+        let mut ranged = raw_overlay.clone();
+        ranged.as_object_mut().unwrap().insert("fps".to_string(), json!(10));
+        ranged.as_object_mut().unwrap().insert("start_time".to_string(), json!(0.5));
+        ranged.as_object_mut().unwrap().insert("end_time".to_string(), json!(1));
+        let result = run(&server, ranged);
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        assert_eq!(server.calls("cv/ObjectDetection"), 15);
+    }
+
+    #[test]
+    fn dedup_fan_out_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        //0.3s with the left half lit, 0.2s with the right half lit, then the left half again.
+        let left = "color=black:s=64x64:r=10:d=0.3,drawbox=x=0:y=0:w=32:h=64:color=white:t=fill";
+        let right = "color=black:s=64x64:r=10:d=0.2,drawbox=x=32:y=0:w=32:h=64:color=white:t=fill";
+        let local = Path::new("/tmp/mock_test/input.mp4");
+        ::common::file_mgmt::create_directory(local.parent().unwrap());
+        let response = Command::new("ffmpeg")
+            .args(&["-loglevel", "error", "-f", "lavfi", "-i", left, "-f", "lavfi", "-i", right, "-f", "lavfi", "-i", left,
+                "-filter_complex", "[0:v][1:v][2:v]concat=n=3:v=1:a=0", "-c:v", "libx264", "-pix_fmt", "yuv420p",
+                local.to_str().unwrap(), "-y"]).output().unwrap();
+        assert!(response.status.success(), "failed to create sample video: {}", String::from_utf8_lossy(&response.stderr));
+        server.data().put(INPUT_FILE, local).unwrap();
+        server.script("deeplearning/IllustrationTagger", |input: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tagged": input["image"].clone()}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/deduped.json",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "fps" : 10,
+    "dedup" : "perceptual",
+    "advanced_input" : {"image": "$SINGLE_INPUT"}
+    });
+        let result = run(&server, raw.clone());
+        assert!(result.is_ok(), "errored: {}", result.err().unwrap());
+        let output = server.data().contents("data://.my/test/deduped.json").expect("output json was not uploaded");
+        let parsed: Value = serde_json::from_slice(&output).unwrap();
+        let tagged: Vec<Value> = parsed["frame_data"].as_array().unwrap().iter().map(|frame| frame["data"]["tagged"].clone()).collect();
+        assert_eq!(tagged.len(), 8);
+        //each run shares its first frame's result, the second left run looks like the first but gets its own.
+        assert_eq!((&tagged[1], &tagged[2]), (&tagged[0], &tagged[0]));
+        assert_eq!(tagged[4], tagged[3]);
+        assert!(tagged[5] != tagged[0] && tagged[3] != tagged[0]);
+        assert_eq!((&tagged[6], &tagged[7]), (&tagged[5], &tagged[5]));
+        assert_eq!(server.calls("deeplearning/IllustrationTagger"), 3);
+        //duplicates would be sent another frame's index.
+        let mut indexed = raw.clone();
+        indexed.as_object_mut().unwrap().insert("advanced_input".to_string(), json!({"image": "$SINGLE_INPUT", "index": "$FRAME_INDEX"}));
+        let err = run(&server, indexed).err().expect("dedup with frame variables should be rejected");
+        assert!(err.to_string().contains("early exit"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn result_cache_test() {
+        let _lock = match serial() { Some(lock) => lock, None => return };
+        let server = MockServer::start();
+        upload_sample_video(&server);
+        server.script("deeplearning/IllustrationTagger", |_: &Value, _: usize, _: &_| {
+            MockResponse::Result(json!({"tags": [{"name": "test pattern"}]}))
+        });
+        let raw = json!({
+    "input_file" : INPUT_FILE,
+    "output_file" : "data://.my/test/tags.json",
+    "algorithm" : "algo://deeplearning/IllustrationTagger/0.2.3",
+    "mode" : "extract",
+    "fps" : 5,
+    "cache" : "data://.my/test/cache",
+    "advanced_input" : {"image": "$SINGLE_INPUT"}
+    });
+        let first = run(&server, raw.clone());
+        assert!(first.is_ok(), "errored: {}", first.err().unwrap());
+        let second = run(&server, raw);
+        assert!(second.is_ok(), "errored: {}", second.err().unwrap());
+        //the second run finds every frame in the cache.
+        assert_eq!(server.calls("deeplearning/IllustrationTagger"), 10);
+        assert!(server.data().contents("data://.my/test/tags.json").is_some());
+    }
 }
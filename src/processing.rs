@@ -1,13 +1,22 @@
 use algorithmia::Algorithmia;
 use std::path::*;
 use common::file_mgmt;
+use common::watchdog::Progress;
+use common::storage::Storage;
+use common::checkpoint::Checkpoint;
+use common::cache::{ResultCache, CacheScope};
+use common::concurrency::ConcurrencyController;
+use std::sync::Arc;
 use rayon;
+use rayon::prelude::*;
 use serde_json::Value;
 use common::structs::ffmpeg::FFMpeg;
 use common::video_error::VideoError;
 use common::structs::prelude::*;
+use common::structs::processor::{self, processor_for};
 use uuid::Uuid;
-use std::ascii::AsciiExt;
+use std::fs::{copy, remove_file};
+use std::collections::BTreeMap;
 
 //import all packages
 use transform;
@@ -16,17 +25,114 @@ use extract;
 static MAX_FPS: f64 = 60f64;
 static MAX_FRAMES: u64 = 10000;
 
-//split video limits the fps to FPSMAX, if its higher we only sample at FPSMAX
+//split video limits the fps to FPSMAX, if its higher we only sample at FPSMAX. scene sampling ignores fps and keeps a frame per scene change instead.
+//with ranges only those parts of the video are split, transforms widen them out to keyframes so the rest of the video can be copied untouched in gather.
 pub fn scatter(ffmpeg: &FFMpeg,
                video_file: &Path,
                frame_dir: &Path,
                regex: &str,
                fps: Option<f64>,
-               compression_factor: Option<u64>) -> Result<Scattered, VideoError> {
+               compression_factor: Option<u64>,
+               sampling: &Sampling,
+               ranges: Option<&Vec<TimeRange>>,
+               align_to_keyframes: bool,
+               dedup: Option<&Dedup>) -> Result<Scattered, VideoError> {
     file_mgmt::create_directory(frame_dir);
     println!("scattering video into frames and audio");
-    let origin_fps = ffmpeg.get_video_fps(video_file)?;
-    let output_fps = match fps {
+    let info = ffmpeg.probe(video_file)?;
+    let origin_fps = info.fps()?;
+    let duration:f64 = info.duration()?;
+    let properties = VideoProperties::from_stream(info.video_stream()?);
+    //ffmpeg applies the rotation when splitting, so rotated frames come out with width & height swapped.
+    let dimensions = match (info.video_stream()?.width(), info.video_stream()?.height()) {
+        (Some(width), Some(height)) if properties.rotation() == 90 || properties.rotation() == 270 => Some((height, width)),
+        (Some(width), Some(height)) => Some((width, height)),
+        _ => None
+    };
+    let too_many_frames = format!("early exit:\nInput videos total number of frames greater than {}, please reduce fps or reduce the total size of the video file.", MAX_FRAMES);
+    let regions: Option<Vec<TimeRange>> = match ranges {
+        Some(ranges) if align_to_keyframes => Some(TimeRange::align_to_keyframes(ranges, &ffmpeg.keyframes(video_file)?, duration)),
+        Some(ranges) => Some(ranges.clone()),
+        None => None
+    };
+    let spans: Vec<Option<&TimeRange>> = match regions {
+        Some(ref regions) => regions.iter().map(|region| Some(region)).collect(),
+        None => vec![None]
+    };
+    let output_fps = match sampling {
+        &Sampling::Uniform => uniform_fps(fps, origin_fps),
+        &Sampling::Scene(_) => origin_fps,
+        //r_frame_rate is the finest timing in a variable frame rate video, the average is closer to the real number of frames.
+        &Sampling::Source => info.video_stream()?.avg_frame_rate().unwrap_or(origin_fps)
+    };
+    //scene sampling can't know how many frames it keeps until it's done.
+    if !is_scene(sampling) {
+        let scattered_duration: f64 = spans.iter().map(|span| span.map(|range| range.duration()).unwrap_or(duration)).sum();
+        let num_frames: u64 = (scattered_duration*output_fps).ceil() as u64;
+        if num_frames > MAX_FRAMES {
+            return Err(too_many_frames.into())
+        }
+    }
+
+    let mut timestamps: Vec<f64> = Vec::new();
+    let mut processed_parts: Vec<Part> = Vec::new();
+    for span in spans.iter().cloned() {
+        let first_frame = timestamps.len() + 1;
+        let offset = span.map(|range| range.start()).unwrap_or(0f64);
+        let span_timestamps: Vec<f64> = match sampling {
+            &Sampling::Uniform => {
+                let result = ffmpeg.split_video(video_file, frame_dir, &regex, output_fps, &compression_factor, span, first_frame)?;
+                //the fps filter puts frame n at n / fps seconds from the start of the span.
+                (0..result.len() - timestamps.len()).map(|frame| offset + frame as f64 / output_fps).collect()
+            }
+            &Sampling::Scene(threshold) => {
+                let (_, scene_timestamps) = ffmpeg.split_video_scenes(video_file, frame_dir, &regex, threshold, &compression_factor, span, first_frame)?;
+                scene_timestamps
+            }
+            &Sampling::Source => {
+                let (_, frame_timestamps) = ffmpeg.split_video_source(video_file, frame_dir, &regex, &compression_factor, span, first_frame)?;
+                frame_timestamps
+            }
+        };
+        processed_parts.push(Part::Processed { first_frame: first_frame, frame_count: span_timestamps.len(),
+                                               region: span.cloned().unwrap_or(TimeRange::new(0f64, duration)) });
+        timestamps.extend(span_timestamps);
+    }
+    if timestamps.len() as u64 > MAX_FRAMES {
+        return Err(too_many_frames.into())
+    }
+    if is_scene(sampling) {
+        println!("found {} scenes.", timestamps.len());
+    }
+
+    let (passthrough, parts) = match (ranges, regions) {
+        (Some(ranges), Some(regions)) if align_to_keyframes => {
+            let passthrough: Vec<usize> = timestamps.iter().enumerate()
+                .filter(|&(_, time)| !ranges.iter().any(|range| range.contains(*time)))
+                .map(|(frame, _)| frame + 1).collect();
+            let mut parts: Vec<(f64, Part)> = regions.iter().map(|region| region.start()).zip(processed_parts.into_iter()).collect();
+            parts.extend(TimeRange::gaps(&regions, duration).into_iter().map(|gap| (gap.start(), Part::Original(gap))));
+            parts.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            (passthrough, Some(parts.into_iter().map(|(_, part)| part).collect()))
+        }
+        _ => (Vec::new(), None)
+    };
+    //passthrough frames aren't processed, so they can't stand in for frames that are.
+    let duplicates = match dedup {
+        Some(dedup) => {
+            let duplicates = dedup.duplicates(ffmpeg, frame_dir, regex, timestamps.len(), &passthrough)?;
+            println!("found {} duplicate frames, {} of {} frames will be processed.", duplicates.len(), timestamps.len() - passthrough.len() - duplicates.len(), timestamps.len());
+            duplicates
+        }
+        None => BTreeMap::new()
+    };
+    Ok(Scattered::new(PathBuf::from(frame_dir), timestamps.len(), PathBuf::from(video_file), output_fps, regex.to_string(), timestamps, passthrough, parts, properties,
+                      *sampling == Sampling::Source, dimensions, duplicates))
+}
+
+//the fps uniform sampling splits at, the requested one or the original's up to MAX_FPS.
+pub fn uniform_fps(fps: Option<f64>, origin_fps: f64) -> f64 {
+    match fps {
         Some(fps) => {fps},
         None => {
             if origin_fps <= MAX_FPS {
@@ -36,73 +142,254 @@ pub fn scatter(ffmpeg: &FFMpeg,
                     MAX_FPS
                 }
         }
+    }
+}
+
+fn is_scene(sampling: &Sampling) -> bool {
+    match sampling {
+        &Sampling::Scene(_) => true,
+        _ => false
+    }
+}
+
+//splits the first audio track of video_file into windows of window seconds, they're processed like frames are.
+pub fn scatter_audio(ffmpeg: &FFMpeg,
+                     video_file: &Path,
+                     windows_dir: &Path,
+                     regex: &str,
+                     window: f64) -> Result<Scattered, VideoError> {
+    file_mgmt::create_directory(windows_dir);
+    println!("scattering audio into {}s windows", window);
+    let (windows, starts) = ffmpeg.split_audio(video_file, windows_dir, regex, window)?;
+    if windows.len() as u64 > MAX_FRAMES {
+        return Err(format!("early exit:\nInput audio splits into more than {} windows, please increase audio_window.", MAX_FRAMES).into())
+    }
+    Ok(Scattered::audio_windows(PathBuf::from(windows_dir), windows.len(), PathBuf::from(video_file), window, regex.to_string(), starts))
+}
+
+//joins processed audio windows end to end and swaps them in for the original video's audio.
+pub fn gather_audio(ffmpeg: &FFMpeg,
+                    video_working_directory: &Path,
+                    output_file: &Path,
+                    data: Altered,
+                    scattered: &Scattered) -> Result<Gathered, VideoError> {
+    println!("gathering audio windows into the output.");
+    if data.frames().len() != scattered.num_frames() {
+        return Err(format!("gather failed:\nfound {} processed audio windows in {}, expected {}.", data.frames().len(), data.frames_dir().display(), scattered.num_frames()).into())
+    }
+    file_mgmt::create_directory(video_working_directory);
+    let filename = Uuid::new_v4();
+    let joined_audio = PathBuf::from(format!("{}/audio-{}.wav", video_working_directory.display(), filename));
+    let list_file = PathBuf::from(format!("{}/windows-{}.txt", video_working_directory.display(), filename));
+    let original_file = scattered.original_video();
+    let original = ffmpeg.probe(original_file)?;
+    let (sample_rate, channels) = match original.audio_streams().first() {
+        Some(stream) => match (stream.sample_rate(), stream.channels()) {
+            (Some(sample_rate), Some(channels)) => (sample_rate, channels),
+            _ => return Err(format!("gather failed:\ncould not find the sample rate & channels of {}'s audio.", original_file.display()).into())
+        },
+        None => return Err(format!("gather failed:\n{} has no audio track.", original_file.display()).into())
     };
-    let duration:f64 = ffmpeg.get_video_duration(video_file)?;
-    let num_frames: u64 = (duration*output_fps).ceil() as u64;
-    if num_frames <= MAX_FRAMES {
-        let result = ffmpeg.split_video(video_file, frame_dir, &regex, output_fps, &compression_factor)?;
-        Ok(Scattered::new(PathBuf::from(frame_dir), result.len(), PathBuf::from(video_file), output_fps, regex.to_string()))
-    }
-        else {
-            Err(format!("early exit:\nInput videos total number of frames greater than {}, please reduce fps or reduce the total size of the video file.", MAX_FRAMES).into())
+    //algorithms can return windows in any format, rate or layout, so each one is decoded & resampled to the original's before they're joined end to end.
+    let mut resampled: Vec<PathBuf> = Vec::new();
+    for (index, window) in data.frames().iter().enumerate() {
+        let resampled_window = PathBuf::from(format!("{}/window-{}-{}.wav", video_working_directory.display(), filename, index + 1));
+        let result = ffmpeg.resample_audio(window, &resampled_window, sample_rate, channels);
+        resampled.push(resampled_window);
+        if let Err(err) = result {
+            for file in &resampled {
+                let _ = remove_file(file);
+            }
+            return Err(format!("gather failed while resampling audio window {}:\n{}", index + 1, err).into())
         }
+    }
+    let joined = ffmpeg.concat(&resampled, &list_file, &joined_audio);
+    for file in &resampled {
+        let _ = remove_file(file);
+    }
+    joined.map_err(|err| format!("gather failed while joining {} audio windows:\n{}", data.frames().len(), err))?;
+    let output = ffmpeg.replace_audio(original_file, &joined_audio, output_file)
+        .map_err(|err| format!("gather failed while replacing the audio of {}:\n{}", original_file.display(), err))?;
+    let _ = remove_file(&joined_audio);
+    Ok(Gathered::new(output))
 }
 
-//combines video frames in directory frames_dir with audio_file to create a video file.
+//combines video frames in directory frames_dir with the subtitle streams of the original video & the chosen audio to create a video file.
+//if only parts of the video were processed, those are encoded separately and joined with the untouched parts of the original,
+//which are copied as is when the processed parts could be encoded to match them & re-encoded with the processed parts' settings when they couldn't.
 pub fn gather(ffmpeg: &FFMpeg,
-                video_working_directory: &Path,
+              video_working_directory: &Path,
               output_file: &Path,
               data: Altered,
-              original_file: &Path,
-              crf: Option<u64>) -> Result<Gathered, VideoError> {
+              scattered: &Scattered,
+              encoding: &OutputEncoding,
+              audio: &AudioTrack) -> Result<Gathered, VideoError> {
     println!("gathering frames and audio into video.");
+    if data.frames().is_empty() {
+        return Err(format!("gather failed:\nno processed frames were found in {}.", data.frames_dir().display()).into())
+    }
+    let original_file = scattered.original_video();
+    file_mgmt::create_directory(video_working_directory);
     let filename = Uuid::new_v4();
     let extension = output_file.extension().ok_or(format!("failed to find a file extension for output file."))?.to_str().unwrap();
     let catted_video_no_audio = PathBuf::from(format!("{}/{}-{}.{}", video_working_directory.display(), "streamless", filename, extension));
-    // let catted_video_with_audio = PathBuf::from(format!("{}/{}-{}.{}", video_working_directory.display(), "with_streams", filename, extension));
-    // ffmpeg.cat_video(&catted_video_no_audio, data.frames_dir(), data.regex(), data.fps(), crf)?;
-//    let video_with_streams = ffmpeg.attach_streams(&catted_video_no_audio, &catted_video_with_audio, &original_file)?;
-    Ok(Gathered::new(catted_video_no_audio))
+    let trimmed_audio: Option<AudioTrack> = match scattered.parts() {
+        None => {
+            encode_frames(ffmpeg, &catted_video_no_audio, &data, scattered, encoding, None)
+                .map_err(|err| format!("gather failed while concatenating {} processed frames:\n{}", data.frames().len(), err))?;
+            None
+        }
+        Some(parts) => {
+            let original = ffmpeg.probe(original_file)?;
+            let original_stream = original.video_stream()?;
+            //processed parts are encoded the way the original was where the encoder can, so the untouched parts are copied without re-encoding.
+            let matching: Option<OutputEncoding> = encoding.matching(original_stream);
+            let part_encoding: OutputEncoding = match matching {
+                Some(ref matching) => matching.clone(),
+                None => encoding.spliced(original_stream)
+            };
+            let part_files: Vec<PathBuf> = (0..parts.len())
+                .map(|index| PathBuf::from(format!("{}/part-{}-{}.{}", video_working_directory.display(), filename, index, extension))).collect();
+            for (part, part_file) in parts.iter().zip(part_files.iter()) {
+                if let &Part::Processed { first_frame, frame_count, .. } = part {
+                    encode_frames(ffmpeg, part_file, &data, scattered, &part_encoding, Some((first_frame, frame_count)))
+                        .map_err(|err| format!("gather failed while encoding frames {} - {}:\n{}", first_frame, first_frame + frame_count - 1, err))?;
+                }
+            }
+            //the encoder doesn't honour every setting for every input, so what it made is checked against the original before copying next to it.
+            let processed_file: Option<&PathBuf> = parts.iter().zip(part_files.iter())
+                .filter_map(|(part, part_file)| match part { &Part::Processed { .. } => Some(part_file), _ => None }).next();
+            let copy_untouched = matching.is_some() && match processed_file {
+                Some(processed_file) => original_stream.joins_with(ffmpeg.probe(processed_file)?.video_stream()?),
+                None => true
+            };
+            if !copy_untouched {
+                println!("the processed parts can't be joined to a copy of the original's {} video, re-encoding the untouched parts too.",
+                         original_stream.codec().unwrap_or("unknown"));
+            }
+            for (part, part_file) in parts.iter().zip(part_files.iter()) {
+                if let &Part::Original(ref range) = part {
+                    if copy_untouched {
+                        ffmpeg.copy_range(original_file, part_file, range)
+                            .map_err(|err| format!("gather failed while copying untouched video:\n{}", err))?;
+                    } else {
+                        ffmpeg.encode_range(original_file, part_file, range, &part_encoding, scattered.properties())
+                            .map_err(|err| format!("gather failed while re-encoding untouched video:\n{}", err))?;
+                    }
+                }
+            }
+            //each part's audio is cut from the same span of the original as its video, as long as the video part came out, so every part stays in sync.
+            let mut audio_ranges: Vec<TimeRange> = Vec::new();
+            for (part, part_file) in parts.iter().zip(part_files.iter()) {
+                let length = ffmpeg.probe(part_file)?.duration()?;
+                audio_ranges.push(TimeRange::new(part.region().start(), part.region().start() + length));
+            }
+            let list_file = PathBuf::from(format!("{}/parts-{}.txt", video_working_directory.display(), filename));
+            let joined = ffmpeg.concat(&part_files, &list_file, &catted_video_no_audio);
+            for part_file in &part_files {
+                let _ = remove_file(part_file);
+            }
+            joined.map_err(|err| format!("gather failed while joining processed & untouched video:\n{}", err))?;
+            Some(audio.clone().trimmed_to(audio_ranges))
+        }
+    };
+    let audio = trimmed_audio.as_ref().unwrap_or(audio);
+    let video_with_streams = ffmpeg.attach_streams(&catted_video_no_audio, output_file, original_file, scattered.properties(), audio)
+        .map_err(|err| format!("gather failed while copying audio & subtitle streams from {}:\n{}", original_file.display(), err))?;
+    let _ = remove_file(&catted_video_no_audio);
+    Ok(Gathered::new(video_with_streams))
+}
+
+//encodes processed frames first_frame..first_frame + frame_count, or all of them, at a constant fps or at the original's timestamps when the scatter kept them.
+pub fn encode_frames(ffmpeg: &FFMpeg,
+                     output_file: &Path,
+                     data: &Altered,
+                     scattered: &Scattered,
+                     encoding: &OutputEncoding,
+                     frames: Option<(usize, usize)>) -> Result<PathBuf, VideoError> {
+    if scattered.exact_timing() {
+        let (first_frame, frame_count) = frames.unwrap_or((1, data.frames().len()));
+        let selected = (first_frame - 1)..(first_frame - 1 + frame_count);
+        if selected.end > data.frames().len() || selected.end > scattered.timestamps().len() {
+            return Err(format!("frames {} - {} are out of range, there are only {} processed frames.", first_frame, selected.end, data.frames().len()).into())
+        }
+        ffmpeg.cat_video_timed(output_file, &data.frames()[selected.clone()], &scattered.timestamps()[selected], 1f64 / data.fps(),
+                               encoding, scattered.properties())
+    } else {
+        ffmpeg.cat_video(output_file, data.frames_dir(), data.regex(), data.fps(), encoding, scattered.properties(), frames)
+    }
+}
+
+//joins video-only chunks encoded one at a time, then attaches the subtitle streams of the original video & the chosen audio.
+pub fn gather_chunks(ffmpeg: &FFMpeg,
+                     video_working_directory: &Path,
+                     output_file: &Path,
+                     chunk_files: &Vec<PathBuf>,
+                     original_file: &Path,
+                     properties: &VideoProperties,
+                     audio: &AudioTrack) -> Result<Gathered, VideoError> {
+    println!("joining {} chunks and audio into video.", chunk_files.len());
+    if chunk_files.is_empty() {
+        return Err(format!("gather failed:\nno chunks were processed.").into())
+    }
+    file_mgmt::create_directory(video_working_directory);
+    let filename = Uuid::new_v4();
+    let extension = output_file.extension().ok_or(format!("failed to find a file extension for output file."))?.to_str().unwrap();
+    let catted_video_no_audio = PathBuf::from(format!("{}/{}-{}.{}", video_working_directory.display(), "streamless", filename, extension));
+    let list_file = PathBuf::from(format!("{}/chunks-{}.txt", video_working_directory.display(), filename));
+    ffmpeg.concat(chunk_files, &list_file, &catted_video_no_audio)
+        .map_err(|err| format!("gather failed while joining {} chunks:\n{}", chunk_files.len(), err))?;
+    let video_with_streams = ffmpeg.attach_streams(&catted_video_no_audio, output_file, original_file, properties, audio)
+        .map_err(|err| format!("gather failed while copying audio & subtitle streams from {}:\n{}", original_file.display(), err))?;
+    let _ = remove_file(&catted_video_no_audio);
+    Ok(Gathered::new(video_with_streams))
 }
 
 // alter branch, used by VideoTransform
 pub fn transform(client: &Algorithmia,
+                 storage: Arc<Storage>,
                  algorithm: &str,
                  algo_input: Option<&Value>,
                  data: &Scattered,
                  remote_dir: &str,
-                 local_out_dir: &Path,
-                 output_regex: &str,
-                 max_threads: isize,
-                 starting_threads: isize,
-                 batch_size: usize) -> Result<Altered, VideoError> {
-    let config = rayon::Configuration::new().set_num_threads(max_threads as usize);
-    println!("starting threads: {}", starting_threads);
-    rayon::initialize(config)?;
-    let alter = Alter::new(client.clone(),
+                 checkpoint: Arc<Checkpoint>,
+                 controller: Arc<ConcurrencyController>,
+                 batch_size: usize,
+                 progress: Option<Arc<Progress>>,
+                 cache: Option<Arc<ResultCache>>) -> Result<Altered, VideoError> {
+    init_thread_pool(controller.as_ref());
+    let extension = Path::new(data.regex()).extension().and_then(|ext| ext.to_str()).unwrap_or("png");
+    let output_regex = checkpoint.output_regex(extension);
+    keep_passthrough_frames(data, checkpoint.as_ref(), &output_regex)?;
+    let pending = unique_frames(checkpoint.pending_frames(data.num_frames(), Some(&output_regex))?, data);
+    let context = FrameContext::new(checkpoint.id(), data.fps(), data.dimensions(), data.timestamps().clone());
+    let alter = Alter::new(processor_for(algorithm, client, storage, remote_dir),
                            data.regex().clone(),
-                           output_regex.clone(),
-                           local_out_dir.clone(),
+                           &output_regex,
+                           checkpoint.local_directory(),
                            data.frames_dir().clone(),
-                           remote_dir.clone());
+                           context);
     //batch size is only used if the algorithm accepts batching and/or the user defined advanced input has a $BATCH_FILE_INPUT & $BATCH_FILE_OUTPUT designated.
     match algo_input {
         Some(advanced_input) => {
             println!("advanced input found");
-            let search: AdvancedInput = AdvancedInput::create_transform(advanced_input)?;
-            transform::executor::advanced(alter, data.num_frames(), data.fps(), algorithm, batch_size, starting_threads, max_threads, search)
+            let search: AdvancedInput = if data.is_audio() { AdvancedInput::create_audio_transform(advanced_input)? } else { AdvancedInput::create_transform(advanced_input)? };
+            let scope = cache_scope(cache, algorithm, &search, "transform");
+            transform::executor::advanced(alter, data.num_frames(), pending, data.duplicates(), data.fps(), algorithm, batch_size, controller, search, progress, checkpoint, scope)
         }
-        //no custom json input, so we use defaults.
+        //no custom json input, so we use defaults. there are none for audio.
+        None if data.is_audio() => Err(String::from("No default audio algorithm definition, advanced_input required.").into()),
         None => {
-            if algorithm.to_ascii_lowercase().as_str().contains("deepfilter") {
-                transform::executor::default(alter, data.num_frames(), data.fps(), batch_size, starting_threads, max_threads, &transform::functions::deep_filter)
-            } else if algorithm.to_ascii_lowercase().as_str().contains("salnet") {
-                transform::executor::default(alter, data.num_frames(), data.fps(), batch_size, starting_threads, max_threads, &transform::functions::salnet)
-            } else if algorithm.to_ascii_lowercase().as_str().contains("colorfulimagecolorization") {
-                transform::executor::default(alter, data.num_frames(), data.fps(), batch_size, starting_threads, max_threads, &transform::functions::colorful_colorization)
-            } else {
-                println!("failed to pattern match anything.");
-                Err(String::from("No default algorithm definition, advanced_input required.").into())
+            match transform_default(algorithm) {
+                Some((default_algorithm, default_input)) => {
+                    let search: AdvancedInput = AdvancedInput::create_transform(&default_input)?;
+                    let scope = cache_scope(cache, default_algorithm, &search, "transform");
+                    transform::executor::advanced(alter, data.num_frames(), pending, data.duplicates(), data.fps(), default_algorithm, batch_size, controller, search, progress, checkpoint, scope)
+                }
+                None => {
+                    println!("failed to pattern match anything.");
+                    Err(String::from("No default algorithm definition, advanced_input required.").into())
+                }
             }
         }
     }
@@ -110,40 +397,129 @@ pub fn transform(client: &Algorithmia,
 
 //extract branch, used by VideoMetadataExtraction
 pub fn extract(client: &Algorithmia,
+               storage: Arc<Storage>,
                algorithm: &str,
                algo_input: Option<&Value>,
                data: &Scattered,
                remote_dir: &str,
-                starting_threads: isize,
-               max_threads: isize,
-               duration: f64,
-               batch_size: usize) -> Result<Value, VideoError> {
-    let config = rayon::Configuration::new().set_num_threads(max_threads as usize);
-    println!("starting threads: {}", starting_threads);
-    rayon::initialize(config)?;
-
-    let extract = Extract::new(client.clone(),
+               checkpoint: Arc<Checkpoint>,
+               controller: Arc<ConcurrencyController>,
+               batch_size: usize,
+               progress: Option<Arc<Progress>>,
+               cache: Option<Arc<ResultCache>>) -> Result<Value, VideoError> {
+    init_thread_pool(controller.as_ref());
+    //frames outside the requested ranges are drawn as they are in overlay mode, so they're recorded without a result instead of being sent to the algorithm.
+    if !data.passthrough().is_empty() {
+        checkpoint.record(data.passthrough(), None);
+    }
+    let pending = unique_frames(checkpoint.pending_frames(data.num_frames(), None)?, data);
+
+    let context = FrameContext::new(checkpoint.id(), data.fps(), data.dimensions(), data.timestamps().clone());
+    let extract = Extract::new(processor_for(algorithm, client, storage, remote_dir),
                                data.regex().clone(),
                                data.frames_dir().clone(),
-                               remote_dir.clone());
+                               context);
 
     match algo_input {
         Some(advanced_input) => {
             println!("advanced input found");
-            let search: AdvancedInput = AdvancedInput::create_extract(advanced_input)?;
-            extract::executor::advanced(extract, data.num_frames(), algorithm,  batch_size, duration, starting_threads, max_threads, search)
+            let search: AdvancedInput = if data.is_audio() { AdvancedInput::create_audio_extract(advanced_input)? } else { AdvancedInput::create_extract(advanced_input)? };
+            let scope = cache_scope(cache, algorithm, &search, "extract");
+            extract::executor::advanced(extract, data.timestamps(), pending, data.duplicates(), algorithm,  batch_size, controller, search, progress, checkpoint, scope)
         }
-        //no custom json input, so we use defaults.
+        //no custom json input, so we use defaults. there are none for audio.
+        None if data.is_audio() => Err(String::from("No default audio algorithm definition, advanced_input required.").into()),
         None => {
-            if algorithm.to_ascii_lowercase().as_str().contains("nuditydetection") {
-                extract::executor::default(extract, data.num_frames(), batch_size, duration, starting_threads, max_threads, &extract::functions::nudity_detection)
-            } else if algorithm.to_ascii_lowercase().as_str().contains("illustrationtagger") {
-                extract::executor::default(extract, data.num_frames(), batch_size, duration, starting_threads, max_threads, &extract::functions::illustration_tagger)
-            } else {
-                println!("failed to pattern match anything.");
-                Err(String::from("not implemented.").into())
+            match extract_default(algorithm) {
+                Some((default_algorithm, default_input)) => {
+                    let search: AdvancedInput = AdvancedInput::create_extract(&default_input)?;
+                    let scope = cache_scope(cache, default_algorithm, &search, "extract");
+                    extract::executor::advanced(extract, data.timestamps(), pending, data.duplicates(), default_algorithm, batch_size, controller, search, progress, checkpoint, scope)
+                }
+                None => {
+                    println!("failed to pattern match anything.");
+                    Err(String::from("No default algorithm definition, advanced_input required.").into())
+                }
             }
         }
     }
 }
 
+//overlay branch, draws each frame's extract result onto it so the frames can be gathered like transformed ones.
+//frames outside the requested ranges are copied as they are.
+pub fn overlay(ffmpeg: &FFMpeg,
+               results: &Value,
+               overlay: &Overlay,
+               data: &Scattered,
+               checkpoint: &Checkpoint) -> Result<Altered, VideoError> {
+    println!("drawing results onto {} frames.", data.num_frames());
+    let frame_data = results["frame_data"].as_array().ok_or(format!("extract result did not contain any frame_data."))?;
+    if frame_data.len() != data.num_frames() {
+        return Err(format!("got {} extracted results for {} frames.", frame_data.len(), data.num_frames()).into())
+    }
+    let extension = Path::new(data.regex()).extension().and_then(|ext| ext.to_str()).unwrap_or("png");
+    let output_regex = checkpoint.output_regex(extension);
+    let frames: Vec<usize> = (1..data.num_frames() + 1).collect();
+    let mut drawn: Vec<Result<PathBuf, VideoError>> = Vec::new();
+    frames.par_iter().map(|frame| {
+        let input = data.frames_dir().join(file_mgmt::from_regex(data.regex(), *frame)?);
+        let output = checkpoint.local_directory().join(file_mgmt::from_regex(&output_regex, *frame)?);
+        let filters = if data.passthrough().contains(frame) { None } else { overlay.filters(&frame_data[*frame - 1]["data"]) };
+        match filters {
+            Some(filters) => ffmpeg.draw(&input, &output, &filters),
+            None => {
+                copy(&input, &output).map_err(|err| format!("failed to keep frame {} as is\n{}", input.display(), err))?;
+                Ok(output)
+            }
+        }
+    }).collect_into(&mut drawn);
+    let frames: Vec<PathBuf> = drawn.into_iter().collect::<Result<Vec<PathBuf>, VideoError>>()?;
+    Ok(Altered::new(PathBuf::from(checkpoint.local_directory()), frames, data.fps(), output_regex))
+}
+
+//frames outside the requested ranges are copied to the output as they are, and recorded so they're never sent to the algorithm.
+fn keep_passthrough_frames(data: &Scattered, checkpoint: &Checkpoint, output_regex: &str) -> Result<(), VideoError> {
+    if data.passthrough().is_empty() {
+        return Ok(())
+    }
+    for frame in data.passthrough() {
+        let input = data.frames_dir().join(file_mgmt::from_regex(data.regex(), *frame)?);
+        let output = checkpoint.local_directory().join(file_mgmt::from_regex(output_regex, *frame)?);
+        copy(&input, &output).map_err(|err| format!("failed to keep frame {} as is\n{}", input.display(), err))?;
+    }
+    checkpoint.record(data.passthrough(), None);
+    Ok(())
+}
+
+//the part of the cache this algorithm & input can use, if there's a cache & its results can be reused.
+fn cache_scope(cache: Option<Arc<ResultCache>>, algorithm: &str, input: &AdvancedInput, kind: &str) -> Option<CacheScope> {
+    match cache {
+        Some(cache) => CacheScope::new(cache, algorithm, input, kind),
+        None => None
+    }
+}
+
+//duplicate frames get their representative's result, so they're never processed themselves.
+fn unique_frames(pending: Vec<usize>, data: &Scattered) -> Vec<usize> {
+    pending.into_iter().filter(|frame| !data.duplicates().contains_key(frame)).collect()
+}
+
+//finds the default transform algorithm & input for an algorithm, if we have one. local processes never have defaults.
+pub fn transform_default(algorithm: &str) -> Option<(&'static str, Value)> {
+    if processor::is_local(algorithm) { None } else { transform::functions::default_input(algorithm) }
+}
+
+//finds the default extract algorithm & input for an algorithm, if we have one. local processes never have defaults.
+pub fn extract_default(algorithm: &str) -> Option<(&'static str, Value)> {
+    if processor::is_local(algorithm) { None } else { extract::functions::default_input(algorithm) }
+}
+
+//rayon's global pool can only be set up once per process, later requests reuse it.
+//the pool has a thread for every call the controller could ever allow at once.
+fn init_thread_pool(controller: &ConcurrencyController) -> () {
+    let config = rayon::Configuration::new().set_num_threads(controller.max());
+    println!("concurrency limit: {} of {}", controller.limit(), controller.max());
+    if let Err(err) = rayon::initialize(config) {
+        println!("thread pool already initialized, reusing it: {}", err);
+    }
+}
@@ -0,0 +1,42 @@
+//standalone entry points that don't need a full request, used by the videotransform cli.
+use std::error::Error;
+use std::path::*;
+use serde_json::Value;
+use common::config::Config;
+use common::storage::Storages;
+use common::structs::ffmpeg::FFMpeg;
+use common::structs::sampling::Sampling;
+use common::video_error::VideoError;
+use processing;
+
+static FFMPEG_REMOTE: &'static str = "data://media/bin/ffmpeg-static.tar.gz";
+
+//describes a local video file.
+pub fn probe(video: &Path) -> Result<Value, Box<Error>> {
+    let ffmpeg = local_ffmpeg()?;
+    let info = ffmpeg.probe(video)?;
+    Ok(json!({
+        "file": video.to_str().unwrap(),
+        "duration": info.duration()?,
+        "fps": info.fps()?,
+        "frames": info.frame_count()?,
+        "format": info.format(),
+        "bit_rate": info.bit_rate(),
+        "tags": info.tags(),
+        "streams": info.streams()
+    }))
+}
+
+//splits a local video into numbered frames in output_dir, returns the number of frames written.
+pub fn split(video: &Path, output_dir: &Path, fps: Option<f64>, image_compression: Option<u64>) -> Result<usize, Box<Error>> {
+    let ffmpeg = local_ffmpeg()?;
+    let regex = if image_compression.is_some() { "frame-%07d.jpg" } else { "frame-%07d.png" };
+    let scattered = processing::scatter(&ffmpeg, video, output_dir, regex, fps, image_compression, &Sampling::Uniform, None, false, None)?;
+    Ok(scattered.num_frames())
+}
+
+fn local_ffmpeg() -> Result<FFMpeg, VideoError> {
+    let config = Config::load()?;
+    let storages = Storages::new(&config.client());
+    FFMpeg::create(FFMPEG_REMOTE, &config.local_work_directory().join("ffmpeg"), storages.resolve(FFMPEG_REMOTE)?.as_ref())
+}
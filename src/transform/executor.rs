@@ -1,94 +1,86 @@
-use algorithmia::Algorithmia;
 use std::path::*;
 use rayon::prelude::*;
-use super::functions::{advanced_batch, advanced_single};
+use super::functions::process;
 use common::video_error::VideoError;
-use common::watchdog::Watchdog;
+use common::watchdog::{Watchdog, Progress};
 use common::threading::*;
 use common::misc;
+use common::checkpoint::Checkpoint;
+use common::cache::CacheScope;
+use common::concurrency::ConcurrencyController;
+use common::file_mgmt::from_regex;
 use common::structs::prelude::*;
 use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::fs::copy;
 use std::io::{self, Write};
 
-//used to template all of the default image proc algorithms, uses rayon for multi-threading and uses Arc<Mutex> locking to fail early if an exception is found.
-pub fn default(
-               data: Alter,
-               number_of_frames: usize,
-               fps: f64,
-               batch_size: usize,
-               starting_threads: isize,
-                max_threads: isize,
-               function: &Default<Alter, PathBuf>) -> Result<Altered, VideoError> {
-    //generate batches of frames by number, based on the batch size.
-    let frame_batches: Box<Vec<Vec<usize>>> = misc::frame_batches_simple(batch_size, number_of_frames);
-    let mut result: Vec<Result<Vec<PathBuf>, ()>> = Vec::new();
-    let out_dir = PathBuf::from(data.local_output());
-    let out_regex = data.output_regex().to_string();
-    let global_threadable = Threadable::create(starting_threads, max_threads, data);
-    let inner_threadable = global_threadable.clone();
-
-    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len());
-    let wd_t = wd.get_comms();
-    frame_batches.par_iter().map(move |batch| {
-        let res = try_algorithm_default(function, &batch, &inner_threadable);
-        if res.is_ok() {
-            wd_t.send_success_signal();
-        }
-        res
-    }).weight(1f64).collect_into(&mut result);
-    wd.terminate();
-    let signal = global_threadable.extract_term_signal();
-    match signal {
-        None => {
-            println!("failed");
-            let processed_frames: Vec<PathBuf> = result.into_iter().collect::<Result<Vec<Vec<_>>, _>>().unwrap().concat();
-            let out = Altered::new(PathBuf::from(out_dir), processed_frames, fps, out_regex.to_string());
-            Ok(out)
-        }
-        Some(err) => Err(format!("error, video processing failed: {}", err).into())
-    }
-}
-
-
+//every pending batch of frames goes through the processor, uses rayon for multi-threading and uses Arc<Mutex> locking to fail early if an exception is found.
+//finished batches are recorded in the checkpoint so a retry only processes what's left.
 pub fn advanced(data: Alter,
                 number_of_frames: usize,
+                pending_frames: Vec<usize>,
+                duplicates: &BTreeMap<usize, usize>,
                 fps: f64,
                 algorithm: &str,
                 batch_size: usize,
-                starting_threads: isize,
-                max_threads: isize,
-                ain: AdvancedInput) -> Result<Altered, VideoError> {
+                controller: Arc<ConcurrencyController>,
+                ain: AdvancedInput,
+                progress: Option<Arc<Progress>>,
+                checkpoint: Arc<Checkpoint>,
+                cache: Option<CacheScope>) -> Result<Altered, VideoError> {
     let mut result: Vec<Result<Vec<PathBuf>, ()>> = Vec::new();
+    //cached frames are copied into place & recorded like any other finished frame.
+    let pending_frames: Vec<usize> = match cache {
+        Some(ref cache) => {
+            let restored = cache.restore_frames(&pending_frames, &data);
+            if !restored.is_empty() {
+                checkpoint.record_outputs(&restored, data.output_regex());
+            }
+            pending_frames.into_iter().filter(|frame| restored.binary_search(frame).is_err()).collect()
+        }
+        None => pending_frames
+    };
     let search: Arc<AdvancedInput> = Arc::new(ain);
-    let frame_batches = misc::frame_batches_advanced(batch_size, number_of_frames, search.option());
+    let frame_batches = misc::frame_batches_advanced(batch_size, &pending_frames, search.option());
     let out_dir = PathBuf::from(data.local_output());
     let out_regex = data.output_regex().to_string();
-    let global_threadable = Threadable::create(starting_threads, max_threads, data);
+    let global_threadable = Threadable::create(controller.clone(), data);
     let inner_threadable = global_threadable.clone();
-    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len());
+    let wd = Watchdog::create(global_threadable.arc_term_signal(), frame_batches.len(), progress);
     let wd_t = wd.get_comms();
+    let batch_checkpoint = checkpoint.clone();
+    let batch_regex = out_regex.clone();
     io::stderr().write(b"starting parallel map.\n")?;
     frame_batches.par_iter().map(move |batch| {
 //        let thread_t = inner_threadable.clone();
-        let res = if search.option() == "batch" {
-            try_algorithm_advanced(&advanced_batch, &batch,
-                                   algorithm, &search, &inner_threadable)
-        } else {
-            try_algorithm_advanced(&advanced_single, &batch,
-                                   algorithm, &search, &inner_threadable)
-        };
+        let res = try_algorithm_advanced(&process, &batch,
+                                         algorithm, &search, &inner_threadable);
         if res.is_ok() {
+            if let Some(ref cache) = cache {
+                cache.store_frames(&batch, &inner_threadable.arc_data());
+            }
+            batch_checkpoint.record_outputs(&batch, &batch_regex);
             wd_t.send_success_signal();
         }
         res
     }).collect_into(&mut result);
     wd.terminate();
+    checkpoint.flush();
     println!("exited parallel map.");
     let signal = global_threadable.extract_term_signal();
     match signal {
         None => {
             println!("we detected no failure");
-            let processed_frames: Vec<PathBuf> = result.into_iter().collect::<Result<Vec<Vec<_>>, _>>().unwrap().concat();
+            for (frame, representative) in duplicates {
+                let processed = out_dir.join(from_regex(&out_regex, *representative)?);
+                copy(&processed, out_dir.join(from_regex(&out_regex, *frame)?))
+                    .map_err(|err| format!("failed to copy processed frame {} to its duplicate {}\n{}", representative, frame, err))?;
+            }
+            //frames from earlier attempts are already in out_dir, so the full set is every frame number.
+            let processed_frames: Vec<PathBuf> = (1..number_of_frames + 1)
+                .map(|frame| from_regex(&out_regex, frame).map(|name| out_dir.join(name)))
+                .collect::<Result<Vec<PathBuf>, VideoError>>()?;
             let out = Altered::new(PathBuf::from(out_dir), processed_frames, fps, out_regex.to_string());
             Ok(out)
         }